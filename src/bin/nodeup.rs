@@ -1,70 +1,195 @@
 use clap::load_yaml;
-use clap::App;
-use std::{env, path::Path, process};
+use clap::{App, ArgMatches, Shell};
+use serde::{Deserialize, Serialize};
+use std::{
+    env, fmt, io,
+    io::Write,
+    path::{Path, PathBuf},
+    process::{self, ExitStatus},
+    sync::atomic::{AtomicBool, Ordering},
+};
+use termcolor::{Color, ColorChoice, ColorSpec, StandardStream, WriteColor};
 
 use nodeup::{
-    local, registry,
-    verify::{self, ConfigurationCheck},
-    Target, Version,
+    import::{self, ImportMode},
+    local,
+    registry::{self, DownloadStats, Registry},
+    shell, verify, Hook, OperatingSystem, Override, OverrideScope, Target, Version,
 };
 
 type CLIResult = Result<(), Box<dyn std::error::Error>>;
 
-fn main() {
-    env_logger::init();
+// Set by --quiet. Informational and success messages are skipped, warnings and errors still print
+static QUIET: AtomicBool = AtomicBool::new(false);
+
+// Set by --format json so a failure is reported as json as well
+static JSON_ERRORS: AtomicBool = AtomicBool::new(false);
+
+// Number of versions listed by `nodeup versions list --remote` unless --all is passed
+const REMOTE_VERSIONS_LISTED: usize = 20;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum OutputFormat {
+    Human,
+    Json,
+}
+
+impl OutputFormat {
+    fn from_args(args: Option<&ArgMatches>) -> Self {
+        match args.and_then(|args| args.value_of("format")) {
+            Some("json") => OutputFormat::Json,
+            _ => OutputFormat::Human,
+        }
+    }
+}
+
+/// How a failure is reported with `--format json`. Kinds are the ones from NodeupError::kind plus
+/// config, registry, local, io, and other for errors coming from elsewhere
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct ErrorEntry {
+    error: String,
+    kind: String,
+    code: i32,
+}
+
+impl ErrorEntry {
+    fn new(error: &(dyn std::error::Error + 'static)) -> Self {
+        ErrorEntry {
+            error: error.to_string(),
+            kind: error_kind(error).to_string(),
+            code: exit_code(error),
+        }
+    }
+}
+
+/// A single entry of `--format json` output. The path is the install location for installed
+/// versions, the overridden directory for overrides, and missing for remote versions
+#[derive(Debug, PartialEq, Serialize, Deserialize)]
+struct TargetEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
+    version: Version,
+    os: OperatingSystem,
+    arch: String,
+}
+
+impl TargetEntry {
+    fn new(path: Option<PathBuf>, target: &Target) -> Self {
+        TargetEntry {
+            path,
+            version: target.version().clone(),
+            os: target.os(),
+            arch: target.arch().to_string(),
+        }
+    }
+}
 
+fn main() {
     let mut args = env::args();
     let command = args.next().expect("Command name should have been there");
     let executable = Path::new(&command)
-        .file_name()
+        .file_stem()
         .expect("Should've been able to find execuatable name");
     match executable {
         cmd if cmd == "nodeup" => {
             if let Err(e) = nodeup_command() {
-                println!("{}", e);
-                process::exit(1);
-            }
-        }
-        cmd if cmd == "node" => {
-            if let Err(e) = node_command(args) {
-                println!("{}", e);
-                process::exit(1);
+                report_failure(e.as_ref());
+                process::exit(exit_code(e.as_ref()));
             }
         }
-        cmd if cmd == "npm" => {
-            if let Err(e) = npm_command(args) {
-                println!("{}", e);
-                process::exit(1);
-            }
-        }
-        cmd if cmd == "npx" => {
-            if let Err(e) = npx_command(args) {
-                println!("{}", e);
-                process::exit(1);
+        cmd if nodeup::SHIM_EXECUTABLES.iter().any(|bin| cmd == *bin) => {
+            env_logger::init();
+            let bin = cmd.to_str().expect("Shim names are valid unicode");
+            match bin_command(bin, args) {
+                // A command killed by a signal has no code of its own
+                Ok(status) if !status.success() => process::exit(status.code().unwrap_or(1)),
+                Ok(_) => (),
+                Err(e) => {
+                    failure(&e);
+                    process::exit(exit_code(e.as_ref()));
+                }
             }
         }
         other => panic!("Unrecognized command: {:?}", other),
     }
 }
 
+fn report_failure(error: &(dyn std::error::Error + 'static)) {
+    match JSON_ERRORS.load(Ordering::Relaxed) {
+        true => {
+            let _ = write_error_json(error, &mut io::stderr());
+        }
+        false => failure(error),
+    }
+}
+
+fn write_error_json<W: Write>(
+    error: &(dyn std::error::Error + 'static),
+    out: &mut W,
+) -> io::Result<()> {
+    writeln!(out, "{}", serde_json::to_string(&ErrorEntry::new(error))?)
+}
+
+fn error_kind(error: &(dyn std::error::Error + 'static)) -> &'static str {
+    if let Some(error) = error.downcast_ref::<nodeup::NodeupError>() {
+        return error.kind();
+    }
+
+    match error {
+        error if error.is::<nodeup::ConfigError>() => "config",
+        error if error.is::<registry::RegistryError>() => "registry",
+        error if error.is::<local::LocalError>() => "local",
+        error if error.is::<io::Error>() => "io",
+        _ => "other",
+    }
+}
+
+// Errors from the library use the codes documented on NodeupError::exit_code. Registry and config
+// errors returned directly by the cli are mapped the same way
+fn exit_code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(error) = error.downcast_ref::<nodeup::NodeupError>() {
+        return error.exit_code();
+    }
+
+    match error.downcast_ref::<nodeup::ConfigError>() {
+        Some(nodeup::ConfigError::Registry(_)) => 4,
+        Some(nodeup::ConfigError::Local(_)) => 3,
+        Some(_) => 2,
+        None if error.is::<registry::RegistryError>() => 4,
+        None if error.is::<local::LocalError>() || error.is::<io::Error>() => 3,
+        None => 1,
+    }
+}
+
 fn nodeup_command() -> CLIResult {
     let yaml = load_yaml!("cli.yaml");
     let args = App::from_yaml(yaml).get_matches();
+    init_logger(flag_present(&args, "verbose"));
+    QUIET.store(flag_present(&args, "quiet"), Ordering::Relaxed);
+    JSON_ERRORS.store(
+        global_value(&args, "format") == Some("json"),
+        Ordering::Relaxed,
+    );
+    // Set through the environment so registries created within the library are offline as well
+    if flag_present(&args, "offline") {
+        env::set_var(registry::OFFLINE_VAR, "1");
+    }
     match args.subcommand() {
         ("override", args) => match args.unwrap().subcommand() {
             ("add", args) => {
                 let args = args.unwrap();
                 let version = args.value_of("version").expect("Version required");
-                let version = nodeup::Version::parse(version)?;
-                let target = Target::from_version(version);
+                let target = nodeup::resolve_version(version)?;
+                let force = args.is_present("force");
                 if args.is_present("default") {
-                    nodeup::change_default_target(target)?;
+                    nodeup::change_default_target(target, force)?;
                 } else {
-                    nodeup::override_cwd(target)?;
+                    nodeup::override_cwd(target, force)?;
                 }
             }
-            ("list", _) => {
-                print_active_versions()?;
+            ("list", args) => {
+                let paths_only = args.is_some_and(|args| args.is_present("paths-only"));
+                print_active_versions(OutputFormat::from_args(args), paths_only)?;
             }
             ("remove", args) => {
                 let args = args.unwrap();
@@ -74,141 +199,1185 @@ fn nodeup_command() -> CLIResult {
                     remove_override()?
                 }
             }
-            ("which", _) => {
-                which()?;
+            ("clear", args) => {
+                let removed = nodeup::clear_overrides(!args.unwrap().is_present("all"))?;
+                info(format!("Removed {} overrides", removed));
+            }
+            ("prune", args) => {
+                prune_overrides(args.unwrap().is_present("dry-run"))?;
+            }
+            ("which", args) => {
+                let cwd = env::current_dir()?;
+                let directory = match args.unwrap().value_of("cwd") {
+                    Some(path) => cwd.join(path),
+                    None => cwd,
+                };
+                which(
+                    &directory,
+                    args.unwrap().is_present("bin-dir"),
+                    args.unwrap().value_of("executable"),
+                    OutputFormat::from_args(args),
+                )?;
             }
             _ => println!("Run nodeup override --help to see available commands"),
         },
         ("versions", args) => match args.unwrap().subcommand() {
-            ("add", args) => {
-                let args = args.unwrap();
-                let version = args.value_of("version").expect("Version required");
-                let version = if version == "lts" {
-                    nodeup::get_latest_lts()?
-                } else {
-                    Version::parse(version)?
+            ("add", args) => add_command(&install_registry()?, args.unwrap())?,
+            ("remove", args) => {
+                let version = args.unwrap().value_of("version").expect("Version required");
+                let version: Version = match nodeup::resolve_alias(version)? {
+                    Some(aliased) => aliased,
+                    None => version.parse()?,
                 };
                 let target = Target::from_version(version);
-                println!("Installing {}...", target);
-
-                match args.value_of("path") {
-                    Some(path) => download_node_toolchain_at_path(target, Path::new(path))?,
-                    None => download_node_toolchain(target)?,
-                }
-
-                if args.is_present("default") {
-                    nodeup::change_default_target(target)?;
-                }
-
-                if args.is_present("override") {
-                    nodeup::override_cwd(target)?;
+                let removed =
+                    nodeup::remove_node(target.clone(), args.unwrap().is_present("force"))?;
+                success(format!("{} successfully removed", target.version()));
+                removed.iter().for_each(|scope| {
+                    warning(format!("Removed the override for {} which used it", scope))
+                });
+            }
+            ("reinstall", args) => {
+                let version = args.unwrap().value_of("version").expect("Version required");
+                let target = nodeup::resolve_version(version)?;
+                info(format!("Reinstalling {}...", target));
+                nodeup::reinstall(target)?;
+            }
+            ("list", args) => {
+                let format = OutputFormat::from_args(args);
+                let args = args.unwrap();
+                if args.is_present("remote") {
+                    let registry = Registry::new()?;
+                    if args.is_present("refresh") {
+                        registry.refresh_index()?;
+                    }
+                    print_remote_versions(
+                        &registry,
+                        args.is_present("lts"),
+                        args.is_present("all"),
+                        format,
+                    )?;
+                } else {
+                    let os = args
+                        .value_of("os")
+                        .map(OperatingSystem::parse)
+                        .transpose()?;
+                    print_versions(format, args.is_present("all"), os)?;
                 }
             }
-            ("remove", args) => {
+            ("prune", args) => {
+                prune(args.unwrap().is_present("dry-run"))?;
+            }
+            ("bins", args) => {
                 let version = args.unwrap().value_of("version").expect("Version required");
-                let version = nodeup::Version::parse(version)?;
-                let target = Target::from_version(version);
-                nodeup::remove_node(target)?;
-                println!("{} successfully removed", version);
+                let target = nodeup::resolve_version(version)?;
+                nodeup::version_bins(&target)?
+                    .iter()
+                    .for_each(|bin| println!("{}", bin));
             }
-            ("list", _) => {
-                print_versions()?;
+            ("path", args) => {
+                let version = args.unwrap().value_of("version").expect("Version required");
+                let target = nodeup::resolve_version(version)?;
+                println!("{}", nodeup::version_bin_dir(&target)?.display());
             }
-            ("lts", _) => {
-                let version = nodeup::get_latest_lts()?;
-                println!("{}", version)
+            ("compare", args) => {
+                let args = args.unwrap();
+                let a = Version::parse(args.value_of("a").expect("Version required"))?;
+                let b = Version::parse(args.value_of("b").expect("Version required"))?;
+                println!("{}", a.cmp(&b) as i8);
+            }
+            ("lts", args) => {
+                let args = args.unwrap();
+                let registry = Registry::new()?;
+                if args.is_present("refresh") {
+                    registry.refresh_index()?;
+                }
+                if args.is_present("all") {
+                    for (codename, version) in registry.lts_lines()? {
+                        println!("{} -> {}", codename, version);
+                    }
+                } else {
+                    let version = nodeup::latest_lts_with(&registry)?;
+                    println!("{}", version)
+                }
             }
             _ => println!("Run nodeup versions --help to see available commands"),
         },
         ("control", args) => match args.unwrap().subcommand() {
-            ("link", _) => {
-                link_command()?;
+            ("link", args) => {
+                let extra = args
+                    .and_then(|args| args.values_of("with"))
+                    .map(|values| values.collect())
+                    .unwrap_or_else(Vec::new);
+                link_command(
+                    &links_dir(args)?,
+                    extra,
+                    args.is_some_and(|args| args.is_present("force")),
+                )?;
             }
-            ("verify", _) => verify()?,
+            ("unlink", args) => unlink_command(&links_dir(args)?)?,
+            ("verify", args) => verify(
+                &links_dir(args)?,
+                args.is_some_and(|args| args.is_present("fix")),
+            )?,
             _ => println!("Run nodeup control --help to see available commands"),
         },
+        ("run", args) => {
+            let args = args.unwrap();
+            let version = args.value_of("version").expect("Version required");
+            let target = nodeup::resolve_version(version)?;
+            let mut command = args.values_of("command").expect("Command required");
+            let bin = command.next().expect("Command required");
+            let status = nodeup::exec_with(target, bin, command.map(String::from))?;
+            if !status.success() {
+                // A command killed by a signal has no code of its own
+                process::exit(status.code().unwrap_or(1));
+            }
+        }
+        ("alias", args) => match args.unwrap().subcommand() {
+            ("add", args) => {
+                let args = args.unwrap();
+                let name = args.value_of("name").expect("Name required");
+                let version = args.value_of("version").expect("Version required");
+                let version = nodeup::resolve_version(version)?.version().clone();
+                nodeup::add_alias(name, version.clone())?;
+                info(format!("{} now refers to {}", name, version));
+            }
+            ("remove", args) => {
+                let name = args.unwrap().value_of("name").expect("Name required");
+                let version = nodeup::remove_alias(name)?;
+                info(format!("Removed {} which referred to {}", name, version));
+            }
+            ("list", _) => {
+                nodeup::list_aliases()?
+                    .iter()
+                    .for_each(|(name, version)| println!("{} = {}", name, version));
+            }
+            _ => println!("Run nodeup alias --help to see available commands"),
+        },
+        ("config", args) => match args.unwrap().subcommand() {
+            ("path", _) => println!("{}", local::config_file()?.display()),
+            ("show", _) => print!("{}", toml::to_string_pretty(&nodeup::Config::fetch()?)?),
+            ("restore", _) => {
+                nodeup::restore_config()?;
+                success("Restored the config from its backup");
+            }
+            // clap rejects any other setting
+            ("set", args) => {
+                let args = args.unwrap();
+                let value = args.value_of("value").expect("Value required");
+                match args.value_of("setting") {
+                    Some("max-concurrency") => {
+                        let max_concurrency: usize = value.parse()?;
+                        if max_concurrency == 0 {
+                            return Err("max-concurrency must be at least 1".into());
+                        }
+                        nodeup::set_max_concurrency(Some(max_concurrency))?;
+                        success(format!(
+                            "Up to {} versions will be downloaded at once",
+                            max_concurrency
+                        ));
+                    }
+                    Some("preferred-lts") => {
+                        let codename = value.to_lowercase();
+                        nodeup::set_preferred_lts(Some(codename.clone()))?;
+                        success(format!(
+                            "lts will resolve to the newest {} release",
+                            codename
+                        ));
+                    }
+                    _ => {
+                        let prefix = env::current_dir()?.join(value);
+                        nodeup::set_global_prefix(Some(prefix.clone()))?;
+                        success(format!(
+                            "npm global packages will be installed into {}",
+                            prefix.display()
+                        ));
+                    }
+                }
+            }
+            ("unset", args) => match args.unwrap().value_of("setting") {
+                Some("preferred-lts") => {
+                    nodeup::set_preferred_lts(None)?;
+                    success("lts will resolve to the newest lts release");
+                }
+                Some("max-concurrency") => {
+                    nodeup::set_max_concurrency(None)?;
+                    success(format!(
+                        "Up to {} versions will be downloaded at once",
+                        registry::DEFAULT_CONCURRENCY
+                    ));
+                }
+                _ => {
+                    nodeup::set_global_prefix(None)?;
+                    success("npm global packages will be installed into each version");
+                }
+            },
+            _ => println!("Run nodeup config --help to see available commands"),
+        },
+        ("cache", args) => match args.unwrap().subcommand() {
+            ("dir", _) => println!("{}", local::archive_cache_dir()?.display()),
+            ("clean", _) => {
+                let removed = registry::clean_archive_cache()?;
+                info(format!("Removed {} cached archives", removed));
+            }
+            _ => println!("Run nodeup cache --help to see available commands"),
+        },
+        ("self", args) => match args.unwrap().subcommand() {
+            ("update", _) => self_update()?,
+            _ => println!("Run nodeup self --help to see available commands"),
+        },
+        ("current", _) => current()?,
+        ("direnv", _) => direnv()?,
+        ("env", _) => {
+            let target = nodeup::resolve_active(&env::current_dir()?)?.target;
+            let bin_dir = local::bin_dir(&target)?;
+            print!(
+                "{}",
+                shell::env_assignment(&bin_dir, OperatingSystem::default())
+            );
+        }
+        ("import", args) => match args.unwrap().subcommand() {
+            ("nvm", args) => {
+                let mode = match args.is_some_and(|args| args.is_present("copy")) {
+                    true => ImportMode::Copy,
+                    false => ImportMode::Link,
+                };
+                import_nvm(mode)?;
+            }
+            _ => println!("Run nodeup import --help to see available commands"),
+        },
+        ("shell-init", args) => {
+            let shell = args.unwrap().value_of("shell").expect("Shell required");
+            print!("{}", shell::init_script(shell.parse()?));
+        }
+        ("completions", args) => {
+            let shell = args.unwrap().value_of("shell").expect("Shell required");
+            completions(shell.parse()?, &mut io::stdout());
+        }
         _ => println!("Run nodeup --help to see available commands"),
     }
     Ok(())
 }
 
-fn node_command<I: std::iter::Iterator<Item = String>>(args: I) -> CLIResult {
-    nodeup::execute_bin("node", args).map_err(|e| e.into())
+// One registry is shared by every download so the http client and its connections are reused. The
+// environment takes precedence over the config for how many run at once. A config that can't be read
+// is reported by the parts of the install that use it
+fn install_registry() -> Result<Registry, registry::RegistryError> {
+    let registry = Registry::new()?;
+    if registry::max_concurrency_from_env().is_some() {
+        return Ok(registry);
+    }
+    match nodeup::Config::fetch().map(|config| config.max_concurrency()) {
+        Ok(Some(max_concurrency)) => Ok(registry.with_max_concurrency(max_concurrency)),
+        _ => Ok(registry),
+    }
+}
+
+fn add_command(registry: &Registry, args: &ArgMatches) -> CLIResult {
+    let versions: Vec<&str> = args
+        .values_of("version")
+        .expect("Version required")
+        .collect();
+    let single_only = ["default", "override", "from-file"];
+    if versions.len() > 1 && single_only.iter().any(|arg| args.is_present(arg)) {
+        return Err(
+            "--default, --override, and --from-file can only be used when adding one version"
+                .into(),
+        );
+    }
+
+    if args.is_present("refresh") {
+        registry.refresh_index()?;
+    }
+    let download_dir = match args.value_of("path") {
+        Some(path) => Path::new(path).to_path_buf(),
+        None => local::download_dir()?,
+    };
+    if versions.len() > 1 {
+        let jobs = match args.value_of("jobs") {
+            Some(jobs) => jobs.parse()?,
+            None => registry.max_concurrency(),
+        };
+        return add_many(
+            registry,
+            &versions,
+            &download_dir,
+            jobs,
+            args.is_present("dry-run"),
+        );
+    }
+
+    let version = versions[0];
+    let from_file = args.value_of("from-file").map(Path::new);
+    // Local archives are for machines without network access so the version can't be
+    // looked up in the registry
+    let target = match from_file {
+        Some(_) => Target::from_version(version.parse()?),
+        None => nodeup::resolve_version_with(registry, version)?,
+    };
+    if args.is_present("dry-run") {
+        return describe_install(
+            registry,
+            &target,
+            from_file,
+            &download_dir,
+            &mut io::stdout(),
+        );
+    }
+
+    nodeup::run_install_hook(Hook::PreInstall, &target)?;
+    info(format!("Installing {}...", target));
+    match from_file {
+        Some(archive) => registry::install_from_file(&download_dir, archive, &target)?,
+        None => {
+            let stats = registry.download_node_toolchain(&download_dir, target.clone())?;
+            print_download_stats(&target, stats);
+        }
+    }
+    // The install already succeeded so it's kept even if the hook fails
+    if let Err(e) = nodeup::run_install_hook(Hook::PostInstall, &target) {
+        warning(&e);
+    }
+
+    // --no-link conflicts with both of these, so an install with it never touches the config
+    // Installs to a custom path aren't in the download directory but were asked for
+    // explicitly so they aren't checked
+    if args.is_present("default") {
+        nodeup::change_default_target(target.clone(), true)?;
+    }
+
+    if args.is_present("override") {
+        nodeup::override_cwd(target, true)?;
+    }
+    Ok(())
+}
+
+// Versions are resolved and hooks run one at a time, then the downloads run concurrently. Failures
+// are collected so one bad version doesn't stop the others from installing
+fn add_many(
+    registry: &Registry,
+    versions: &[&str],
+    download_dir: &Path,
+    jobs: usize,
+    dry_run: bool,
+) -> CLIResult {
+    let mut failures: Vec<(String, Box<dyn std::error::Error>)> = Vec::new();
+    let mut targets: Vec<Target> = Vec::new();
+    for version in versions {
+        match nodeup::resolve_version_with(registry, version) {
+            Ok(target) => {
+                if !targets.contains(&target) {
+                    targets.push(target);
+                }
+            }
+            Err(e) => failures.push((version.to_string(), e.into())),
+        }
+    }
+
+    if dry_run {
+        for target in &targets {
+            describe_install(registry, target, None, download_dir, &mut io::stdout())?;
+        }
+    } else {
+        targets.retain(
+            |target| match nodeup::run_install_hook(Hook::PreInstall, target) {
+                Ok(()) => true,
+                Err(e) => {
+                    failures.push((target.to_string(), e.into()));
+                    false
+                }
+            },
+        );
+
+        let names: Vec<String> = targets.iter().map(|target| target.to_string()).collect();
+        info(format!("Installing {}...", names.join(", ")));
+        for (target, result) in registry.download_many(download_dir, targets, jobs) {
+            match result {
+                Ok(stats) => {
+                    print_download_stats(&target, stats);
+                    success(format!("Installed {}", target));
+                    if let Err(e) = nodeup::run_install_hook(Hook::PostInstall, &target) {
+                        warning(&e);
+                    }
+                }
+                Err(e) => failures.push((target.to_string(), e.into())),
+            }
+        }
+    }
+
+    if failures.is_empty() {
+        return Ok(());
+    }
+    for (version, e) in &failures {
+        failure(format!("Failed to install {}: {}", version, e));
+    }
+    Err(format!(
+        "{} of {} versions failed to install",
+        failures.len(),
+        versions.len()
+    )
+    .into())
+}
+
+// Prints what `versions add` would do without downloading anything or changing the config
+fn describe_install<W: Write>(
+    registry: &Registry,
+    target: &Target,
+    from_file: Option<&Path>,
+    download_dir: &Path,
+    out: &mut W,
+) -> CLIResult {
+    let source = match from_file {
+        Some(archive) => archive.display().to_string(),
+        None => registry.node_download_url(target),
+    };
+    writeln!(out, "Target: {}", target)?;
+    writeln!(out, "Source: {}", source)?;
+    writeln!(
+        out,
+        "Destination: {}",
+        download_dir.join(target.to_string()).display()
+    )?;
+    Ok(())
+}
+
+// Colors are only used when writing to a terminal and $NO_COLOR isn't set. See https://no-color.org
+fn color_choice() -> ColorChoice {
+    choose_color(
+        atty::is(atty::Stream::Stdout),
+        env::var_os("NO_COLOR").is_some(),
+    )
+}
+
+fn choose_color(is_terminal: bool, no_color: bool) -> ColorChoice {
+    match is_terminal && !no_color {
+        true => ColorChoice::Auto,
+        false => ColorChoice::Never,
+    }
+}
+
+// --verbose turns on debug logs without having to set RUST_LOG
+fn init_logger(verbose: bool) {
+    let mut builder = env_logger::Builder::from_default_env();
+    if verbose {
+        builder.filter_level(log::LevelFilter::Debug);
+    }
+    builder.init();
+}
+
+// Global flags are only recorded on the matches of the subcommand they were passed after
+fn flag_present(args: &ArgMatches, name: &str) -> bool {
+    args.is_present(name)
+        || args
+            .subcommand()
+            .1
+            .is_some_and(|args| flag_present(args, name))
+}
+
+// Global args with a default are set at every level, so the value given to the innermost
+// subcommand is the one that was passed
+fn global_value<'a>(args: &'a ArgMatches, name: &str) -> Option<&'a str> {
+    args.subcommand()
+        .1
+        .and_then(|args| global_value(args, name))
+        .or_else(|| args.value_of(name))
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum Status {
+    Info,
+    Success,
+    Warning,
+    Failure,
+}
+
+impl Status {
+    fn color(self) -> Option<Color> {
+        match self {
+            Status::Info => None,
+            Status::Success => Some(Color::Green),
+            Status::Warning => Some(Color::Yellow),
+            Status::Failure => Some(Color::Red),
+        }
+    }
+
+    fn shown(self, quiet: bool) -> bool {
+        !quiet || self == Status::Warning || self == Status::Failure
+    }
+}
+
+fn info<M: fmt::Display>(message: M) {
+    print_status(Status::Info, message)
+}
+
+fn success<M: fmt::Display>(message: M) {
+    print_status(Status::Success, message)
+}
+
+fn warning<M: fmt::Display>(message: M) {
+    print_status(Status::Warning, format!("Warning: {}", message))
+}
+
+fn failure<M: fmt::Display>(message: M) {
+    print_status(Status::Failure, message)
+}
+
+fn print_status<M: fmt::Display>(status: Status, message: M) {
+    let mut out = StandardStream::stdout(color_choice());
+    // Failing to write a status message isn't worth failing the command over
+    let _ = write_status(&mut out, QUIET.load(Ordering::Relaxed), status, message);
+}
+
+fn write_status<W: WriteColor, M: fmt::Display>(
+    out: &mut W,
+    quiet: bool,
+    status: Status,
+    message: M,
+) -> io::Result<()> {
+    if !status.shown(quiet) {
+        return Ok(());
+    }
+    out.set_color(ColorSpec::new().set_fg(status.color()))?;
+    write!(out, "{}", message)?;
+    out.reset()?;
+    writeln!(out)
+}
+
+fn completions<W: Write>(shell: Shell, out: &mut W) {
+    let yaml = load_yaml!("cli.yaml");
+    App::from_yaml(yaml).gen_completions_to("nodeup", shell, out);
 }
 
-fn npm_command<I: std::iter::Iterator<Item = String>>(args: I) -> CLIResult {
-    nodeup::execute_bin("npm", args).map_err(|e| e.into())
+fn bin_command<I: std::iter::Iterator<Item = String>>(
+    bin: &str,
+    args: I,
+) -> Result<ExitStatus, Box<dyn std::error::Error>> {
+    nodeup::execute_bin_spawn(bin, args).map_err(|e| e.into())
 }
 
-fn npx_command<I: std::iter::Iterator<Item = String>>(args: I) -> CLIResult {
-    nodeup::execute_bin("npx", args).map_err(|e| e.into())
+// --dir takes precedence over $NODEUP_LINKS so scripts can link into a prefix without exporting it
+fn links_dir(args: Option<&ArgMatches>) -> Result<PathBuf, Box<dyn std::error::Error>> {
+    match args.and_then(|args| args.value_of("dir")) {
+        Some(dir) => Ok(env::current_dir()?.join(dir)),
+        None => Ok(local::links()?),
+    }
 }
 
-fn link_command() -> CLIResult {
-    let links_path = local::links()?;
-    match nodeup::link_node_bins(&links_path) {
+fn link_command(links_path: &Path, extra: Vec<&str>, force: bool) -> CLIResult {
+    let mut executables = nodeup::DEFAULT_EXECUTABLES.to_vec();
+    for executable in extra {
+        if !executables.contains(&executable) {
+            executables.push(executable);
+        }
+    }
+
+    match nodeup::link_node_bins(links_path, &executables, force) {
         Ok(path) => {
-            println!("Symlinks created for node, npm, and npx. Make sure {} is in your PATH environment variable.", path.to_str().unwrap_or("[not_found]"));
+            info(format!(
+                "Symlinks created for {}. Make sure {} is in your PATH environment variable.",
+                executables.join(", "),
+                path.to_str().unwrap_or("[not_found]")
+            ));
             Ok(())
         }
         Err(e) => Err(e.into()),
     }
 }
 
-fn download_node_toolchain(target: Target) -> CLIResult {
-    let download_dir = local::download_dir()?;
-    registry::download_node_toolchain(&download_dir, target).map_err(|e| e.into())
+fn unlink_command(links_path: &Path) -> CLIResult {
+    let removed = nodeup::unlink_node_bins(links_path)?;
+    match removed.is_empty() {
+        true => info(format!(
+            "No links to nodeup found in {}",
+            links_path.display()
+        )),
+        false => removed
+            .iter()
+            .for_each(|link| info(format!("Removed {}", link.display()))),
+    }
+    Ok(())
 }
 
-fn download_node_toolchain_at_path(target: Target, download_dir: &Path) -> CLIResult {
-    registry::download_node_toolchain(&download_dir, target).map_err(|e| e.into())
+fn print_json<T: Serialize>(value: &T) -> CLIResult {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
 }
 
-fn print_versions() -> CLIResult {
+// Only versions that can run on this machine are listed unless all or another os are asked for
+fn print_versions(format: OutputFormat, all: bool, os: Option<OperatingSystem>) -> CLIResult {
     let download_dir = local::download_dir()?;
-    let targets = nodeup::installed_versions(&download_dir)?;
-    targets
-        .iter()
-        .for_each(|target| println!("{}", target.to_string()));
+    let targets = match (os, all) {
+        (Some(os), _) => nodeup::installed_versions_for_os(&download_dir, os)?,
+        (None, true) => nodeup::installed_versions(&download_dir)?,
+        (None, false) => nodeup::installed_versions_for_host(&download_dir)?,
+    };
+    match format {
+        OutputFormat::Human => targets.iter().for_each(|target| println!("{}", target)),
+        OutputFormat::Json => {
+            let entries: Vec<_> = targets
+                .iter()
+                .map(|target| TargetEntry::new(Some(download_dir.join(target.to_string())), target))
+                .collect();
+            print_json(&entries)?;
+        }
+    }
     Ok(())
 }
 
-fn print_active_versions() -> CLIResult {
-    nodeup::get_active_targets()?.for_each(|(dir, target)| {
-        println!("({}) {}", dir.display(), target);
-    });
+fn print_remote_versions(
+    registry: &Registry,
+    lts_only: bool,
+    all: bool,
+    format: OutputFormat,
+) -> CLIResult {
+    let versions = match lts_only {
+        true => registry.list_lts_versions()?,
+        false => registry.list_versions()?,
+    };
 
+    let shown = match all {
+        true => versions.len(),
+        false => REMOTE_VERSIONS_LISTED,
+    };
+    let versions = versions.into_iter().take(shown);
+    match format {
+        OutputFormat::Human => versions.for_each(|version| println!("{}", version)),
+        OutputFormat::Json => {
+            let entries: Vec<_> = versions
+                .map(|version| TargetEntry::new(None, &Target::from_version(version)))
+                .collect();
+            print_json(&entries)?;
+        }
+    }
     Ok(())
 }
 
-fn verify() -> CLIResult {
-    let path = local::links()?;
-    match verify::verify_links(&path) {
-        Ok(ConfigurationCheck::Correct) => {
-            println!("Everything looks properly configured!");
-            Ok(())
+fn prune(dry_run: bool) -> CLIResult {
+    let unused = nodeup::unused_versions()?;
+    if unused.is_empty() {
+        info("No unused versions to remove");
+        return Ok(());
+    }
+
+    let mut reclaimed = 0;
+    for target in &unused {
+        reclaimed += nodeup::installed_size(target)?;
+    }
+
+    if dry_run {
+        unused.iter().for_each(|target| println!("{}", target));
+        println!("{} would be reclaimed", display_size(reclaimed));
+        return Ok(());
+    }
+
+    let removed = nodeup::prune_unused()?;
+    removed
+        .iter()
+        .for_each(|target| info(format!("Removed {}", target)));
+    info(format!("Reclaimed {}", display_size(reclaimed)));
+    Ok(())
+}
+
+// Nothing is printed for archives that came from the archive cache
+fn print_download_stats(target: &Target, stats: DownloadStats) {
+    if stats.bytes == 0 {
+        return;
+    }
+    info(format!(
+        "Downloaded node {} ({} in {:.1}s, {}/s)",
+        target.version(),
+        display_size(stats.bytes),
+        stats.duration.as_secs_f64(),
+        display_size(stats.bytes_per_second() as u64)
+    ));
+}
+
+fn display_size(bytes: u64) -> String {
+    format!("{:.1} MB", bytes as f64 / 1_000_000.0)
+}
+
+fn print_active_versions(format: OutputFormat, paths_only: bool) -> CLIResult {
+    let active = nodeup::get_active_targets()?;
+    match format {
+        OutputFormat::Human => write_overrides(&active, paths_only, &mut io::stdout())?,
+        OutputFormat::Json => {
+            let entries: Vec<_> = active
+                .iter()
+                .map(|entry| {
+                    let path = PathBuf::from(entry.scope.to_string());
+                    TargetEntry::new(Some(path), &entry.target)
+                })
+                .collect();
+            print_json(&entries)?;
         }
-        Ok(ConfigurationCheck::Incorrect(i)) => {
-            println!("{}", i);
-            process::exit(1);
+    }
+
+    Ok(())
+}
+
+// The default is marked with a * and the targets are lined up in a column. With paths_only only the
+// directories are printed, one per line
+fn write_overrides<W: Write>(
+    overrides: &[Override],
+    paths_only: bool,
+    out: &mut W,
+) -> io::Result<()> {
+    if paths_only {
+        for entry in overrides {
+            if let OverrideScope::Directory(dir) = &entry.scope {
+                writeln!(out, "{}", dir.display())?;
+            }
         }
-        Err(e) => Err(e.into()),
+        return Ok(());
+    }
+
+    let scopes: Vec<String> = overrides
+        .iter()
+        .map(|entry| entry.scope.to_string())
+        .collect();
+    let width = scopes
+        .iter()
+        .map(|scope| scope.chars().count())
+        .max()
+        .unwrap_or(0);
+    for (entry, scope) in overrides.iter().zip(scopes) {
+        let marker = match entry.scope {
+            OverrideScope::Default => '*',
+            OverrideScope::Directory(_) => ' ',
+        };
+        writeln!(
+            out,
+            "{} {:<width$}  {}",
+            marker,
+            scope,
+            entry.target,
+            width = width
+        )?;
+    }
+    Ok(())
+}
+
+fn verify(links: &Path, fix: bool) -> CLIResult {
+    let download_dir = local::download_dir()?;
+    let mut problems = verify::doctor(links, &download_dir)?;
+    if fix && verify::fix_links(links, &problems)? {
+        info(format!("Relinked the executables in {}", links.display()));
+        problems = verify::doctor(links, &download_dir)?;
+    }
+    if problems.is_empty() {
+        success("Everything looks properly configured!");
+        return Ok(());
+    }
+
+    problems.iter().for_each(failure);
+    process::exit(1);
+}
+
+fn import_nvm(mode: ImportMode) -> CLIResult {
+    let nvm_dir =
+        import::nvm_dir().ok_or("Can't find nvm's directory. Set $NVM_DIR to its location")?;
+    let imported = import::import_nvm(&nvm_dir, &local::download_dir()?, mode)?;
+    imported
+        .skipped
+        .iter()
+        .for_each(|target| info(format!("{} is already installed", target)));
+    imported
+        .imported
+        .iter()
+        .for_each(|target| success(format!("Imported {}", target)));
+    if imported.imported.is_empty() && imported.skipped.is_empty() {
+        info(format!("No versions found in {}", nvm_dir.display()));
     }
+    Ok(())
 }
 
 fn remove_override() -> CLIResult {
     nodeup::remove_override().map_err(|e| e.into())
 }
 
+fn prune_overrides(dry_run: bool) -> CLIResult {
+    let stale = nodeup::prune_overrides(dry_run)?;
+    if stale.is_empty() {
+        info("No overrides for missing directories to remove");
+        return Ok(());
+    }
+
+    match dry_run {
+        true => stale.iter().for_each(|dir| println!("{}", dir.display())),
+        false => stale
+            .iter()
+            .for_each(|dir| info(format!("Removed override for {}", dir.display()))),
+    }
+    Ok(())
+}
+
 fn remove_default_override() -> CLIResult {
     nodeup::remove_default_override().map_err(|e| e.into())
 }
 
-fn which() -> CLIResult {
+fn which(
+    directory: &Path,
+    bin_dir: bool,
+    executable: Option<&str>,
+    format: OutputFormat,
+) -> CLIResult {
+    let resolution = nodeup::resolve_active(directory)?;
+    let active_target = resolution.target;
+
+    if let Some(executable) = executable {
+        let path = nodeup::bin_path(&active_target, executable)?;
+        match format {
+            OutputFormat::Human => println!("{}", path.display()),
+            OutputFormat::Json => print_json(&TargetEntry::new(Some(path), &active_target))?,
+        }
+        return Ok(());
+    }
+
+    match (format, bin_dir) {
+        (OutputFormat::Human, true) => {
+            println!("{}", local::bin_dir(&active_target)?.display())
+        }
+        (OutputFormat::Human, false) => {
+            println!("{} (from {})", active_target, resolution.source)
+        }
+        (OutputFormat::Json, true) => {
+            let path = local::bin_dir(&active_target)?;
+            print_json(&TargetEntry::new(Some(path), &active_target))?;
+        }
+        (OutputFormat::Json, false) => {
+            let path = local::download_dir()?.join(active_target.to_string());
+            print_json(&TargetEntry::new(Some(path), &active_target))?;
+        }
+    }
+
+    Ok(())
+}
+
+// Only the version is printed so scripts can use it as is. Nothing is printed when no version is
+// set for the directory, only the exit code tells
+fn current() -> CLIResult {
     let cwd = env::current_dir()?;
-    let active_target = nodeup::which(&cwd)?;
+    match nodeup::resolve_active(&cwd) {
+        Ok(resolution) => write_current(&resolution.target, &mut io::stdout())?,
+        Err(e @ nodeup::NodeupError::NoVersionFound) => process::exit(e.exit_code()),
+        Err(e) => return Err(e.into()),
+    }
+    Ok(())
+}
+
+fn write_current<W: Write>(target: &Target, out: &mut W) -> io::Result<()> {
+    writeln!(out, "{}", target.version())
+}
+
+#[cfg(feature = "self-update")]
+fn self_update() -> CLIResult {
+    use nodeup::self_update::{self, Updated};
+
+    info("Checking for a newer release of nodeup...");
+    match self_update::update()? {
+        Updated::AlreadyLatest(version) => {
+            success(format!("nodeup {} is the latest release", version))
+        }
+        Updated::Updated { from, to } => success(format!("Updated nodeup from {} to {}", from, to)),
+    }
+    Ok(())
+}
+
+#[cfg(not(feature = "self-update"))]
+fn self_update() -> CLIResult {
+    Err("This nodeup was built without the self-update feature. Update it the way it was installed, ex/ with cargo install".into())
+}
 
-    println!("{}", active_target);
+// An .envrc shouldn't fail to load because no version is set, so nothing is printed instead
+fn direnv() -> CLIResult {
+    let cwd = env::current_dir()?;
+    let target = match nodeup::resolve_active(&cwd) {
+        Ok(resolution) => Some(resolution.target),
+        Err(nodeup::NodeupError::NoVersionFound) => None,
+        Err(e) => return Err(e.into()),
+    };
+    write_direnv(target.as_ref(), &mut io::stdout())
+}
 
+fn write_direnv<W: Write>(target: Option<&Target>, out: &mut W) -> CLIResult {
+    if let Some(target) = target {
+        write!(out, "{}", shell::direnv_exports(&local::bin_dir(target)?))?;
+    }
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn target_entries_round_trip() {
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        let entries = vec![
+            TargetEntry::new(Some(PathBuf::from("/projects/app")), &target),
+            TargetEntry::new(None, &target),
+        ];
+
+        let json = serde_json::to_string(&entries).unwrap();
+        let parsed: Vec<TargetEntry> = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed, entries);
+        assert_eq!(parsed[0].version, *target.version());
+        assert_eq!(parsed[0].os, OperatingSystem::Linux);
+        assert_eq!(parsed[0].arch, "x64");
+        assert_eq!(parsed[1].path, None);
+    }
+
+    #[test]
+    fn output_format_from_args() {
+        let yaml = load_yaml!("cli.yaml");
+        let matches = App::from_yaml(yaml)
+            .get_matches_from(vec!["nodeup", "versions", "list", "--format", "json"]);
+        let (_, versions) = matches.subcommand();
+        let (_, list) = versions.unwrap().subcommand();
+        assert_eq!(OutputFormat::from_args(list), OutputFormat::Json);
+
+        let matches = App::from_yaml(yaml).get_matches_from(vec!["nodeup", "override", "list"]);
+        let (_, overrides) = matches.subcommand();
+        let (_, list) = overrides.unwrap().subcommand();
+        assert_eq!(OutputFormat::from_args(list), OutputFormat::Human);
+    }
+
+    #[test]
+    fn which_executable_is_optional() {
+        let yaml = load_yaml!("cli.yaml");
+        let matches =
+            App::from_yaml(yaml).get_matches_from(vec!["nodeup", "override", "which", "npm"]);
+        let (_, overrides) = matches.subcommand();
+        let (_, which) = overrides.unwrap().subcommand();
+        assert_eq!(which.unwrap().value_of("executable"), Some("npm"));
+
+        let matches = App::from_yaml(yaml).get_matches_from(vec!["nodeup", "override", "which"]);
+        let (_, overrides) = matches.subcommand();
+        let (_, which) = overrides.unwrap().subcommand();
+        assert_eq!(which.unwrap().value_of("executable"), None);
+    }
+
+    #[test]
+    fn which_for_another_directory() {
+        let yaml = load_yaml!("cli.yaml");
+        let matches = App::from_yaml(yaml).get_matches_from(vec![
+            "nodeup",
+            "override",
+            "which",
+            "--cwd",
+            "/projects/api",
+        ]);
+        let (_, overrides) = matches.subcommand();
+        let (_, which) = overrides.unwrap().subcommand();
+        assert_eq!(which.unwrap().value_of("cwd"), Some("/projects/api"));
+    }
+
+    #[test]
+    fn explicit_links_dir() {
+        let yaml = load_yaml!("cli.yaml");
+        for command in &["link", "unlink", "verify"] {
+            let matches = App::from_yaml(yaml).get_matches_from(vec![
+                "nodeup",
+                "control",
+                command,
+                "--dir",
+                "/opt/links",
+            ]);
+            let (_, control) = matches.subcommand();
+            let (_, args) = control.unwrap().subcommand();
+            assert_eq!(links_dir(args).unwrap(), PathBuf::from("/opt/links"));
+        }
+    }
+
+    #[test]
+    fn json_error() {
+        let error: Box<dyn std::error::Error> = Box::new(nodeup::NodeupError::NoVersionFound);
+        let mut out = Vec::new();
+        write_error_json(error.as_ref(), &mut out).unwrap();
+
+        let entry: ErrorEntry = serde_json::from_slice(&out).unwrap();
+        assert_eq!(
+            entry,
+            ErrorEntry {
+                error: error.to_string(),
+                kind: String::from("no_version_found"),
+                code: 5,
+            }
+        );
+    }
+
+    #[test]
+    fn format_after_subcommand() {
+        let yaml = load_yaml!("cli.yaml");
+        let matches = App::from_yaml(yaml)
+            .get_matches_from(vec!["nodeup", "override", "which", "--format", "json"]);
+        assert_eq!(global_value(&matches, "format"), Some("json"));
+
+        let matches = App::from_yaml(yaml).get_matches_from(vec!["nodeup", "override", "which"]);
+        assert_eq!(global_value(&matches, "format"), Some("human"));
+    }
+
+    #[test]
+    fn default_override_is_marked() {
+        let target = |name: &str| name.parse::<Target>().unwrap();
+        let overrides = vec![
+            Override {
+                scope: OverrideScope::Default,
+                target: target("node-v20.5.0-linux-x64"),
+            },
+            Override {
+                scope: OverrideScope::Directory(PathBuf::from("/projects/api")),
+                target: target("node-v16.20.1-linux-x64"),
+            },
+        ];
+
+        let mut out = Vec::new();
+        write_overrides(&overrides, false, &mut out).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "* default        node-v20.5.0-linux-x64\n  /projects/api  node-v16.20.1-linux-x64\n"
+        );
+
+        let mut out = Vec::new();
+        write_overrides(&overrides, true, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "/projects/api\n");
+    }
+
+    #[test]
+    fn direnv_puts_active_bin_dir_on_path() {
+        let target: Target = "node-v18.17.0-linux-x64".parse().unwrap();
+        let mut out = Vec::new();
+        write_direnv(Some(&target), &mut out).unwrap();
+        let exports = String::from_utf8(out).unwrap();
+        let bin_dir = local::bin_dir(&target).unwrap();
+        assert!(exports.starts_with("export PATH="));
+        assert!(exports.contains(&*bin_dir.to_string_lossy()));
+
+        let mut out = Vec::new();
+        write_direnv(None, &mut out).unwrap();
+        assert!(out.is_empty());
+    }
+
+    #[test]
+    fn no_link_conflicts_with_default() {
+        let yaml = load_yaml!("cli.yaml");
+        let parse = |args: Vec<&str>| App::from_yaml(yaml).get_matches_from_safe(args);
+        assert!(parse(vec!["nodeup", "versions", "add", "18", "--no-link"]).is_ok());
+        assert!(parse(vec!["nodeup", "versions", "add", "18", "--no-default"]).is_ok());
+        assert!(parse(vec![
+            "nodeup",
+            "versions",
+            "add",
+            "18",
+            "--no-link",
+            "--default"
+        ])
+        .is_err());
+        assert!(parse(vec!["nodeup", "versions", "add", "18", "--no-link", "-o"]).is_err());
+    }
+
+    #[test]
+    fn dry_run_doesnt_install() {
+        let download_dir = tempfile::tempdir().unwrap();
+        let registry = Registry::new().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        let mut out = Vec::new();
+        describe_install(&registry, &target, None, download_dir.path(), &mut out).unwrap();
+
+        let out = String::from_utf8(out).unwrap();
+        assert!(out.contains("Target: node-v16.1.0-linux-x64\n"));
+        assert!(
+            out.contains("Source: https://nodejs.org/dist/v16.1.0/node-v16.1.0-linux-x64.tar.gz\n")
+        );
+        let destination = download_dir.path().join("node-v16.1.0-linux-x64");
+        assert!(out.contains(&format!("Destination: {}\n", destination.display())));
+        assert_eq!(std::fs::read_dir(download_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn colors_only_for_terminals() {
+        assert_eq!(choose_color(true, false), ColorChoice::Auto);
+        assert_eq!(choose_color(true, true), ColorChoice::Never);
+        assert_eq!(choose_color(false, false), ColorChoice::Never);
+    }
+
+    #[test]
+    fn piped_status_has_no_escapes() {
+        let mut piped = termcolor::NoColor::new(Vec::new());
+        write_status(
+            &mut piped,
+            false,
+            Status::Success,
+            "Installed node-v16.1.0-linux-x64",
+        )
+        .unwrap();
+        let piped = String::from_utf8(piped.into_inner()).unwrap();
+        assert_eq!(piped, "Installed node-v16.1.0-linux-x64\n");
+
+        let mut terminal = termcolor::Ansi::new(Vec::new());
+        write_status(
+            &mut terminal,
+            false,
+            Status::Success,
+            "Installed node-v16.1.0-linux-x64",
+        )
+        .unwrap();
+        let terminal = String::from_utf8(terminal.into_inner()).unwrap();
+        assert!(terminal.contains('\u{1b}'));
+    }
+
+    #[test]
+    fn quiet_only_prints_problems() {
+        let mut out = termcolor::NoColor::new(Vec::new());
+        write_status(
+            &mut out,
+            true,
+            Status::Info,
+            "Installing node-v16.1.0-linux-x64...",
+        )
+        .unwrap();
+        write_status(
+            &mut out,
+            true,
+            Status::Success,
+            "Installed node-v16.1.0-linux-x64",
+        )
+        .unwrap();
+        write_status(&mut out, true, Status::Warning, "Warning: hook failed").unwrap();
+        write_status(&mut out, true, Status::Failure, "Version not installed").unwrap();
+        let out = String::from_utf8(out.into_inner()).unwrap();
+        assert_eq!(out, "Warning: hook failed\nVersion not installed\n");
+    }
+
+    #[test]
+    fn global_flags_after_subcommands() {
+        let yaml = load_yaml!("cli.yaml");
+        let matches =
+            App::from_yaml(yaml).get_matches_from(vec!["nodeup", "versions", "list", "--quiet"]);
+        assert!(flag_present(&matches, "quiet"));
+        assert!(!flag_present(&matches, "verbose"));
+
+        let matches =
+            App::from_yaml(yaml).get_matches_from(vec!["nodeup", "-v", "versions", "list"]);
+        assert!(flag_present(&matches, "verbose"));
+    }
+
+    #[test]
+    fn current_prints_only_the_version() {
+        let target: Target = "node-v18.16.0-linux-x64".parse().unwrap();
+        let mut out = Vec::new();
+        write_current(&target, &mut out).unwrap();
+        assert_eq!(String::from_utf8(out).unwrap(), "v18.16.0\n");
+    }
+
+    #[test]
+    fn generate_completions() {
+        for shell in Shell::variants().iter() {
+            let mut out = Vec::new();
+            completions(shell.parse().unwrap(), &mut out);
+            assert!(!out.is_empty(), "No completions generated for {}", shell);
+        }
+    }
+}
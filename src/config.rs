@@ -1,27 +1,40 @@
-use log::error;
+use fs2::FileExt;
+use log::{debug, error, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     collections::HashMap,
-    fs,
+    env, fmt, fs,
     fs::OpenOptions,
     io,
-    io::Read,
+    io::{ErrorKind, Read},
     path::{Path, PathBuf},
 };
+use tempfile::NamedTempFile;
 use thiserror::Error;
 
 use crate::{
     local::{self, LocalError},
-    target::{Target, Version, VersionError},
+    registry::{self, RegistryError},
+    target::{Target, Version, VersionError, VersionRange, VersionReq},
 };
 
 pub type ConfigResult<T> = Result<T, ConfigError>;
 
+/// Environment variable that pins the active version regardless of overrides or version files
+pub const NODE_VERSION_VAR: &str = "NODEUP_NODE_VERSION";
+
+/// Layout of the config written by this version of nodeup. Bumped whenever the layout changes so
+/// older files can be migrated when they're read
+pub const SCHEMA_VERSION: u32 = 2;
+
 #[derive(Debug, Error)]
 pub enum ConfigError {
     #[error(transparent)]
     Local(#[from] LocalError),
 
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+
     #[error("An IO error occured while trying to access {path:?}: {source}")]
     IO { source: io::Error, path: PathBuf },
 
@@ -31,22 +44,168 @@ pub enum ConfigError {
         path: PathBuf,
     },
 
-    #[error("Error parsing the .nvmrc file at {path:?}\n{source}")]
+    #[error("Error parsing the version file at {path:?}\n{source}")]
     ParseError { path: PathBuf, source: VersionError },
+
+    #[error("$NODEUP_NODE_VERSION is set to {value:?} which isn't a version or alias: {source}")]
+    InvalidEnvVersion { value: String, source: VersionError },
+
+    #[error("There is no backup of the config at {path:?} to restore")]
+    NoBackup { path: PathBuf },
+
+    #[error("The version file at {path:?} asks for {content} but no installed version matches. Install one with `nodeup versions add {content}`")]
+    NoInstalledMatch { path: PathBuf, content: String },
+
+    #[error("The config at {path:?} is schema version {version} which is newer than this nodeup supports ({}). Update nodeup to keep using it", SCHEMA_VERSION)]
+    NewerSchema { path: PathBuf, version: u32 },
 }
 
 #[derive(Debug, Deserialize, Serialize)]
 pub struct Config {
+    /// Files written before the field existed are version 0
+    #[serde(default)]
+    schema_version: u32,
+
+    /// Command run before `nodeup versions add` installs a version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pre_install: Option<String>,
+
+    /// Command run after `nodeup versions add` installs a version
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    post_install: Option<String>,
+
+    /// Prefix npm installs global packages into, shared by every version so they survive switching
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    global_prefix: Option<PathBuf>,
+
+    /// The most downloads run at once when installing several versions. $NODEUP_MAX_CONCURRENCY
+    /// takes precedence
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    max_concurrency: Option<usize>,
+
+    /// Codename of the lts line `lts` resolves to instead of the newest one, ex/ gallium
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    preferred_lts: Option<String>,
+
     #[serde(default)]
     version_mappings: HashMap<PathBuf, Target>,
+
+    #[serde(default)]
+    aliases: HashMap<String, Version>,
+
+    /// File the config was loaded from and updates are written to. Unset for configs that weren't
+    /// loaded from a file, which are written to the default location
+    #[serde(skip)]
+    path: Option<PathBuf>,
+}
+
+/// A version set with `nodeup override add`
+#[derive(Debug, Clone, PartialEq)]
+pub struct Override {
+    pub scope: OverrideScope,
+    pub target: Target,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum OverrideScope {
+    /// Used wherever no other version is set
+    Default,
+    Directory(PathBuf),
+}
+
+impl fmt::Display for OverrideScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            OverrideScope::Default => write!(f, "default"),
+            OverrideScope::Directory(dir) => write!(f, "{}", dir.display()),
+        }
+    }
+}
+
+/// The active target along with where it was set
+#[derive(Debug, Clone, PartialEq)]
+pub struct Resolution {
+    pub target: Target,
+    pub source: ResolutionSource,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum ResolutionSource {
+    /// The $NODEUP_NODE_VERSION environment variable
+    Environment,
+    /// An override set with `nodeup override add` for the directory
+    Override(PathBuf),
+    Nvmrc(PathBuf),
+    NodeVersion(PathBuf),
+    ToolVersions(PathBuf),
+    PackageJson(PathBuf),
+    Default,
+}
+
+impl fmt::Display for ResolutionSource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use ResolutionSource::*;
+        match self {
+            Environment => write!(f, "${}", NODE_VERSION_VAR),
+            Override(dir) => write!(f, "override for {}", dir.display()),
+            Nvmrc(path) | NodeVersion(path) | ToolVersions(path) | PackageJson(path) => {
+                let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+                let dir = path.parent().unwrap_or(path);
+                write!(f, "{} at {}", file_name, dir.display())
+            }
+            Default => write!(f, "default"),
+        }
+    }
+}
+
+/// Exclusive lock on the config lock file, released when dropped
+struct ConfigLock {
+    file: fs::File,
+}
+
+impl ConfigLock {
+    fn acquire(lock_file: PathBuf) -> ConfigResult<Self> {
+        if let Some(config_dir) = lock_file.parent() {
+            fs::create_dir_all(config_dir).map_err(|source| ConfigError::IO {
+                source,
+                path: config_dir.to_path_buf(),
+            })?;
+        }
+
+        let file = OpenOptions::new()
+            .write(true)
+            .create(true)
+            .truncate(false)
+            .open(&lock_file)
+            .map_err(|source| ConfigError::IO {
+                source,
+                path: lock_file.clone(),
+            })?;
+        file.lock_exclusive().map_err(|source| ConfigError::IO {
+            source,
+            path: lock_file,
+        })?;
+
+        Ok(ConfigLock { file })
+    }
+}
+
+impl Drop for ConfigLock {
+    fn drop(&mut self) {
+        let _ = FileExt::unlock(&self.file);
+    }
 }
 
 pub type VersionIterator = std::collections::hash_map::IntoIter<std::path::PathBuf, Target>;
 
 impl Config {
     pub fn fetch() -> ConfigResult<Self> {
-        let config_file = local::config_file()?;
+        Config::load_from(&local::config_file()?)
+    }
 
+    /// Reads the config at `config_file`, creating an empty one if it doesn't exist. Updates are
+    /// written back to the same file
+    pub fn load_from(config_file: &Path) -> ConfigResult<Self> {
         if let Some(config_dir) = config_file.parent() {
             fs::create_dir_all(config_dir).map_err(|source| ConfigError::IO {
                 source,
@@ -57,40 +216,92 @@ impl Config {
             .read(true)
             .write(true)
             .create(true)
-            .open(&config_file)
+            .truncate(false)
+            .open(config_file)
             .map_err(|source| ConfigError::IO {
                 source,
-                path: config_file.clone(),
+                path: config_file.to_path_buf(),
             })?;
 
         let mut content = Vec::new();
         file.read_to_end(&mut content)
             .map_err(|source| ConfigError::IO {
                 source,
-                path: config_file.clone(),
+                path: config_file.to_path_buf(),
             })?;
 
-        let config: Config =
+        let mut config: Config =
             toml::from_slice(&content[..]).map_err(|source| ConfigError::Corruption {
                 source,
-                path: config_file,
+                path: config_file.to_path_buf(),
             })?;
+        config.path = Some(config_file.to_path_buf());
+        config.migrate()?;
 
         Ok(config)
     }
 
-    pub fn update(&self) -> ConfigResult<()> {
+    /// Upgrades a config written by an older version of nodeup to the current layout. The result is
+    /// written out with the next update. Configs from a newer nodeup are refused rather than
+    /// rewritten, since fields this version doesn't know about would be dropped
+    pub fn migrate(&mut self) -> ConfigResult<()> {
+        if self.schema_version > SCHEMA_VERSION {
+            return Err(ConfigError::NewerSchema {
+                path: self.file()?,
+                version: self.schema_version,
+            });
+        }
+        // Version 0 only lacked the field itself. Up to version 1 override directories were kept as
+        // they were given, so they're canonicalized to match the keys written since
+        if self.schema_version < 2 {
+            self.version_mappings = self
+                .version_mappings
+                .drain()
+                .map(|(dir, target)| (override_key(dir), target))
+                .collect();
+        }
+        self.schema_version = SCHEMA_VERSION;
+        Ok(())
+    }
+
+    pub fn schema_version(&self) -> u32 {
+        self.schema_version
+    }
+
+    fn file(&self) -> ConfigResult<PathBuf> {
+        match &self.path {
+            Some(path) => Ok(path.clone()),
+            None => Ok(local::config_file()?),
+        }
+    }
+
+    // Only called through modify, which holds the lock for the config file
+    fn update(&self) -> ConfigResult<()> {
         let updated_contents = toml::to_vec(&self)
             .expect("Failed to serialize updated config file. This shouldn't fail");
 
-        let updated_config_file = local::transitory_config_file()?;
+        let config_file = self.file()?;
+        let config_dir = config_file.parent().unwrap_or_else(|| Path::new("."));
+        let updated_config_file =
+            NamedTempFile::new_in(config_dir).map_err(|source| ConfigError::IO {
+                source,
+                path: config_dir.to_path_buf(),
+            })?;
 
         fs::write(&updated_config_file, updated_contents).map_err(|source| ConfigError::IO {
             source,
             path: updated_config_file.path().to_path_buf(),
         })?;
 
-        let config_file = local::config_file()?;
+        // One generation of backup is kept so a bad update can be undone with `nodeup config restore`
+        if config_file.exists() {
+            let backup_file = ConfigFiles::next_to(&config_file).backup;
+            fs::copy(&config_file, &backup_file).map_err(|source| ConfigError::IO {
+                source,
+                path: backup_file,
+            })?;
+        }
+
         fs::rename(&updated_config_file, &config_file).map_err(|source| ConfigError::IO {
             source,
             path: updated_config_file.path().to_path_buf(),
@@ -99,83 +310,1012 @@ impl Config {
         Ok(())
     }
 
+    /// Swaps the backup made by the last update with the current config. Restoring twice undoes the
+    /// restore.
+    pub fn restore_backup() -> ConfigResult<()> {
+        Config::restore_backup_of(&local::config_file()?)
+    }
+
+    fn restore_backup_of(config_file: &Path) -> ConfigResult<()> {
+        let ConfigFiles {
+            lock: lock_file,
+            backup: backup_file,
+        } = ConfigFiles::next_to(config_file);
+        let _lock = ConfigLock::acquire(lock_file)?;
+
+        let backup = match fs::read(&backup_file) {
+            Ok(backup) => backup,
+            Err(e) if e.kind() == ErrorKind::NotFound => {
+                return Err(ConfigError::NoBackup { path: backup_file })
+            }
+            Err(source) => {
+                return Err(ConfigError::IO {
+                    source,
+                    path: backup_file,
+                })
+            }
+        };
+        toml::from_slice::<Config>(&backup).map_err(|source| ConfigError::Corruption {
+            source,
+            path: backup_file.clone(),
+        })?;
+
+        let current = match fs::read(config_file) {
+            Ok(current) => Some(current),
+            Err(e) if e.kind() == ErrorKind::NotFound => None,
+            Err(source) => {
+                return Err(ConfigError::IO {
+                    source,
+                    path: config_file.to_path_buf(),
+                })
+            }
+        };
+
+        let config_dir = config_file.parent().unwrap_or_else(|| Path::new("."));
+        let restored_config_file =
+            NamedTempFile::new_in(config_dir).map_err(|source| ConfigError::IO {
+                source,
+                path: config_dir.to_path_buf(),
+            })?;
+        fs::write(&restored_config_file, &backup).map_err(|source| ConfigError::IO {
+            source,
+            path: restored_config_file.path().to_path_buf(),
+        })?;
+        fs::rename(&restored_config_file, config_file).map_err(|source| ConfigError::IO {
+            source,
+            path: restored_config_file.path().to_path_buf(),
+        })?;
+
+        match current {
+            Some(current) => fs::write(&backup_file, current),
+            None => fs::remove_file(&backup_file),
+        }
+        .map_err(|source| ConfigError::IO {
+            source,
+            path: backup_file,
+        })
+    }
+
     pub fn active_versions(self) -> VersionIterator {
         self.version_mappings.into_iter()
     }
 
+    /// Every override along with the default, which is keyed by the path "default"
+    pub fn overrides(&self) -> impl Iterator<Item = (&PathBuf, &Target)> {
+        self.version_mappings.iter()
+    }
+
+    /// The default followed by the directory overrides sorted by directory
+    pub fn override_list(&self) -> Vec<Override> {
+        let mut overrides: Vec<Override> = self
+            .version_mappings
+            .iter()
+            .map(|(dir, target)| Override {
+                scope: match dir == Path::new("default") {
+                    true => OverrideScope::Default,
+                    false => OverrideScope::Directory(dir.clone()),
+                },
+                target: target.clone(),
+            })
+            .collect();
+        overrides.sort_by(|a, b| a.scope.cmp(&b.scope));
+        overrides
+    }
+
+    /// Whether the target is used by any override or the default
+    pub fn references(&self, target: &Target) -> bool {
+        self.version_mappings
+            .values()
+            .any(|mapped| mapped == target)
+    }
+
+    /// Where the target is set as the default or an override, the default first
+    pub fn overrides_using(&self, target: &Target) -> Vec<OverrideScope> {
+        self.override_list()
+            .into_iter()
+            .filter(|entry| entry.target == *target)
+            .map(|entry| entry.scope)
+            .collect()
+    }
+
+    /// Removes the default and every override set to the target, returning where they were set
+    pub fn remove_overrides_using(&mut self, target: &Target) -> ConfigResult<Vec<OverrideScope>> {
+        let mut removed = Vec::new();
+        self.modify(|config| {
+            removed = config.overrides_using(target);
+            config.version_mappings.retain(|_, mapped| mapped != target);
+        })?;
+        Ok(removed)
+    }
+
     pub fn get_active_target(&self, from_dir: &Path) -> ConfigResult<Option<Target>> {
-        let mut current_dir = from_dir;
+        self.resolve_active(from_dir)
+            .map(|resolution| resolution.map(|resolution| resolution.target))
+    }
+
+    /// Like `get_active_target`, but also says where the target was set
+    ///
+    /// $NODEUP_NODE_VERSION takes precedence over everything else when it's set
+    pub fn resolve_active(&self, from_dir: &Path) -> ConfigResult<Option<Resolution>> {
+        if let Some(value) = env::var_os(NODE_VERSION_VAR) {
+            let target = self.env_target(&value.to_string_lossy())?;
+            debug!("Using {} from ${}", target, NODE_VERSION_VAR);
+            return Ok(Some(Resolution {
+                target,
+                source: ResolutionSource::Environment,
+            }));
+        }
+
+        let from_dir = canonical_dir(from_dir);
+        let mut current_dir = from_dir.as_path();
         loop {
-            if let Some(target) = self.override_at_path(current_dir)? {
-                return Ok(Some(target));
+            debug!("Looking for a version in {}", current_dir.display());
+            if let Some(resolution) = self.override_at_path(current_dir)? {
+                debug!("Using {} from {}", resolution.target, resolution.source);
+                return Ok(Some(resolution));
             };
 
             match current_dir.parent() {
                 Some(next_dir) => current_dir = next_dir,
                 None => {
-                    return Ok(self
-                        .version_mappings
-                        .get(&PathBuf::from("default"))
-                        .copied())
+                    let default = self.version_mappings.get(&PathBuf::from("default"));
+                    match default {
+                        Some(target) => debug!("Using the default {}", target),
+                        None => debug!("No version is set for {}", from_dir.display()),
+                    }
+                    return Ok(default.map(|target| Resolution {
+                        target: target.clone(),
+                        source: ResolutionSource::Default,
+                    }));
                 }
             }
         }
     }
 
+    fn env_target(&self, value: &str) -> ConfigResult<Target> {
+        let value = value.trim();
+        let version = match self.alias(value) {
+            Some(version) => version.clone(),
+            None => Version::parse(value).map_err(|source| ConfigError::InvalidEnvVersion {
+                value: value.to_string(),
+                source,
+            })?,
+        };
+        Ok(Target::from_version(version))
+    }
+
+    /// Directories are stored canonicalized so they match however the directory is reached later,
+    /// ex/ through a symlink
     pub fn set_override(&mut self, target: Target, dir: PathBuf) -> ConfigResult<()> {
-        self.version_mappings.insert(dir, target);
-        self.update()
+        let dir = override_key(dir);
+        self.modify(|config| {
+            config.version_mappings.insert(dir, target);
+        })
     }
 
     pub fn remove_override(&mut self, dir: PathBuf) -> ConfigResult<()> {
-        self.version_mappings.remove(&dir);
-        self.update()
+        // Overrides added before paths were canonicalized are stored as they were given
+        let key = override_key(dir.clone());
+        self.modify(|config| {
+            config.version_mappings.remove(&dir);
+            config.version_mappings.remove(&key);
+        })
+    }
+
+    /// Overrides for directories that no longer exist, sorted by directory. The default is never
+    /// included. A directory that can't be checked, ex/ behind a parent without permissions, is
+    /// kept since it may still be there
+    pub fn stale_overrides(&self) -> Vec<PathBuf> {
+        let mut stale: Vec<PathBuf> = self
+            .version_mappings
+            .keys()
+            .filter(|dir| *dir != Path::new("default"))
+            .filter(|dir| matches!(fs::metadata(dir), Err(e) if e.kind() == ErrorKind::NotFound))
+            .cloned()
+            .collect();
+        stale.sort();
+        stale
+    }
+
+    /// Removes the overrides returned by `stale_overrides`, returning the directories removed
+    pub fn prune_overrides(&mut self) -> ConfigResult<Vec<PathBuf>> {
+        let mut removed = Vec::new();
+        self.modify(|config| {
+            removed = config.stale_overrides();
+            for dir in &removed {
+                config.version_mappings.remove(dir);
+            }
+        })?;
+        Ok(removed)
+    }
+
+    /// Removes every override, along with the default unless `keep_default` is set. Returns how
+    /// many were removed.
+    pub fn clear_overrides(&mut self, keep_default: bool) -> ConfigResult<usize> {
+        let mut removed = 0;
+        self.modify(|config| {
+            let before = config.version_mappings.len();
+            config
+                .version_mappings
+                .retain(|dir, _| keep_default && dir == Path::new("default"));
+            removed = before - config.version_mappings.len();
+        })?;
+        Ok(removed)
+    }
+
+    pub fn pre_install(&self) -> Option<&str> {
+        self.pre_install.as_deref()
+    }
+
+    pub fn post_install(&self) -> Option<&str> {
+        self.post_install.as_deref()
+    }
+
+    pub fn global_prefix(&self) -> Option<&Path> {
+        self.global_prefix.as_deref()
+    }
+
+    /// Sets the shared npm global prefix, or turns it off when `None`
+    pub fn set_global_prefix(&mut self, prefix: Option<PathBuf>) -> ConfigResult<()> {
+        self.modify(|config| config.global_prefix = prefix)
+    }
+
+    pub fn max_concurrency(&self) -> Option<usize> {
+        self.max_concurrency
+    }
+
+    /// Sets the most downloads run at once, or goes back to the default when `None`
+    pub fn set_max_concurrency(&mut self, max_concurrency: Option<usize>) -> ConfigResult<()> {
+        self.modify(|config| config.max_concurrency = max_concurrency)
+    }
+
+    pub fn preferred_lts(&self) -> Option<&str> {
+        self.preferred_lts.as_deref()
+    }
+
+    /// Sets the lts line `lts` resolves to, or goes back to the newest line when `None`
+    pub fn set_preferred_lts(&mut self, codename: Option<String>) -> ConfigResult<()> {
+        self.modify(|config| config.preferred_lts = codename)
+    }
+
+    pub fn alias(&self, name: &str) -> Option<&Version> {
+        self.aliases.get(name)
+    }
+
+    pub fn aliases(&self) -> impl Iterator<Item = (&String, &Version)> {
+        self.aliases.iter()
     }
 
-    fn override_at_path(&self, path: &Path) -> ConfigResult<Option<Target>> {
+    pub fn set_alias(&mut self, name: String, version: Version) -> ConfigResult<()> {
+        self.modify(|config| {
+            config.aliases.insert(name, version);
+        })
+    }
+
+    /// Returns the version the alias pointed at if it existed
+    pub fn remove_alias(&mut self, name: &str) -> ConfigResult<Option<Version>> {
+        let mut removed = None;
+        self.modify(|config| removed = config.aliases.remove(name))?;
+        Ok(removed)
+    }
+
+    // The config is read again while holding the lock so changes written by another process since
+    // this one was fetched aren't lost
+    fn modify<F>(&mut self, change: F) -> ConfigResult<()>
+    where
+        F: FnOnce(&mut Config),
+    {
+        let config_file = self.file()?;
+        let _lock = ConfigLock::acquire(ConfigFiles::next_to(&config_file).lock)?;
+
+        let mut latest = Config::load_from(&config_file)?;
+        change(&mut latest);
+        latest.update()?;
+
+        *self = latest;
+        Ok(())
+    }
+
+    /// The version set for exactly this directory. In order of precedence it comes from:
+    ///
+    /// 1. an override set with `nodeup override add`
+    /// 2. a .nvmrc file
+    /// 3. a .node-version file, as used by nodenv and fnm
+    /// 4. the nodejs entry of a .tool-versions file, as used by asdf
+    /// 5. the engines field of a package.json file
+    fn override_at_path(&self, path: &Path) -> ConfigResult<Option<Resolution>> {
         if let Some(target) = self.version_mappings.get(path) {
-            return Ok(Some(*target));
+            debug!("Found an override for {}", path.display());
+            return Ok(Some(Resolution {
+                target: target.clone(),
+                source: ResolutionSource::Override(path.to_path_buf()),
+            }));
         };
 
-        let entry_iter = match std::fs::read_dir(path) {
-            Ok(iter) => iter,
-            Err(e) => {
-                error!("Error getting the iterator at path: {:?}.\n{}", path, e);
-                return Ok(None);
+        // Looking the files up directly is cheaper than listing the directory and still works for
+        // directories that can be searched but not listed
+        let version_file = |name| {
+            let file = Some(path.join(name)).filter(|file| file.is_file());
+            if let Some(file) = &file {
+                debug!("Found {}", file.display());
             }
+            file
         };
+        let nvmrc = version_file(".nvmrc");
+        let node_version = version_file(".node-version");
+        let tool_versions = version_file(".tool-versions");
+        let package_json = version_file("package.json");
 
-        for entry in entry_iter {
-            let entry = match entry {
-                Ok(entry) => entry,
-                Err(e) => {
-                    error!("Error reading entry in iterator {}", e);
-                    continue;
+        let resolved =
+            |target: Option<Target>, source: fn(PathBuf) -> ResolutionSource, path: PathBuf| {
+                if target.is_none() {
+                    debug!("{} doesn't set a version", path.display());
                 }
+                target.map(|target| Resolution {
+                    target,
+                    source: source(path),
+                })
             };
 
-            if entry.file_name() != ".nvmrc" {
-                continue;
-            };
+        if let Some(nvmrc) = nvmrc {
+            let target = read_version_file(&nvmrc)?;
+            if let Some(resolution) = resolved(target, ResolutionSource::Nvmrc, nvmrc) {
+                return Ok(Some(resolution));
+            }
+        }
 
-            let nvmrc_path = entry.path();
-            let version_string = match std::fs::read_to_string(&nvmrc_path) {
-                Ok(version_string) => version_string,
-                Err(e) => {
-                    error!("Error reading nvmrc file at: {:?}\n{}", nvmrc_path, e);
-                    continue;
-                }
-            };
+        if let Some(node_version) = node_version {
+            let target = read_version_file(&node_version)?;
+            if let Some(resolution) = resolved(target, ResolutionSource::NodeVersion, node_version)
+            {
+                return Ok(Some(resolution));
+            }
+        }
+
+        if let Some(tool_versions) = tool_versions {
+            let target = read_tool_versions(&tool_versions)?;
+            if let Some(resolution) =
+                resolved(target, ResolutionSource::ToolVersions, tool_versions)
+            {
+                return Ok(Some(resolution));
+            }
+        }
+
+        match package_json {
+            Some(package_json) => {
+                let target = engines_target(&package_json)?;
+                Ok(resolved(
+                    target,
+                    ResolutionSource::PackageJson,
+                    package_json,
+                ))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// Files kept next to the config. Updates and restores both go through this so they agree on which
+/// file is locked and where the backup is, ex/ settings.lock and settings.toml.bak
+struct ConfigFiles {
+    lock: PathBuf,
+    backup: PathBuf,
+}
+
+impl ConfigFiles {
+    fn next_to(config_file: &Path) -> Self {
+        let mut backup_name = config_file.file_name().unwrap_or_default().to_os_string();
+        backup_name.push(".bak");
+        ConfigFiles {
+            lock: config_file.with_extension("lock"),
+            backup: config_file.with_file_name(backup_name),
+        }
+    }
+}
+
+// The default is stored under the key "default" which must not be resolved against the cwd
+fn override_key(dir: PathBuf) -> PathBuf {
+    match dir == Path::new("default") {
+        true => dir,
+        false => canonical_dir(&dir),
+    }
+}
+
+// Directories that no longer exist can't be canonicalized, so the closest ancestor that does is
+// canonicalized instead and the rest of the path is kept as is
+fn canonical_dir(dir: &Path) -> PathBuf {
+    match dir.canonicalize() {
+        Ok(canonical) => strip_verbatim_prefix(canonical),
+        Err(_) => match (dir.parent(), dir.file_name()) {
+            (Some(parent), Some(name)) => canonical_dir(parent).join(name),
+            _ => dir.to_path_buf(),
+        },
+    }
+}
+
+// Windows canonicalizes to verbatim paths, ex/ \\?\C:\project, which don't match the same directory
+// written any other way. Network shares, \\?\UNC\..., are left verbatim
+fn strip_verbatim_prefix(path: PathBuf) -> PathBuf {
+    match path.to_str().and_then(|path| path.strip_prefix(r"\\?\")) {
+        Some(stripped) if !stripped.starts_with(r"UNC\") => PathBuf::from(stripped),
+        _ => path,
+    }
+}
+
+// Reads a file containing a single version, ex/ .nvmrc or .node-version. A file that can't be read
+// is skipped, but one that can't be parsed is an error
+fn read_version_file(version_file: &Path) -> ConfigResult<Option<Target>> {
+    let version_string = match std::fs::read_to_string(version_file) {
+        Ok(version_string) => version_string,
+        Err(e) => {
+            error!("Error reading version file at: {:?}\n{}", version_file, e);
+            return Ok(None);
+        }
+    };
+
+    let version = parse_version_file(&version_string, version_file)?;
+    Ok(Some(Target::from_version(version)))
+}
+
+// Each line of a .tool-versions file names a tool followed by one or more versions, the first of which
+// is used, ex/ nodejs 18.16.0. Lines for other tools and comments are ignored
+fn read_tool_versions(tool_versions: &Path) -> ConfigResult<Option<Target>> {
+    let content = match std::fs::read_to_string(tool_versions) {
+        Ok(content) => content,
+        Err(e) => {
+            error!(
+                "Error reading .tool-versions at: {:?}\n{}",
+                tool_versions, e
+            );
+            return Ok(None);
+        }
+    };
+
+    let node_version = content.lines().find_map(|line| {
+        let line = line.split('#').next().unwrap_or_default();
+        let mut fields = line.split_whitespace();
+        match fields.next() {
+            Some("nodejs") | Some("node") => fields.next(),
+            _ => None,
+        }
+    });
 
-            let version =
-                Version::parse(&version_string).map_err(|source| ConfigError::ParseError {
+    match node_version {
+        Some(version) => parse_version_file(version, tool_versions)
+            .map(|version| Some(Target::from_version(version))),
+        None => Ok(None),
+    }
+}
+
+// Like nvm, anything other than an exact version is resolved against the installed versions so the
+// shim never has to reach the registry. That covers partial versions, ex/ 18, and the keywords node
+// for the newest installed version, lts/* for the newest installed lts release, and lts/<codename>
+fn parse_version_file(content: &str, version_file: &Path) -> ConfigResult<Version> {
+    let content = content.trim();
+    if let Ok(version) = Version::parse(content) {
+        return Ok(version);
+    }
+
+    let installed: Vec<Version> = installed_targets()?
+        .into_iter()
+        .map(|target| target.version().clone())
+        .filter(|version| version.prerelease.is_none())
+        .collect();
+    let newest = match content {
+        "node" => installed.into_iter().max(),
+        "lts/*" => {
+            let lines = lts_majors(None)?;
+            installed
+                .into_iter()
+                .filter(|version| match &lines {
+                    Some(lines) => lines.contains(&version.major),
+                    // Every even numbered line becomes an lts line
+                    None => version.major >= 4 && version.major % 2 == 0,
+                })
+                .max()
+        }
+        _ => match content.strip_prefix("lts/") {
+            Some(codename) => {
+                let lines = lts_majors(Some(codename))?.unwrap_or_default();
+                installed
+                    .into_iter()
+                    .filter(|version| lines.contains(&version.major))
+                    .max()
+            }
+            None => {
+                let req = VersionReq::parse(content).map_err(|source| ConfigError::ParseError {
                     source,
-                    path: path.to_path_buf(),
+                    path: version_file.to_path_buf(),
                 })?;
+                installed
+                    .into_iter()
+                    .filter(|version| req.matches(version))
+                    .max()
+            }
+        },
+    };
+
+    newest.ok_or_else(|| ConfigError::NoInstalledMatch {
+        path: version_file.to_path_buf(),
+        content: content.to_string(),
+    })
+}
+
+// The major versions of the lts lines, or only of the one with the codename, from the cached index.
+// None when the index hasn't been cached yet
+fn lts_majors(codename: Option<&str>) -> ConfigResult<Option<Vec<usize>>> {
+    let lines = match registry::cached_lts_lines()? {
+        Some(lines) => lines,
+        None => {
+            debug!("The lts lines aren't known until the index is cached, ex/ by `nodeup versions lts`");
+            return Ok(None);
+        }
+    };
+
+    Ok(Some(
+        lines
+            .into_iter()
+            .filter(|(name, _)| codename.is_none_or(|codename| name.eq_ignore_ascii_case(codename)))
+            .map(|(_, version)| version.major)
+            .collect(),
+    ))
+}
+
+// The newest installed version satisfying the engines field of package.json. A package.json without
+// a usable engines field, or whose range no installed version satisfies, is skipped so resolution
+// continues up the tree.
+fn engines_target(package_json: &Path) -> ConfigResult<Option<Target>> {
+    let range = match engines_range(package_json) {
+        Some(range) => range,
+        None => return Ok(None),
+    };
+
+    match newest_satisfying(&range, installed_targets()?) {
+        Some(target) => Ok(Some(target)),
+        None => {
+            warn!(
+                "The package.json at {:?} requires node {} but no installed version matches",
+                package_json, range
+            );
+            Ok(None)
+        }
+    }
+}
+
+fn engines_range(package_json: &Path) -> Option<VersionRange> {
+    let content = match fs::read_to_string(package_json) {
+        Ok(content) => content,
+        Err(e) => {
+            error!("Error reading package.json at: {:?}\n{}", package_json, e);
+            return None;
+        }
+    };
+
+    let manifest: serde_json::Value = match serde_json::from_str(&content) {
+        Ok(manifest) => manifest,
+        Err(e) => {
+            warn!(
+                "Ignoring malformed package.json at: {:?}\n{}",
+                package_json, e
+            );
+            return None;
+        }
+    };
+
+    let engines = manifest["engines"]["node"].as_str()?;
+    match VersionRange::parse(engines) {
+        Ok(range) => Some(range),
+        Err(e) => {
+            warn!(
+                "Ignoring unsupported engines range {:?} in {:?}\n{}",
+                engines, package_json, e
+            );
+            None
+        }
+    }
+}
 
-            return Ok(Some(Target::from_version(version)));
+fn newest_satisfying(range: &VersionRange, installed: Vec<Target>) -> Option<Target> {
+    installed
+        .into_iter()
+        .filter(|target| range.matches(target.version()))
+        .max_by(|a, b| a.version().cmp(b.version()))
+}
+
+fn installed_targets() -> ConfigResult<Vec<Target>> {
+    let download_dir = local::download_dir()?;
+    let entries = match fs::read_dir(&download_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(ConfigError::IO {
+                source,
+                path: download_dir,
+            })
+        }
+    };
+
+    Ok(entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| {
+            entry
+                .file_name()
+                .to_str()
+                .and_then(|name| name.parse().ok())
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE_PACKAGE_JSON: &str =
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/package.json");
+
+    fn empty_config() -> Config {
+        toml::from_str("").unwrap()
+    }
+
+    #[test]
+    fn unversioned_config_is_stamped() {
+        let mut config: Config = toml::from_str(
+            r#"
+            [aliases]
+            work = { major = 18, minor = 17, patch = 0 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(config.schema_version(), 0);
+
+        config.migrate().unwrap();
+        assert_eq!(config.schema_version(), SCHEMA_VERSION);
+        assert_eq!(config.aliases.len(), 1);
+
+        let serialized = toml::to_string(&config).unwrap();
+        assert!(serialized.starts_with("schema_version = 2\n"));
+    }
+
+    #[test]
+    fn migrate_canonicalizes_override_dirs() {
+        let project = tempfile::tempdir().unwrap();
+        let uncanonical = project.path().join("src").join("..");
+        fs::create_dir(project.path().join("src")).unwrap();
+
+        let mut config = empty_config();
+        config.schema_version = 1;
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        config
+            .version_mappings
+            .insert(uncanonical.clone(), target.clone());
+        config
+            .version_mappings
+            .insert(PathBuf::from("default"), target.clone());
+
+        config.migrate().unwrap();
+        let canonical = project.path().canonicalize().unwrap();
+        assert_eq!(config.version_mappings.get(&canonical), Some(&target));
+        assert_eq!(
+            config.version_mappings.get(Path::new("default")),
+            Some(&target)
+        );
+        assert!(!config.version_mappings.contains_key(&uncanonical));
+    }
+
+    #[test]
+    fn verbatim_prefix_is_stripped() {
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\C:\project")),
+            PathBuf::from(r"C:\project")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from(r"\\?\UNC\server\share")),
+            PathBuf::from(r"\\?\UNC\server\share")
+        );
+        assert_eq!(
+            strip_verbatim_prefix(PathBuf::from("/home/me/project")),
+            PathBuf::from("/home/me/project")
+        );
+    }
+
+    #[test]
+    fn default_sorts_before_directories() {
+        let config: Config = toml::from_str(
+            r#"
+            [version_mappings]
+            "/projects/web" = { version = { major = 18, minor = 17, patch = 0 }, os = "Linux", arch = "X64" }
+            "default" = { version = { major = 20, minor = 5, patch = 0 }, os = "Linux", arch = "X64" }
+            "/projects/api" = { version = { major = 16, minor = 20, patch = 1 }, os = "Linux", arch = "X64" }
+            "#,
+        )
+        .unwrap();
+
+        let scopes: Vec<OverrideScope> = config
+            .override_list()
+            .into_iter()
+            .map(|entry| entry.scope)
+            .collect();
+        assert_eq!(
+            scopes,
+            vec![
+                OverrideScope::Default,
+                OverrideScope::Directory(PathBuf::from("/projects/api")),
+                OverrideScope::Directory(PathBuf::from("/projects/web")),
+            ]
+        );
+    }
+
+    #[test]
+    fn install_hooks() {
+        let mut config: Config = toml::from_str(r#"post_install = "npm i -g typescript""#).unwrap();
+        config
+            .aliases
+            .insert(String::from("work"), "v18.17.0".parse().unwrap());
+        assert_eq!(config.pre_install(), None);
+        assert_eq!(config.post_install(), Some("npm i -g typescript"));
+
+        // Hooks have to be written before the tables for the config to serialize
+        let serialized = toml::to_string(&config).unwrap();
+        let reparsed: Config = toml::from_str(&serialized).unwrap();
+        assert_eq!(reparsed.post_install(), config.post_install());
+        assert!(!serialized.contains("pre_install"));
+    }
+
+    #[test]
+    fn nvmrc_with_whitespace() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(".nvmrc"), "  v16.20.1\n").unwrap();
+
+        let resolution = empty_config().override_at_path(project.path()).unwrap();
+        assert_eq!(
+            resolution.unwrap().target.version(),
+            &Version::parse("v16.20.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn nvmrc_in_deep_ancestor() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(".nvmrc"), "v16.20.1").unwrap();
+        let mut cwd = project.path().to_path_buf();
+        for depth in 0..40 {
+            cwd.push(format!("level-{}", depth));
+            fs::create_dir(&cwd).unwrap();
+            // Unrelated entries alongside the path shouldn't affect the lookup
+            fs::create_dir(cwd.with_file_name(format!("sibling-{}", depth))).unwrap();
+        }
+        fs::create_dir(project.path().join("level-0").join(".nvmrc")).unwrap();
+
+        let resolution = empty_config().resolve_active(&cwd).unwrap().unwrap();
+        assert_eq!(
+            resolution.source,
+            ResolutionSource::Nvmrc(project.path().join(".nvmrc"))
+        );
+        assert_eq!(
+            resolution.target.version(),
+            &Version::parse("v16.20.1").unwrap()
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn nvmrc_in_unlistable_directory() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let project = tempfile::tempdir().unwrap();
+        let locked = project.path().join("locked");
+        fs::create_dir(&locked).unwrap();
+        fs::write(locked.join(".nvmrc"), "v16.20.1").unwrap();
+        fs::create_dir(locked.join("unreadable")).unwrap();
+        fs::set_permissions(locked.join("unreadable"), fs::Permissions::from_mode(0o000)).unwrap();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o311)).unwrap();
+
+        let resolution = empty_config().override_at_path(&locked);
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::set_permissions(locked.join("unreadable"), fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(
+            resolution.unwrap().unwrap().source,
+            ResolutionSource::Nvmrc(locked.join(".nvmrc"))
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn unreadable_override_dir_is_not_stale() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let projects = tempfile::tempdir().unwrap();
+        let locked = projects.path().join("locked");
+        let project = locked.join("project");
+        fs::create_dir_all(&project).unwrap();
+        let mut config = empty_config();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        config.version_mappings.insert(project, target.clone());
+        config
+            .version_mappings
+            .insert(projects.path().join("deleted"), target);
+
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o000)).unwrap();
+        let stale = config.stale_overrides();
+        fs::set_permissions(&locked, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_eq!(stale, vec![projects.path().join("deleted")]);
+    }
+
+    #[test]
+    fn malformed_nvmrc() {
+        let project = tempfile::tempdir().unwrap();
+        let nvmrc = project.path().join(".nvmrc");
+        fs::write(&nvmrc, "sixteen\n").unwrap();
+
+        match empty_config().override_at_path(project.path()) {
+            Err(ConfigError::ParseError { path, .. }) => assert_eq!(path, nvmrc),
+            other => panic!("Expected a parse error, got: {:?}", other),
         }
+    }
+
+    #[test]
+    fn node_version_file() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(".node-version"), "v16.20.1").unwrap();
+
+        let resolution = empty_config().override_at_path(project.path()).unwrap();
+        assert_eq!(
+            resolution.unwrap().target.version(),
+            &Version::parse("v16.20.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn nvmrc_before_node_version() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(".node-version"), "v16.20.1").unwrap();
+        fs::write(project.path().join(".nvmrc"), "v18.16.1").unwrap();
+
+        let resolution = empty_config().override_at_path(project.path()).unwrap();
+        assert_eq!(
+            resolution.unwrap().target.version(),
+            &Version::parse("v18.16.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn tool_versions_file() {
+        let project = tempfile::tempdir().unwrap();
+        let content = "# managed by asdf\nruby 3.2.2\nnodejs 18.16.0 16.20.1\npython 3.11.4\n";
+        fs::write(project.path().join(".tool-versions"), content).unwrap();
+
+        let resolution = empty_config().override_at_path(project.path()).unwrap();
+        assert_eq!(
+            resolution.unwrap().target.version(),
+            &Version::parse("v18.16.0").unwrap()
+        );
+    }
+
+    #[test]
+    fn tool_versions_without_node() {
+        let project = tempfile::tempdir().unwrap();
+        fs::write(project.path().join(".tool-versions"), "ruby 3.2.2\n").unwrap();
+
+        let resolution = empty_config().override_at_path(project.path()).unwrap();
+        assert_eq!(resolution, None);
+    }
 
-        Ok(None)
+    #[test]
+    fn resolution_sources() {
+        let project = tempfile::tempdir().unwrap();
+        let overridden = project.path().join("overridden");
+        let with_nvmrc = project.path().join("with-nvmrc");
+        fs::create_dir(&overridden).unwrap();
+        fs::create_dir(&with_nvmrc).unwrap();
+        fs::write(with_nvmrc.join(".nvmrc"), "v16.20.1").unwrap();
+
+        let config: Config = toml::from_str(&format!(
+            r#"
+            [version_mappings]
+            default = {{ os = "Linux", version = {{ major = 18, minor = 16, patch = 1 }} }}
+            {:?} = {{ os = "Linux", version = {{ major = 14, minor = 21, patch = 3 }} }}
+            "#,
+            overridden
+        ))
+        .unwrap();
+
+        let source = |dir: &Path| config.resolve_active(dir).unwrap().unwrap().source;
+        assert_eq!(
+            source(&overridden.join("nested")),
+            ResolutionSource::Override(overridden.clone())
+        );
+        assert_eq!(
+            source(&with_nvmrc),
+            ResolutionSource::Nvmrc(with_nvmrc.join(".nvmrc"))
+        );
+        assert_eq!(source(project.path()), ResolutionSource::Default);
+        assert_eq!(
+            ResolutionSource::Nvmrc(with_nvmrc.join(".nvmrc")).to_string(),
+            format!(".nvmrc at {}", with_nvmrc.display())
+        );
+    }
+
+    #[test]
+    fn package_json_engines() {
+        let range = engines_range(Path::new(FIXTURE_PACKAGE_JSON)).unwrap();
+        assert_eq!(range, VersionRange::parse(">=16 <19").unwrap());
+
+        let installed: Vec<Target> = vec![
+            "node-v14.21.3-linux-x64",
+            "node-v18.16.1-linux-x64",
+            "node-v16.20.1-linux-x64",
+            "node-v20.5.0-linux-x64",
+        ]
+        .into_iter()
+        .map(|name| name.parse().unwrap())
+        .collect();
+        let newest = newest_satisfying(&range, installed).unwrap();
+        assert_eq!(newest.to_string(), "node-v18.16.1-linux-x64");
+    }
+
+    #[test]
+    fn load_mutate_and_reload() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config_file = config_dir.path().join("settings.toml");
+
+        let mut config = Config::load_from(&config_file).unwrap();
+        assert_eq!(config.aliases().count(), 0);
+        let target: Target = "node-v18.17.0-linux-x64".parse().unwrap();
+        config
+            .set_override(target.clone(), config_dir.path().to_path_buf())
+            .unwrap();
+        config
+            .set_alias(String::from("work"), target.version().clone())
+            .unwrap();
+
+        let reloaded = Config::load_from(&config_file).unwrap();
+        assert_eq!(reloaded.alias("work"), Some(target.version()));
+        assert_eq!(
+            reloaded.get_active_target(config_dir.path()).unwrap(),
+            Some(target)
+        );
+        assert_eq!(reloaded.schema_version(), SCHEMA_VERSION);
+        assert!(config_dir.path().join("settings.toml.bak").is_file());
+    }
+
+    #[test]
+    fn restore_backup_next_to_config() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config_file = config_dir.path().join("settings.toml");
+        let mut config = Config::load_from(&config_file).unwrap();
+        let first: Version = "v18.17.0".parse().unwrap();
+        let second: Version = "v20.5.0".parse().unwrap();
+        config
+            .set_alias(String::from("work"), first.clone())
+            .unwrap();
+        config
+            .set_alias(String::from("work"), second.clone())
+            .unwrap();
+        assert!(config_dir.path().join("settings.lock").is_file());
+
+        Config::restore_backup_of(&config_file).unwrap();
+        let restored = Config::load_from(&config_file).unwrap();
+        assert_eq!(restored.alias("work"), Some(&first));
+
+        Config::restore_backup_of(&config_file).unwrap();
+        let undone = Config::load_from(&config_file).unwrap();
+        assert_eq!(undone.alias("work"), Some(&second));
+    }
+
+    #[test]
+    fn newer_config_is_refused() {
+        let config_dir = tempfile::tempdir().unwrap();
+        let config_file = config_dir.path().join("settings.toml");
+        let content = format!("schema_version = {}\n", SCHEMA_VERSION + 1);
+        fs::write(&config_file, &content).unwrap();
+
+        match Config::load_from(&config_file) {
+            Err(ConfigError::NewerSchema { path, version }) => {
+                assert_eq!(path, config_file);
+                assert_eq!(version, SCHEMA_VERSION + 1);
+            }
+            other => panic!("expected a newer schema error, got {:?}", other),
+        }
+        assert_eq!(fs::read_to_string(&config_file).unwrap(), content);
     }
 }
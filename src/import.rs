@@ -0,0 +1,236 @@
+use log::warn;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+};
+
+use crate::{ErrorTask, NodeupError, NodeupResult, Target, Version};
+
+/// How versions are brought over from another version manager
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ImportMode {
+    /// The version stays where it is and nodeup's download directory links to it
+    Link,
+    /// The version is copied into the download directory
+    Copy,
+}
+
+/// Targets brought over by an import along with the ones that were already installed
+#[derive(Debug, Default, PartialEq)]
+pub struct Imported {
+    pub imported: Vec<Target>,
+    pub skipped: Vec<Target>,
+}
+
+/// nvm's directory, $NVM_DIR or ~/.nvm
+pub fn nvm_dir() -> Option<PathBuf> {
+    env::var_os("NVM_DIR")
+        .map(PathBuf::from)
+        .or_else(|| dirs::home_dir().map(|home| home.join(".nvm")))
+}
+
+/// Brings the versions installed by nvm into the download directory. nvm keeps each version under
+/// versions/node/<version> with the same layout as the archives nodeup downloads, so they only need
+/// to be named after the target.
+pub fn import_nvm(nvm_dir: &Path, download_dir: &Path, mode: ImportMode) -> NodeupResult<Imported> {
+    use ErrorTask::Importing as task;
+
+    let versions_dir = nvm_dir.join("versions").join("node");
+    let entries = fs::read_dir(&versions_dir).map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: versions_dir.clone(),
+    })?;
+
+    let mut found: Vec<(Target, PathBuf)> = entries
+        .filter_map(|entry| entry.ok())
+        .filter(|entry| entry.path().is_dir())
+        .filter_map(|entry| {
+            let name = entry.file_name();
+            match Version::parse(&name.to_string_lossy()) {
+                Ok(version) => Some((Target::from_version(version), entry.path())),
+                Err(e) => {
+                    warn!("Skipping {}: {}", entry.path().display(), e);
+                    None
+                }
+            }
+        })
+        .collect();
+    found.sort_by(|(a, _), (b, _)| a.version().cmp(b.version()));
+
+    fs::create_dir_all(download_dir).map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: download_dir.to_path_buf(),
+    })?;
+
+    let mut imported = Imported::default();
+    for (target, source_dir) in found {
+        let destination = download_dir.join(target.to_string());
+        if destination.exists() {
+            imported.skipped.push(target);
+            continue;
+        }
+        // A dangling link, ex/ from an earlier import of a version nvm has since removed, is replaced
+        if fs::symlink_metadata(&destination).is_ok() {
+            remove_link(&destination).map_err(|source| NodeupError::IO {
+                source,
+                task,
+                path: destination.clone(),
+            })?;
+        }
+
+        let result = match mode {
+            ImportMode::Link => link_dir(&source_dir, &destination),
+            ImportMode::Copy => copy_into_place(&source_dir, download_dir, &destination),
+        };
+        result.map_err(|source| NodeupError::IO {
+            source,
+            task,
+            path: destination,
+        })?;
+        imported.imported.push(target);
+    }
+
+    Ok(imported)
+}
+
+#[cfg(unix)]
+fn link_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::unix::fs::symlink(source, destination)
+}
+
+#[cfg(windows)]
+fn link_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    std::os::windows::fs::symlink_dir(source, destination)
+}
+
+// Windows directory links are removed as directories
+fn remove_link(link: &Path) -> io::Result<()> {
+    fs::remove_file(link).or_else(|_| fs::remove_dir(link))
+}
+
+// The copy is made in a staging directory next to the other versions and only renamed into place
+// once it's complete, so a failed copy never leaves a partial version behind
+fn copy_into_place(source: &Path, download_dir: &Path, destination: &Path) -> io::Result<()> {
+    let staging = tempfile::tempdir_in(download_dir)?;
+    let copied = staging.path().join("version");
+    copy_dir(source, &copied)?;
+    fs::rename(&copied, destination)
+}
+
+// Symlinks inside a version, ex/ bin/npm, are recreated rather than followed
+fn copy_dir(source: &Path, destination: &Path) -> io::Result<()> {
+    fs::create_dir(destination)?;
+    for entry in fs::read_dir(source)? {
+        let entry = entry?;
+        let from = entry.path();
+        let to = destination.join(entry.file_name());
+        let file_type = entry.file_type()?;
+        if file_type.is_dir() {
+            copy_dir(&from, &to)?;
+        } else if file_type.is_symlink() {
+            let link = fs::read_link(&from)?;
+            #[cfg(unix)]
+            std::os::unix::fs::symlink(link, &to)?;
+            #[cfg(windows)]
+            fs::copy(from.parent().unwrap_or(source).join(link), &to).map(|_| ())?;
+        } else {
+            fs::copy(&from, &to)?;
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installed_versions;
+    use tempfile::tempdir;
+
+    fn fake_nvm(versions: &[&str]) -> tempfile::TempDir {
+        let nvm = tempdir().unwrap();
+        for version in versions {
+            let bin = nvm.path().join("versions/node").join(version).join("bin");
+            fs::create_dir_all(&bin).unwrap();
+            fs::write(bin.join("node"), version).unwrap();
+        }
+        nvm
+    }
+
+    #[test]
+    fn import_nvm_versions() {
+        let nvm = fake_nvm(&["v16.20.1", "v18.17.0"]);
+        let download_dir = tempdir().unwrap();
+
+        let imported = import_nvm(nvm.path(), download_dir.path(), ImportMode::Link).unwrap();
+
+        let expected: Vec<Target> = vec![
+            Target::from_version("v16.20.1".parse().unwrap()),
+            Target::from_version("v18.17.0".parse().unwrap()),
+        ];
+        assert_eq!(imported.imported, expected);
+        let mut installed = installed_versions(download_dir.path()).unwrap();
+        installed.sort_by(|a, b| a.version().cmp(b.version()));
+        assert_eq!(installed, expected);
+        let node = download_dir
+            .path()
+            .join(expected[1].to_string())
+            .join("bin/node");
+        assert_eq!(fs::read_to_string(node).unwrap(), "v18.17.0");
+    }
+
+    #[test]
+    fn import_copies_and_skips_installed() {
+        let nvm = fake_nvm(&["v16.20.1", "v18.17.0"]);
+        let download_dir = tempdir().unwrap();
+        let installed = Target::from_version("v16.20.1".parse().unwrap());
+        fs::create_dir(download_dir.path().join(installed.to_string())).unwrap();
+
+        let imported = import_nvm(nvm.path(), download_dir.path(), ImportMode::Copy).unwrap();
+
+        assert_eq!(imported.skipped, vec![installed]);
+        let copied = download_dir.path().join(imported.imported[0].to_string());
+        assert!(!fs::symlink_metadata(&copied)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert!(copied.join("bin/node").is_file());
+    }
+
+    #[test]
+    fn import_replaces_dangling_link() {
+        let nvm = fake_nvm(&["v18.17.0"]);
+        let download_dir = tempdir().unwrap();
+        let target = Target::from_version("v18.17.0".parse().unwrap());
+        let destination = download_dir.path().join(target.to_string());
+        link_dir(&nvm.path().join("versions/node/v16.20.1"), &destination).unwrap();
+
+        let imported = import_nvm(nvm.path(), download_dir.path(), ImportMode::Link).unwrap();
+
+        assert_eq!(imported.imported, vec![target]);
+        assert_eq!(
+            fs::read_to_string(destination.join("bin/node")).unwrap(),
+            "v18.17.0"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failed_copy_leaves_nothing_behind() {
+        let nvm = fake_nvm(&["v18.17.0"]);
+        let download_dir = tempdir().unwrap();
+        // Sockets can't be copied so the copy fails partway through
+        let socket = nvm.path().join("versions/node/v18.17.0/bin/socket");
+        let _listener = std::os::unix::net::UnixListener::bind(&socket).unwrap();
+
+        let result = import_nvm(nvm.path(), download_dir.path(), ImportMode::Copy);
+
+        assert!(
+            matches!(result, Err(NodeupError::IO { .. })),
+            "{:?}",
+            result
+        );
+        assert_eq!(fs::read_dir(download_dir.path()).unwrap().count(), 0);
+    }
+}
@@ -1,27 +1,60 @@
 use log::warn;
+#[cfg(unix)]
+use std::os::unix::fs::symlink;
 use std::{
     env, fmt, fs, io,
     io::ErrorKind,
-    os::unix::fs::symlink,
     path::{Path, PathBuf},
-    process::{Command, Stdio},
+    process::{Command, ExitStatus},
 };
 use thiserror::Error;
 
 pub mod config;
+pub mod import;
 pub mod local;
 pub mod registry;
+#[cfg(feature = "self-update")]
+pub mod self_update;
+pub mod shell;
 mod target;
 pub mod verify;
 
-pub use config::{Config, ConfigError};
+pub use config::{Config, ConfigError, Override, OverrideScope, Resolution, ResolutionSource};
 use local::LocalError;
 pub use registry::get_latest_lts;
-pub use target::{Target, Version};
+use registry::{Registry, RegistryError};
+pub use target::{
+    Architecture, ArchitectureError, Libc, OperatingSystem, Target, TargetError, Version,
+    VersionError, VersionRange, VersionReq,
+};
 
 pub const NODE_EXECUTABLE: &str = "node";
 pub const NPM_EXECUTABLE: &str = "npm";
 pub const NPX_EXECUTABLE: &str = "npx";
+pub const COREPACK_EXECUTABLE: &str = "corepack";
+pub const YARN_EXECUTABLE: &str = "yarn";
+pub const PNPM_EXECUTABLE: &str = "pnpm";
+
+/// Environment variable npm reads its global prefix from
+const NPM_PREFIX_VAR: &str = "NPM_CONFIG_PREFIX";
+
+/// Executables linked by `nodeup control link` unless more are requested
+pub const DEFAULT_EXECUTABLES: [&str; 4] = [
+    NODE_EXECUTABLE,
+    NPM_EXECUTABLE,
+    NPX_EXECUTABLE,
+    COREPACK_EXECUTABLE,
+];
+
+/// Every executable nodeup knows how to dispatch to the active node version
+pub const SHIM_EXECUTABLES: [&str; 6] = [
+    NODE_EXECUTABLE,
+    NPM_EXECUTABLE,
+    NPX_EXECUTABLE,
+    COREPACK_EXECUTABLE,
+    YARN_EXECUTABLE,
+    PNPM_EXECUTABLE,
+];
 
 #[cfg(test)]
 #[ctor::ctor]
@@ -52,6 +85,12 @@ pub enum NodeupError {
         task: ErrorTask,
     },
 
+    #[error("An error occured downloading from the registry while trying to {task}: {source}")]
+    Registry {
+        source: RegistryError,
+        task: ErrorTask,
+    },
+
     #[error("Couldn't create symlinks required to {task}: {source}")]
     Linking {
         source: LinkingError,
@@ -63,8 +102,110 @@ pub enum NodeupError {
     )]
     NoVersionFound,
 
-    #[error("Couldn't find target {target} when trying to {task}. You can install the target by running: nodeup versions add {}", target.version())]
-    VersionNotFound { target: Target, task: ErrorTask },
+    #[error("{target} doesn't include {bin:?}. Run `nodeup versions bins {}` to see the executables it does include", target.version())]
+    BinNotFound { bin: String, target: Target },
+
+    #[error("{name:?} can't be used as an alias since it would be mistaken for a version")]
+    InvalidAlias { name: String },
+
+    #[error("There is no alias named {name:?}")]
+    AliasNotFound { name: String },
+
+    #[error("{input:?} isn't a version, range, alias, or keyword like lts: {source}")]
+    InvalidVersion { input: String, source: VersionError },
+
+    #[error("The {hook} hook {command:?} failed with {status}")]
+    HookFailed {
+        hook: Hook,
+        command: String,
+        status: ExitStatus,
+    },
+
+    #[error("{target} is used by: {}. Removing it would leave them without node. Pass --force to remove it along with them", users.iter().map(|scope| scope.to_string()).collect::<Vec<_>>().join(", "))]
+    VersionInUse {
+        target: Target,
+        users: Vec<OverrideScope>,
+    },
+
+    #[error("Couldn't {task} since {target} isn't installed. Install it by running: nodeup versions add {}{}. {}", target.version(), force_hint(task), installed_message(installed))]
+    NotInstalled {
+        target: Target,
+        installed: Vec<Target>,
+        task: ErrorTask,
+    },
+}
+
+impl NodeupError {
+    /// The code the nodeup binary exits with when it fails with this error
+    ///
+    /// |Code|Cause                                    |
+    /// |----|-----------------------------------------|
+    /// |1   |Anything else, ex/ an invalid alias      |
+    /// |2   |The config couldn't be read or written   |
+    /// |3   |Local files or links couldn't be accessed|
+    /// |4   |The node registry couldn't be reached    |
+    /// |5   |No version is set for the directory      |
+    /// |6   |The requested version isn't installed    |
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            NodeupError::Config {
+                source: ConfigError::Registry(_),
+                ..
+            } => 4,
+            NodeupError::Config {
+                source: ConfigError::Local(_),
+                ..
+            } => 3,
+            NodeupError::Config { .. } => 2,
+            NodeupError::Local { .. } | NodeupError::IO { .. } | NodeupError::Linking { .. } => 3,
+            NodeupError::Registry { .. } => 4,
+            NodeupError::NoVersionFound => 5,
+            NodeupError::BinNotFound { .. } | NodeupError::NotInstalled { .. } => 6,
+            NodeupError::InvalidAlias { .. }
+            | NodeupError::AliasNotFound { .. }
+            | NodeupError::InvalidVersion { .. }
+            | NodeupError::VersionInUse { .. }
+            | NodeupError::HookFailed { .. } => 1,
+        }
+    }
+
+    /// A name for the variant that programs reading `--format json` errors can match on. These
+    /// don't change once released
+    pub fn kind(&self) -> &'static str {
+        match self {
+            NodeupError::Local { .. } => "local",
+            NodeupError::IO { .. } => "io",
+            NodeupError::Config { .. } => "config",
+            NodeupError::Registry { .. } => "registry",
+            NodeupError::Linking { .. } => "linking",
+            NodeupError::NoVersionFound => "no_version_found",
+            NodeupError::BinNotFound { .. } => "bin_not_found",
+            NodeupError::InvalidAlias { .. } => "invalid_alias",
+            NodeupError::AliasNotFound { .. } => "alias_not_found",
+            NodeupError::InvalidVersion { .. } => "invalid_version",
+            NodeupError::HookFailed { .. } => "hook_failed",
+            NodeupError::VersionInUse { .. } => "version_in_use",
+            NodeupError::NotInstalled { .. } => "not_installed",
+        }
+    }
+}
+
+// Overrides can point at a version before it's installed
+fn force_hint(task: &ErrorTask) -> &'static str {
+    match task {
+        ErrorTask::Override | ErrorTask::ChangingDefault => ", or pass --force to use it anyway",
+        _ => "",
+    }
+}
+
+fn installed_message(installed: &[Target]) -> String {
+    match installed.is_empty() {
+        true => String::from("No versions are installed"),
+        false => {
+            let names: Vec<String> = installed.iter().map(|t| t.to_string()).collect();
+            format!("Installed versions are: {}", names.join(", "))
+        }
+    }
 }
 
 #[derive(Debug, Error)]
@@ -72,20 +213,33 @@ pub enum LinkingError {
     #[error("An IO error occurect while trying to access {path:?}: {source}")]
     IO { source: io::Error, path: PathBuf },
 
-    #[error("It looks like something already exists at {path}. Try removing and linking again. The link directory can also be controlled by setting the $NODEUP_LINKS environment variable")]
+    #[error("It looks like something already exists at {path}. Try removing and linking again, or link with --force to move it aside. The link directory can also be controlled by setting the $NODEUP_LINKS environment variable")]
     AlreadyExists { path: PathBuf },
 }
 
 #[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum ErrorTask {
     ActiveVersions,
+    Alias,
     ChangingDefault,
+    ChangingSetting,
+    CheckingInstalled,
+    ClearingOverrides,
     Executing,
+    Hook,
+    Importing,
     Installing,
     Linking,
+    ListingBins,
     Override,
+    Pruning,
+    PruningOverrides,
+    RestoringConfig,
+    Reinstalling,
     Removing,
     RemovingOverride,
+    ResolvingVersion,
+    Unlinking,
     Verify,
     Which,
 }
@@ -96,40 +250,226 @@ impl fmt::Display for ErrorTask {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
         match self {
             ErrorTask::ActiveVersions => write!(f, "list active versions"),
+            ErrorTask::Alias => write!(f, "manage aliases"),
             ErrorTask::ChangingDefault => write!(f, "change default"),
+            ErrorTask::ChangingSetting => write!(f, "change setting"),
+            ErrorTask::CheckingInstalled => write!(f, "check if node is installed"),
+            ErrorTask::ClearingOverrides => write!(f, "remove all overrides"),
             ErrorTask::Executing => write!(f, "execute command"),
+            ErrorTask::Hook => write!(f, "run install hook"),
+            ErrorTask::Importing => write!(f, "import versions"),
             ErrorTask::Installing => write!(f, "install node"),
             ErrorTask::Linking => write!(f, "create sym links"),
+            ErrorTask::ListingBins => write!(f, "list executables"),
             ErrorTask::Override => write!(f, "create override"),
+            ErrorTask::Pruning => write!(f, "remove unused versions"),
+            ErrorTask::PruningOverrides => write!(f, "remove stale overrides"),
+            ErrorTask::RestoringConfig => write!(f, "restore the config"),
+            ErrorTask::Reinstalling => write!(f, "reinstall node"),
             ErrorTask::Removing => write!(f, "remove node"),
             ErrorTask::RemovingOverride => write!(f, "remove override"),
+            ErrorTask::ResolvingVersion => write!(f, "resolve version"),
+            ErrorTask::Unlinking => write!(f, "remove sym links"),
             ErrorTask::Verify => write!(f, "verify setup"),
             ErrorTask::Which => write!(f, "find active node version"),
         }
     }
 }
 
-pub fn remove_node(target: Target) -> NodeupResult<()> {
+/// Whether the target has been downloaded to the download directory
+pub fn is_installed(target: &Target) -> NodeupResult<bool> {
+    use ErrorTask::CheckingInstalled as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    Ok(is_installed_at(&download_dir, target))
+}
+
+fn is_installed_at(download_dir: &Path, target: &Target) -> bool {
+    download_dir.join(target.to_string()).is_dir()
+}
+
+// Errors with the versions that are installed so the user can pick one of them instead. Overrides
+// pointing at versions that were never downloaded only fail later when node is run, so they're
+// checked with this up front unless forced
+fn ensure_installed_at(download_dir: &Path, target: &Target, task: ErrorTask) -> NodeupResult<()> {
+    if is_installed_at(download_dir, target) {
+        return Ok(());
+    }
+
+    let installed = installed_versions(download_dir)?;
+    Err(NodeupError::NotInstalled {
+        target: target.clone(),
+        installed,
+        task,
+    })
+}
+
+fn ensure_installed(target: &Target, task: ErrorTask) -> NodeupResult<()> {
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    ensure_installed_at(&download_dir, target, task)
+}
+
+/// Removes an installed version. A version the default or an override is set to is only removed
+/// with `force`, which removes those overrides as well. Returns where they were set
+pub fn remove_node(target: Target, force: bool) -> NodeupResult<Vec<OverrideScope>> {
     use ErrorTask::Removing as task;
 
-    let path = local::target_path(&target).map_err(|source| NodeupError::Local { source, task })?;
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    remove_node_at(&download_dir, &mut config, target, force)
+}
+
+fn remove_node_at(
+    download_dir: &Path,
+    config: &mut Config,
+    target: Target,
+    force: bool,
+) -> NodeupResult<Vec<OverrideScope>> {
+    use ErrorTask::Removing as task;
 
-    if !path.exists() {
-        return Err(NodeupError::VersionNotFound { task, target });
+    let path = download_dir.join(target.to_string());
+    if !is_installed_at(download_dir, &target) {
+        let installed = installed_versions(download_dir)?;
+        return Err(NodeupError::NotInstalled {
+            target,
+            installed,
+            task,
+        });
     };
 
+    let users = config.overrides_using(&target);
+    if !users.is_empty() && !force {
+        return Err(NodeupError::VersionInUse { target, users });
+    }
+
     fs::remove_dir_all(&path).map_err(|source| NodeupError::IO { source, task, path })?;
-    Ok(())
+    match users.is_empty() {
+        true => Ok(users),
+        false => config
+            .remove_overrides_using(&target)
+            .map_err(|source| NodeupError::Config { source, task }),
+    }
 }
 
-pub fn installed_versions(path: &Path) -> NodeupResult<Vec<Target>> {
-    use ErrorTask::Installing as task;
+/// Removes whatever is installed for the target and downloads it again. Overrides are left alone so
+/// anything pointing at the target keeps using it. Installs the target if it wasn't installed.
+pub fn reinstall(target: Target) -> NodeupResult<()> {
+    use ErrorTask::Reinstalling as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    reinstall_at(&download_dir, target, |dir, target| {
+        registry::download_node_toolchain(dir, target).map(|_| ())
+    })
+}
+
+fn reinstall_at<F>(download_dir: &Path, target: Target, install: F) -> NodeupResult<()>
+where
+    F: FnOnce(&Path, Target) -> Result<(), RegistryError>,
+{
+    use ErrorTask::Reinstalling as task;
 
-    let entries = fs::read_dir(path).map_err(|source| NodeupError::IO {
+    let io_error = |source, path: &Path| NodeupError::IO {
         source,
         task,
         path: path.to_path_buf(),
-    })?;
+    };
+
+    // The new install is unpacked next to the old one and only swapped in once it's complete, so a
+    // failed download leaves the old install untouched
+    fs::create_dir_all(download_dir).map_err(|source| io_error(source, download_dir))?;
+    let staging =
+        tempfile::tempdir_in(download_dir).map_err(|source| io_error(source, download_dir))?;
+    install(staging.path(), target.clone())
+        .map_err(|source| NodeupError::Registry { source, task })?;
+
+    let path = download_dir.join(target.to_string());
+    let installed = staging.path().join(target.to_string());
+    if !path.exists() {
+        return fs::rename(&installed, &path).map_err(|source| io_error(source, &path));
+    }
+
+    // The old install is moved into the staging directory so it's removed along with it
+    let old = staging.path().join("old");
+    fs::rename(&path, &old).map_err(|source| io_error(source, &path))?;
+    if let Err(source) = fs::rename(&installed, &path) {
+        let _ = fs::rename(&old, &path);
+        return Err(io_error(source, &path));
+    }
+
+    if let Err(e) = staging.close() {
+        warn!("Couldn't remove the previous install of {}: {}", target, e);
+    }
+    Ok(())
+}
+
+/// Installed versions that aren't used by any override or the default. Versions only referenced by a
+/// .nvmrc file can't be known about so they're included.
+pub fn unused_versions() -> NodeupResult<Vec<Target>> {
+    use ErrorTask::Pruning as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+
+    let installed = installed_versions(&download_dir)?;
+    Ok(installed
+        .into_iter()
+        .filter(|target| !config.references(target))
+        .collect())
+}
+
+/// Removes every version returned by `unused_versions`, returning the versions that were removed
+pub fn prune_unused() -> NodeupResult<Vec<Target>> {
+    let unused = unused_versions()?;
+    for target in &unused {
+        remove_node(target.clone(), false)?;
+    }
+
+    Ok(unused)
+}
+
+/// The number of bytes an installed version takes up on disk
+pub fn installed_size(target: &Target) -> NodeupResult<u64> {
+    use ErrorTask::Pruning as task;
+
+    let path = local::target_path(target).map_err(|source| NodeupError::Local { source, task })?;
+    dir_size(&path).map_err(|source| NodeupError::IO { source, task, path })
+}
+
+fn dir_size(path: &Path) -> io::Result<u64> {
+    let mut size = 0;
+    for entry in fs::read_dir(path)? {
+        let entry = entry?;
+        let metadata = fs::symlink_metadata(entry.path())?;
+        size += match metadata.is_dir() {
+            true => dir_size(&entry.path())?,
+            false => metadata.len(),
+        };
+    }
+
+    Ok(size)
+}
+
+/// Every version in the download directory, newest first. A download directory that hasn't been
+/// created yet has nothing installed
+pub fn installed_versions(path: &Path) -> NodeupResult<Vec<Target>> {
+    use ErrorTask::Installing as task;
+
+    let entries = match fs::read_dir(path) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(source) => {
+            return Err(NodeupError::IO {
+                source,
+                task,
+                path: path.to_path_buf(),
+            })
+        }
+    };
 
     let target_paths = entries.filter_map(|entry| match entry {
         Ok(entry) => Some(entry),
@@ -166,10 +506,46 @@ pub fn installed_versions(path: &Path) -> NodeupResult<Vec<Target>> {
         }
     });
 
-    Ok(targets.collect())
+    // Newest first, falling back to the os and arch so the order doesn't depend on the filesystem
+    let mut targets: Vec<Target> = targets.collect();
+    targets.sort_by(|a, b| {
+        b.version()
+            .cmp(a.version())
+            .then_with(|| a.os().to_string().cmp(&b.os().to_string()))
+            .then_with(|| a.arch().to_string().cmp(&b.arch().to_string()))
+    });
+    Ok(targets)
+}
+
+/// The installed versions built for the os and architecture of this machine. Versions for other
+/// platforms can end up in a download directory shared between machines
+pub fn installed_versions_for_host(path: &Path) -> NodeupResult<Vec<Target>> {
+    let (os, arch) = (OperatingSystem::default(), Architecture::host());
+    let mut targets = installed_versions(path)?;
+    targets.retain(|target| target.os() == os && target.arch() == arch);
+    Ok(targets)
+}
+
+/// The installed versions built for the os, for every architecture. ex/ to see what's installed
+/// in another machine's download directory
+pub fn installed_versions_for_os(path: &Path, os: OperatingSystem) -> NodeupResult<Vec<Target>> {
+    let mut targets = installed_versions(path)?;
+    targets.retain(|target| target.os() == os);
+    Ok(targets)
 }
 
+/// Runs an executable of the version active in the current directory. How it exited is dropped, use
+/// `execute_bin_spawn` to pass it on
 pub fn execute_bin<I: std::iter::Iterator<Item = String>>(bin: &str, args: I) -> NodeupResult<()> {
+    execute_bin_spawn(bin, args).map(|_| ())
+}
+
+/// Runs an executable of the version active in the current directory, returning how it exited so
+/// the shims can exit the same way and programs embedding nodeup can carry on afterwards
+pub fn execute_bin_spawn<I: std::iter::Iterator<Item = String>>(
+    bin: &str,
+    args: I,
+) -> NodeupResult<ExitStatus> {
     use ErrorTask::Executing as task;
 
     let cwd = env::current_dir().map_err(|source| NodeupError::IO {
@@ -179,47 +555,298 @@ pub fn execute_bin<I: std::iter::Iterator<Item = String>>(bin: &str, args: I) ->
     })?;
 
     let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
-    let active_target = config
-        .get_active_target(&cwd)
-        .map_err(|source| NodeupError::Config { source, task })?;
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    execute_bin_spawn_at(&config, &cwd, &download_dir, bin, args)
+}
 
-    match active_target {
-        Some(target) => {
-            let target_path = local::target_path(&target)
-                .map_err(|source| NodeupError::Local { source, task })?;
-            let bin_path = target_path.join("bin").join(bin);
+fn execute_bin_spawn_at<I: std::iter::Iterator<Item = String>>(
+    config: &Config,
+    cwd: &Path,
+    download_dir: &Path,
+    bin: &str,
+    args: I,
+) -> NodeupResult<ExitStatus> {
+    use ErrorTask::Executing as task;
 
-            if !bin_path.exists() {
-                return Err(NodeupError::VersionNotFound { task, target });
-            }
+    let (mut command, bin_path) = active_bin_command_at(config, cwd, download_dir, bin)?;
+    command
+        .args(args)
+        .status()
+        .map_err(|source| NodeupError::IO {
+            source,
+            task,
+            path: bin_path,
+        })
+}
 
-            Command::new(&bin_path)
-                .stdin(Stdio::inherit())
-                .stdout(Stdio::inherit())
-                .stderr(Stdio::inherit())
-                .args(args)
-                .output()
-                .map_err(|source| NodeupError::IO {
-                    source,
-                    task,
-                    path: bin_path,
-                })?;
+// The version can be pinned by a version file without ever being installed, so that's checked before
+// running anything rather than failing to find the executable
+fn active_bin_command_at(
+    config: &Config,
+    cwd: &Path,
+    download_dir: &Path,
+    bin: &str,
+) -> NodeupResult<(Command, PathBuf)> {
+    use ErrorTask::Executing as task;
 
-            Ok(())
+    let target = config
+        .get_active_target(cwd)
+        .map_err(|source| NodeupError::Config { source, task })?
+        .ok_or(NodeupError::NoVersionFound)?;
+
+    let bin_path = bin_path_at(download_dir, &target, bin, task)?;
+    let command = bin_command(&bin_path, config.global_prefix());
+    Ok((command, bin_path))
+}
+
+// npm and npx are pointed at the shared global prefix when one is set so global packages don't live
+// inside whichever version installed them
+fn bin_command(bin_path: &Path, global_prefix: Option<&Path>) -> Command {
+    let mut command = Command::new(bin_path);
+    let is_npm = bin_path
+        .file_stem()
+        .is_some_and(|name| name == NPM_EXECUTABLE || name == NPX_EXECUTABLE);
+    if let (true, Some(prefix)) = (is_npm, global_prefix) {
+        command.env(NPM_PREFIX_VAR, prefix);
+    }
+    command
+}
+
+/// The path to an executable of an installed version, ex/ .../node-v18.16.0-linux-x64/bin/npm
+pub fn bin_path(target: &Target, bin: &str) -> NodeupResult<PathBuf> {
+    use ErrorTask::Which as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    bin_path_at(&download_dir, target, bin, task)
+}
+
+fn bin_path_at(
+    download_dir: &Path,
+    target: &Target,
+    bin: &str,
+    task: ErrorTask,
+) -> NodeupResult<PathBuf> {
+    ensure_installed_at(download_dir, target, task)?;
+
+    let bin_dir = local::install_bin_dir(&download_dir.join(target.to_string()), target);
+    local::executable_names(target, bin)
+        .into_iter()
+        .map(|name| bin_dir.join(name))
+        .find(|bin_path| bin_path.exists())
+        .ok_or_else(|| NodeupError::BinNotFound {
+            bin: bin.to_string(),
+            target: target.clone(),
+        })
+}
+
+/// Whether an installed version has a node executable it can run. Versions are only recognized by
+/// their directory, so an install that was cut short otherwise goes unnoticed until node is run
+pub fn verify_install(target: &Target) -> NodeupResult<bool> {
+    use ErrorTask::CheckingInstalled as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    ensure_installed_at(&download_dir, target, task)?;
+    Ok(verify_install_at(&download_dir, target))
+}
+
+pub(crate) fn verify_install_at(download_dir: &Path, target: &Target) -> bool {
+    let bin_dir = local::install_bin_dir(&download_dir.join(target.to_string()), target);
+    local::executable_names(target, NODE_EXECUTABLE)
+        .iter()
+        .any(|name| is_executable(&bin_dir.join(name)))
+}
+
+#[cfg(unix)]
+fn is_executable(path: &Path) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    fs::metadata(path)
+        .is_ok_and(|metadata| metadata.is_file() && metadata.permissions().mode() & 0o111 != 0)
+}
+
+#[cfg(not(unix))]
+fn is_executable(path: &Path) -> bool {
+    path.is_file()
+}
+
+/// The bin directory of an installed version, for putting it on the PATH by hand
+pub fn version_bin_dir(target: &Target) -> NodeupResult<PathBuf> {
+    use ErrorTask::CheckingInstalled as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    version_bin_dir_at(&download_dir, target)
+}
+
+fn version_bin_dir_at(download_dir: &Path, target: &Target) -> NodeupResult<PathBuf> {
+    ensure_installed_at(download_dir, target, ErrorTask::CheckingInstalled)?;
+    Ok(local::install_bin_dir(
+        &download_dir.join(target.to_string()),
+        target,
+    ))
+}
+
+/// The names of the executables in the bin directory of an installed version, sorted by name
+pub fn version_bins(target: &Target) -> NodeupResult<Vec<String>> {
+    use ErrorTask::ListingBins as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    version_bins_at(&download_dir, target)
+}
+
+fn version_bins_at(download_dir: &Path, target: &Target) -> NodeupResult<Vec<String>> {
+    use ErrorTask::ListingBins as task;
+
+    ensure_installed_at(download_dir, target, task)?;
+
+    let bin_dir = local::install_bin_dir(&download_dir.join(target.to_string()), target);
+    let entries = fs::read_dir(&bin_dir).map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: bin_dir.clone(),
+    })?;
+    let mut bins = Vec::new();
+    for entry in entries {
+        let entry = entry.map_err(|source| NodeupError::IO {
+            source,
+            task,
+            path: bin_dir.clone(),
+        })?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        // The top of a Windows install also holds node_modules and docs, so only executables count
+        let is_bin = match target.os() {
+            OperatingSystem::Windows => name.ends_with(".exe") || name.ends_with(".cmd"),
+            _ => true,
+        };
+        if is_bin {
+            bins.push(name);
         }
-        None => Err(NodeupError::NoVersionFound),
+    }
+    bins.sort();
+    Ok(bins)
+}
+
+/// Runs an executable from the given version regardless of which version is active for the current
+/// directory, returning how it exited
+pub fn exec_with<I: std::iter::Iterator<Item = String>>(
+    target: Target,
+    bin: &str,
+    args: I,
+) -> NodeupResult<ExitStatus> {
+    use ErrorTask::Executing as task;
+
+    let download_dir =
+        local::download_dir().map_err(|source| NodeupError::Local { source, task })?;
+    exec_with_at(&download_dir, target, bin, args)
+}
+
+fn exec_with_at<I: std::iter::Iterator<Item = String>>(
+    download_dir: &Path,
+    target: Target,
+    bin: &str,
+    args: I,
+) -> NodeupResult<ExitStatus> {
+    use ErrorTask::Executing as task;
+
+    let bin_path = bin_path_at(download_dir, &target, bin, task)?;
+    Command::new(&bin_path)
+        .args(args)
+        .status()
+        .map_err(|source| NodeupError::IO {
+            source,
+            task,
+            path: bin_path,
+        })
+}
+
+/// Swaps the config with the backup made before its last update
+pub fn restore_config() -> NodeupResult<()> {
+    use ErrorTask::RestoringConfig as task;
+
+    Config::restore_backup().map_err(|source| NodeupError::Config { source, task })
+}
+
+/// Commands from the config that are run around `nodeup versions add`
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Hook {
+    PreInstall,
+    PostInstall,
+}
+
+impl fmt::Display for Hook {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Hook::PreInstall => write!(f, "pre_install"),
+            Hook::PostInstall => write!(f, "post_install"),
+        }
+    }
+}
+
+/// Runs the hook from the config if one is set. The target is available to the command as
+/// $NODEUP_TARGET and its version as $NODEUP_VERSION.
+pub fn run_install_hook(hook: Hook, target: &Target) -> NodeupResult<()> {
+    use ErrorTask::Hook as task;
+
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    let command = match hook {
+        Hook::PreInstall => config.pre_install(),
+        Hook::PostInstall => config.post_install(),
+    };
+
+    match command {
+        Some(command) => run_hook_command(hook, command, target),
+        None => Ok(()),
+    }
+}
+
+fn run_hook_command(hook: Hook, command: &str, target: &Target) -> NodeupResult<()> {
+    use ErrorTask::Hook as task;
+
+    let (shell, flag) = match cfg!(windows) {
+        true => ("cmd", "/C"),
+        false => ("sh", "-c"),
+    };
+    let status = Command::new(shell)
+        .arg(flag)
+        .arg(command)
+        .env("NODEUP_TARGET", target.to_string())
+        .env("NODEUP_VERSION", target.version().to_string())
+        .status()
+        .map_err(|source| NodeupError::IO {
+            source,
+            task,
+            path: PathBuf::from(shell),
+        })?;
+
+    match status.success() {
+        true => Ok(()),
+        false => Err(NodeupError::HookFailed {
+            hook,
+            command: command.to_string(),
+            status,
+        }),
     }
 }
 
-pub fn get_active_targets() -> NodeupResult<config::VersionIterator> {
+/// The default followed by the directory overrides sorted by directory
+pub fn get_active_targets() -> NodeupResult<Vec<Override>> {
     use ErrorTask::ActiveVersions as task;
 
     let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
-    Ok(config.active_versions())
+    Ok(config.override_list())
 }
 
-pub fn change_default_target(target: Target) -> NodeupResult<()> {
-    use ErrorTask::Override as task;
+/// Sets the default version. Fails if the target isn't installed unless `force` is set.
+pub fn change_default_target(target: Target, force: bool) -> NodeupResult<()> {
+    use ErrorTask::ChangingDefault as task;
+
+    if !force {
+        ensure_installed(&target, task)?;
+    }
 
     let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
     config
@@ -227,9 +854,15 @@ pub fn change_default_target(target: Target) -> NodeupResult<()> {
         .map_err(|source| NodeupError::Config { source, task })
 }
 
-pub fn override_cwd(target: Target) -> NodeupResult<()> {
+/// Sets the override for the current directory. Fails if the target isn't installed unless `force`
+/// is set.
+pub fn override_cwd(target: Target, force: bool) -> NodeupResult<()> {
     use ErrorTask::Override as task;
 
+    if !force {
+        ensure_installed(&target, task)?;
+    }
+
     let cwd = env::current_dir().map_err(|source| NodeupError::IO {
         source,
         task,
@@ -241,126 +874,505 @@ pub fn override_cwd(target: Target) -> NodeupResult<()> {
         .map_err(|source| NodeupError::Config { source, task })
 }
 
-pub fn remove_override() -> NodeupResult<()> {
-    use ErrorTask::RemovingOverride as task;
+/// Sets the prefix npm installs global packages into for every version, or turns it off when `None`
+pub fn set_global_prefix(prefix: Option<PathBuf>) -> NodeupResult<()> {
+    use ErrorTask::ChangingSetting as task;
 
-    let cwd = env::current_dir().map_err(|source| NodeupError::IO {
-        source,
-        task,
-        path: PathBuf::from("cwd"),
-    })?;
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    config
+        .set_global_prefix(prefix)
+        .map_err(|source| NodeupError::Config { source, task })
+}
+
+/// Sets the lts line the lts keyword resolves to, or goes back to the newest line when `None`
+pub fn set_preferred_lts(codename: Option<String>) -> NodeupResult<()> {
+    use ErrorTask::ChangingSetting as task;
 
     let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
     config
-        .remove_override(cwd)
+        .set_preferred_lts(codename)
         .map_err(|source| NodeupError::Config { source, task })
 }
 
-pub fn remove_default_override() -> NodeupResult<()> {
-    use ErrorTask::RemovingOverride as task;
+/// Sets the most downloads run at once when installing several versions, or goes back to the
+/// default when `None`
+pub fn set_max_concurrency(max_concurrency: Option<usize>) -> NodeupResult<()> {
+    use ErrorTask::ChangingSetting as task;
 
     let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
     config
-        .remove_override(PathBuf::from("default"))
+        .set_max_concurrency(max_concurrency)
         .map_err(|source| NodeupError::Config { source, task })
 }
 
-pub fn link_node_bins(links_path: &Path) -> NodeupResult<PathBuf> {
-    use ErrorTask::Linking as task;
+pub fn add_alias(name: &str, version: Version) -> NodeupResult<()> {
+    use ErrorTask::Alias as task;
 
-    let nodeup_path = std::env::current_exe().map_err(|source| NodeupError::IO {
-        source,
-        task,
-        path: PathBuf::from("Looking for current executable"),
-    })?;
+    if !is_valid_alias(name) {
+        return Err(NodeupError::InvalidAlias {
+            name: name.to_string(),
+        });
+    }
+
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    config
+        .set_alias(name.to_string(), version)
+        .map_err(|source| NodeupError::Config { source, task })
+}
 
-    link_bin(&nodeup_path, links_path, Path::new(NODE_EXECUTABLE))
-        .map_err(|source| NodeupError::Linking { source, task })?;
+pub fn remove_alias(name: &str) -> NodeupResult<Version> {
+    use ErrorTask::Alias as task;
 
-    link_bin(&nodeup_path, links_path, Path::new(NPM_EXECUTABLE))
-        .map_err(|source| NodeupError::Linking { source, task })?;
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    config
+        .remove_alias(name)
+        .map_err(|source| NodeupError::Config { source, task })?
+        .ok_or_else(|| NodeupError::AliasNotFound {
+            name: name.to_string(),
+        })
+}
 
-    link_bin(&nodeup_path, links_path, Path::new(NPX_EXECUTABLE))
-        .map_err(|source| NodeupError::Linking { source, task })?;
+/// Every alias sorted by name
+pub fn list_aliases() -> NodeupResult<Vec<(String, Version)>> {
+    use ErrorTask::Alias as task;
 
-    Ok(links_path.to_path_buf())
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    let mut aliases: Vec<_> = config
+        .aliases()
+        .map(|(name, version)| (name.clone(), version.clone()))
+        .collect();
+    aliases.sort();
+    Ok(aliases)
 }
 
-fn link_bin(actual: &Path, link_dir: &Path, link_name: &Path) -> Result<(), LinkingError> {
-    let full_link_path = link_dir.join(link_name);
-    match symlink(actual, &full_link_path) {
-        Ok(_) => Ok(()),
-        Err(e) => match e.kind() {
-            ErrorKind::AlreadyExists => {
-                let metadata =
-                    fs::symlink_metadata(&full_link_path).map_err(|source| LinkingError::IO {
-                        source,
-                        path: full_link_path.to_path_buf(),
-                    })?;
-                match metadata.file_type().is_symlink() {
-                    true => Ok(()),
-                    false => Err(LinkingError::AlreadyExists {
-                        path: full_link_path,
-                    }),
-                }
-            }
-            ErrorKind::NotFound => {
-                fs::create_dir_all(link_dir).map_err(|source| LinkingError::IO {
-                    source,
-                    path: link_dir.to_path_buf(),
-                })?;
-                symlink(actual, &full_link_path).map_err(|source| LinkingError::IO {
-                    source,
-                    path: full_link_path,
-                })?;
-                Ok(())
-            }
-            _ => Err(LinkingError::IO {
-                source: e,
-                path: full_link_path,
-            }),
-        },
+pub fn resolve_alias(name: &str) -> NodeupResult<Option<Version>> {
+    use ErrorTask::Alias as task;
+
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    Ok(config.alias(name).cloned())
+}
+
+/// Resolves a version as a user would type it to a target for this machine. Accepts exact versions,
+/// ex/ 18.17.0, ranges, ex/ 18 or ^16, aliases, and the keywords lts, lts/<codename>, and latest.
+/// lts is the newest of the preferred lts line when the config sets one, lts/* is always the newest
+/// lts. The registry is only reached for keywords and ranges
+pub fn resolve_version(input: &str) -> NodeupResult<Target> {
+    use ErrorTask::ResolvingVersion as task;
+
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    if let Some(version) = local_version(&config, input) {
+        return Ok(Target::from_version(version));
     }
+
+    let registry = Registry::new().map_err(|source| NodeupError::Registry { source, task })?;
+    resolve_version_at(&config, &registry, input).map(Target::from_version)
 }
 
-pub fn which(directory: &Path) -> NodeupResult<Target> {
-    use ErrorTask::Which as task;
+/// The newest version of the lts line preferred in the config, or the newest lts when none is
+pub fn latest_lts_with(registry: &Registry) -> NodeupResult<Version> {
+    use ErrorTask::ResolvingVersion as task;
 
     let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
-    let active_target = config
-        .get_active_target(directory)
-        .map_err(|source| NodeupError::Config { source, task })?;
+    latest_lts_at(&config, registry)
+}
 
-    match active_target {
-        Some(target) => Ok(target),
-        None => Err(NodeupError::NoVersionFound),
+fn latest_lts_at(config: &Config, registry: &Registry) -> NodeupResult<Version> {
+    use ErrorTask::ResolvingVersion as task;
+
+    match config.preferred_lts() {
+        Some(codename) => registry.get_lts_by_codename(codename),
+        None => registry.get_latest_lts(),
     }
+    .map_err(|source| NodeupError::Registry { source, task })
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use std::fs::File;
-    use target::OperatingSystem;
-    use tempfile::tempdir;
+/// Same as `resolve_version` but looks versions up in the given registry, ex/ one for a mirror
+pub fn resolve_version_with(registry: &Registry, input: &str) -> NodeupResult<Target> {
+    use ErrorTask::ResolvingVersion as task;
 
-    #[test]
-    fn linking() {
-        let fake_dir = tempdir().unwrap();
-        let linked_path = link_node_bins(fake_dir.path()).unwrap();
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    resolve_version_at(&config, registry, input).map(Target::from_version)
+}
+
+fn resolve_version_at(config: &Config, registry: &Registry, input: &str) -> NodeupResult<Version> {
+    use ErrorTask::ResolvingVersion as task;
+
+    let registry_error = |source| NodeupError::Registry { source, task };
+    match input {
+        "lts" => return latest_lts_at(config, registry),
+        "lts/*" => return registry.get_latest_lts().map_err(registry_error),
+        "latest" | "node" => return registry.get_latest().map_err(registry_error),
+        _ => (),
+    }
+
+    if let Some(codename) = input.strip_prefix("lts/") {
+        return registry
+            .get_lts_by_codename(codename)
+            .map_err(registry_error);
+    }
+
+    if let Some(version) = local_version(config, input) {
+        return Ok(version);
+    }
+
+    let req: VersionReq = input
+        .parse()
+        .map_err(|source| NodeupError::InvalidVersion {
+            input: input.to_string(),
+            source,
+        })?;
+    registry.resolve_version_req(&req).map_err(registry_error)
+}
+
+// Aliases and exact versions are the inputs that resolve without the registry
+fn local_version(config: &Config, input: &str) -> Option<Version> {
+    match config.alias(input) {
+        Some(aliased) => Some(aliased.clone()),
+        None => input.parse().ok(),
+    }
+}
+
+// Aliases are accepted anywhere a version is, so they can't look like a version, a range, or one of
+// the version keywords
+fn is_valid_alias(name: &str) -> bool {
+    let keyword = matches!(name, "lts" | "node" | "latest") || name.starts_with("lts/");
+    let version = name.parse::<Version>().is_ok() || name.parse::<VersionReq>().is_ok();
+    !name.is_empty() && !keyword && !version && !name.chars().any(char::is_whitespace)
+}
+
+pub fn remove_override() -> NodeupResult<()> {
+    use ErrorTask::RemovingOverride as task;
+
+    let cwd = env::current_dir().map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: PathBuf::from("cwd"),
+    })?;
+
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    config
+        .remove_override(cwd)
+        .map_err(|source| NodeupError::Config { source, task })
+}
+
+/// Removes overrides for directories that no longer exist, returning the directories. Nothing is
+/// removed for a dry run.
+pub fn prune_overrides(dry_run: bool) -> NodeupResult<Vec<PathBuf>> {
+    use ErrorTask::PruningOverrides as task;
+
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    match dry_run {
+        true => Ok(config.stale_overrides()),
+        false => config
+            .prune_overrides()
+            .map_err(|source| NodeupError::Config { source, task }),
+    }
+}
+
+/// Removes every override, keeping the default unless `keep_default` is false. Returns how many were
+/// removed.
+pub fn clear_overrides(keep_default: bool) -> NodeupResult<usize> {
+    use ErrorTask::ClearingOverrides as task;
+
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    config
+        .clear_overrides(keep_default)
+        .map_err(|source| NodeupError::Config { source, task })
+}
+
+pub fn remove_default_override() -> NodeupResult<()> {
+    use ErrorTask::RemovingOverride as task;
+
+    let mut config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    config
+        .remove_override(PathBuf::from("default"))
+        .map_err(|source| NodeupError::Config { source, task })
+}
+
+/// Links the executables back to nodeup. Files that aren't links to nodeup are only replaced when
+/// forced, in which case they're kept next to the link with a .bak extension without replacing
+/// earlier backups
+pub fn link_node_bins(
+    links_path: &Path,
+    executables: &[&str],
+    force: bool,
+) -> NodeupResult<PathBuf> {
+    use ErrorTask::Linking as task;
+
+    let nodeup_path = std::env::current_exe().map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: PathBuf::from("Looking for current executable"),
+    })?;
+
+    for executable in executables {
+        link_bin(&nodeup_path, links_path, Path::new(executable), force)
+            .map_err(|source| NodeupError::Linking { source, task })?;
+    }
+
+    Ok(links_path.to_path_buf())
+}
+
+/// Removes the links to nodeup from the links directory, returning the paths that were removed.
+/// Stale links, ex/ to a nodeup that's since moved, go too. Anything else is left alone.
+pub fn unlink_node_bins(links_path: &Path) -> NodeupResult<Vec<PathBuf>> {
+    use ErrorTask::Unlinking as task;
+
+    let nodeup_path = std::env::current_exe().map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: PathBuf::from("Looking for current executable"),
+    })?;
+
+    let mut removed = Vec::new();
+    for executable in SHIM_EXECUTABLES.iter() {
+        let link = link_path(links_path, Path::new(executable));
+        let linked =
+            links_to(&link, &nodeup_path).and_then(|current| Ok(current || is_stale_link(&link)?));
+        match linked {
+            Ok(true) => {
+                fs::remove_file(&link).map_err(|source| NodeupError::IO {
+                    source,
+                    task,
+                    path: link.clone(),
+                })?;
+                removed.push(link);
+            }
+            Ok(false) => warn!(
+                "Leaving {} in place since it isn't a link to nodeup",
+                link.display()
+            ),
+            Err(e) if e.kind() == ErrorKind::NotFound => (),
+            Err(source) => {
+                return Err(NodeupError::IO {
+                    source,
+                    task,
+                    path: link,
+                })
+            }
+        }
+    }
+
+    Ok(removed)
+}
+
+fn link_bin(
+    actual: &Path,
+    link_dir: &Path,
+    link_name: &Path,
+    force: bool,
+) -> Result<(), LinkingError> {
+    let full_link_path = link_path(link_dir, link_name);
+    match create_link(actual, &full_link_path) {
+        Ok(_) => Ok(()),
+        Err(e) => match e.kind() {
+            ErrorKind::AlreadyExists => {
+                let io_error = |source| LinkingError::IO {
+                    source,
+                    path: full_link_path.to_path_buf(),
+                };
+                if links_to(&full_link_path, actual).map_err(io_error)? {
+                    return Ok(());
+                }
+                // Links left behind by a nodeup executable that has since moved are replaced
+                if is_stale_link(&full_link_path).map_err(io_error)? {
+                    fs::remove_file(&full_link_path).map_err(io_error)?;
+                    return create_link(actual, &full_link_path).map_err(io_error);
+                }
+
+                if !force {
+                    return Err(LinkingError::AlreadyExists {
+                        path: full_link_path,
+                    });
+                }
+                let backup = backup_path(&full_link_path);
+                fs::rename(&full_link_path, &backup).map_err(|source| LinkingError::IO {
+                    source,
+                    path: backup,
+                })?;
+                create_link(actual, &full_link_path).map_err(io_error)
+            }
+            ErrorKind::NotFound => {
+                fs::create_dir_all(link_dir).map_err(|source| LinkingError::IO {
+                    source,
+                    path: link_dir.to_path_buf(),
+                })?;
+                create_link(actual, &full_link_path).map_err(|source| LinkingError::IO {
+                    source,
+                    path: full_link_path,
+                })?;
+                Ok(())
+            }
+            _ => Err(LinkingError::IO {
+                source: e,
+                path: full_link_path,
+            }),
+        },
+    }
+}
+
+// ex/ node -> node.bak, or node.bak.2 when that's taken
+fn backup_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".bak");
+    let backup = path.with_file_name(&file_name);
+    // An earlier backup is never overwritten, later ones are numbered instead
+    (1..)
+        .map(|n| match n {
+            1 => backup.clone(),
+            n => {
+                let mut numbered = file_name.clone();
+                numbered.push(format!(".{}", n));
+                path.with_file_name(numbered)
+            }
+        })
+        .find(|candidate| candidate.symlink_metadata().is_err())
+        .expect("There's always an unused backup name")
+}
+
+/// The location of the link for an executable in the links directory. Windows will only run files
+/// with an executable extension so the link is given one there.
+pub(crate) fn link_path(link_dir: &Path, link_name: &Path) -> PathBuf {
+    link_dir
+        .join(link_name)
+        .with_extension(env::consts::EXE_EXTENSION)
+}
+
+#[cfg(unix)]
+fn create_link(actual: &Path, link: &Path) -> io::Result<()> {
+    symlink(actual, link)
+}
+
+// Symlinks require elevated privileges on windows so a hard link to nodeup is used as the shim
+// instead. nodeup dispatches on the name it was invoked with, so the hard link behaves the same way
+// a symlink does. Hard links can't span volumes, in which case nodeup is copied.
+#[cfg(windows)]
+fn create_link(actual: &Path, link: &Path) -> io::Result<()> {
+    match fs::hard_link(actual, link) {
+        Err(e) if e.kind() != ErrorKind::AlreadyExists && e.kind() != ErrorKind::NotFound => {
+            fs::copy(actual, link).map(|_| ())
+        }
+        result => result,
+    }
+}
+
+/// Checks whether the file at `link` is a link back to nodeup
+#[cfg(unix)]
+pub(crate) fn is_link(link: &Path) -> io::Result<bool> {
+    fs::symlink_metadata(link).map(|metadata| metadata.file_type().is_symlink())
+}
+
+/// Checks whether the file at `link` is a link back to nodeup. The shims are hard links, so besides
+/// the running nodeup this counts any file that shares its data with other names the way the shims
+/// do, ex/ shims left from the nodeup a `nodeup self update` replaced
+#[cfg(windows)]
+pub(crate) fn is_link(link: &Path) -> io::Result<bool> {
+    Ok(file_information(link)?.number_of_links() > 1 || links_to(link, &env::current_exe()?)?)
+}
+
+#[cfg(windows)]
+fn file_information(path: &Path) -> io::Result<winapi_util::file::Information> {
+    let handle = winapi_util::Handle::from_path_any(path)?;
+    winapi_util::file::information(&handle)
+}
+
+/// Checks whether the file at `link` is a symlink that resolves to `actual`
+#[cfg(unix)]
+pub(crate) fn links_to(link: &Path, actual: &Path) -> io::Result<bool> {
+    if !is_link(link)? {
+        return Ok(false);
+    }
+
+    let destination = fs::read_link(link)?;
+    let destination = match link.parent() {
+        Some(parent) => parent.join(destination),
+        None => destination,
+    };
+    match (fs::canonicalize(destination), fs::canonicalize(actual)) {
+        (Ok(destination), Ok(actual)) => Ok(destination == actual),
+        // A dangling link can't be pointing at the running executable
+        _ => Ok(false),
+    }
+}
+
+/// Checks whether the file at `link` is a hard link to `actual`. Shims copied because nodeup is on
+/// another volume are compared by content, which is only read when the sizes match
+#[cfg(windows)]
+pub(crate) fn links_to(link: &Path, actual: &Path) -> io::Result<bool> {
+    let (link_info, actual_info) = (file_information(link)?, file_information(actual)?);
+    if link_info.volume_serial_number() == actual_info.volume_serial_number()
+        && link_info.file_index() == actual_info.file_index()
+    {
+        return Ok(true);
+    }
+    if link_info.number_of_links() > 1 || link_info.file_size() != actual_info.file_size() {
+        return Ok(false);
+    }
+    Ok(fs::read(link)? == fs::read(actual)?)
+}
+
+/// Checks whether the file at `link` is a symlink that's dangling or points at another nodeup
+/// executable
+#[cfg(unix)]
+pub(crate) fn is_stale_link(link: &Path) -> io::Result<bool> {
+    if !is_link(link)? {
+        return Ok(false);
+    }
+
+    let destination = fs::read_link(link)?;
+    let dangling = !link.exists();
+    Ok(dangling || destination.file_stem() == Some(std::ffi::OsStr::new("nodeup")))
+}
+
+/// Checks whether the file at `link` is a hard link to a nodeup executable other than this one
+#[cfg(windows)]
+pub(crate) fn is_stale_link(link: &Path) -> io::Result<bool> {
+    Ok(is_link(link)? && !links_to(link, &env::current_exe()?)?)
+}
+
+pub fn which(directory: &Path) -> NodeupResult<Target> {
+    resolve_active(directory).map(|resolution| resolution.target)
+}
+
+/// The active target for the directory along with where it was set
+pub fn resolve_active(directory: &Path) -> NodeupResult<Resolution> {
+    use ErrorTask::Which as task;
+
+    let config = Config::fetch().map_err(|source| NodeupError::Config { source, task })?;
+    let resolution = config
+        .resolve_active(directory)
+        .map_err(|source| NodeupError::Config { source, task })?;
+
+    match resolution {
+        Some(resolution) => Ok(resolution),
+        None => Err(NodeupError::NoVersionFound),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs::File;
+    use target::OperatingSystem;
+    use tempfile::tempdir;
+
+    #[test]
+    fn linking() {
+        let fake_dir = tempdir().unwrap();
+        let linked_path = link_node_bins(fake_dir.path(), &DEFAULT_EXECUTABLES, false).unwrap();
         assert_eq!(linked_path, fake_dir.path());
 
         let link_entries: Vec<_> = fs::read_dir(fake_dir.path())
             .unwrap()
             .map(|e| e.unwrap())
             .collect();
-        assert_eq!(link_entries.len(), 3);
+        assert_eq!(link_entries.len(), 4);
 
         let are_links: Vec<bool> = link_entries
             .iter()
             .map(|e| e.metadata().unwrap().file_type().is_symlink())
             .collect();
-        let expected = vec![true, true, true];
+        let expected = vec![true, true, true, true];
         assert_eq!(are_links, expected);
     }
 
@@ -373,18 +1385,99 @@ mod tests {
 
         symlink(&nodeup_path, node_path).unwrap();
 
-        let linked_path = link_node_bins(fake_dir.path()).unwrap();
+        let linked_path = link_node_bins(fake_dir.path(), &DEFAULT_EXECUTABLES, false).unwrap();
         assert_eq!(linked_path, fake_dir.path());
     }
 
+    #[test]
+    fn stale_link_is_replaced() {
+        let fake_dir = tempdir().unwrap();
+        let node_path = fake_dir.path().join(NODE_EXECUTABLE);
+        symlink(fake_dir.path().join("old-nodeup"), &node_path).unwrap();
+
+        link_node_bins(fake_dir.path(), &DEFAULT_EXECUTABLES, false).unwrap();
+
+        let nodeup_path = std::env::current_exe().unwrap();
+        assert_eq!(fs::read_link(&node_path).unwrap(), nodeup_path);
+    }
+
+    #[test]
+    fn moved_nodeup_link_is_replaced() {
+        let fake_dir = tempdir().unwrap();
+        let old_nodeup = fake_dir.path().join("old").join("nodeup");
+        fs::create_dir(old_nodeup.parent().unwrap()).unwrap();
+        File::create(&old_nodeup).unwrap();
+        let node_path = fake_dir.path().join(NODE_EXECUTABLE);
+        symlink(&old_nodeup, &node_path).unwrap();
+
+        link_node_bins(fake_dir.path(), &[NODE_EXECUTABLE], false).unwrap();
+
+        let nodeup_path = std::env::current_exe().unwrap();
+        assert_eq!(fs::read_link(&node_path).unwrap(), nodeup_path);
+    }
+
+    #[test]
+    fn other_links_need_force() {
+        let fake_dir = tempdir().unwrap();
+        let system_node = fake_dir.path().join("system-node");
+        File::create(&system_node).unwrap();
+        let node_path = fake_dir.path().join(NODE_EXECUTABLE);
+        symlink(&system_node, &node_path).unwrap();
+
+        let result = link_node_bins(fake_dir.path(), &[NODE_EXECUTABLE], false);
+        assert!(matches!(
+            result,
+            Err(NodeupError::Linking {
+                source: LinkingError::AlreadyExists { .. },
+                ..
+            })
+        ));
+        assert_eq!(fs::read_link(&node_path).unwrap(), system_node);
+
+        link_node_bins(fake_dir.path(), &[NODE_EXECUTABLE], true).unwrap();
+        let backup = fake_dir.path().join("node.bak");
+        assert_eq!(fs::read_link(backup).unwrap(), system_node);
+    }
+
     #[test]
     fn node_already_installed() {
         let fake_dir = tempdir().unwrap();
         let already_installed_node = fake_dir.path().join(NODE_EXECUTABLE);
         File::create(already_installed_node).unwrap();
 
-        let result = link_node_bins(fake_dir.path());
+        let result = link_node_bins(fake_dir.path(), &DEFAULT_EXECUTABLES, false);
         assert!(result.is_err());
+        assert!(!is_link(&fake_dir.path().join(NODE_EXECUTABLE)).unwrap());
+    }
+
+    #[test]
+    fn force_link_over_installed_node() {
+        let fake_dir = tempdir().unwrap();
+        let already_installed_node = fake_dir.path().join(NODE_EXECUTABLE);
+        fs::write(&already_installed_node, "system node").unwrap();
+
+        link_node_bins(fake_dir.path(), &DEFAULT_EXECUTABLES, true).unwrap();
+
+        assert!(is_link(&already_installed_node).unwrap());
+        let backup = fake_dir.path().join("node.bak");
+        assert_eq!(fs::read_to_string(backup).unwrap(), "system node");
+    }
+
+    #[test]
+    fn force_link_keeps_earlier_backups() {
+        let fake_dir = tempdir().unwrap();
+        let node = fake_dir.path().join(NODE_EXECUTABLE);
+        fs::write(fake_dir.path().join("node.bak"), "first node").unwrap();
+        fs::write(fake_dir.path().join("node.bak.2"), "second node").unwrap();
+        fs::write(&node, "third node").unwrap();
+
+        link_node_bins(fake_dir.path(), &[NODE_EXECUTABLE], true).unwrap();
+
+        assert!(is_link(&node).unwrap());
+        let read = |name| fs::read_to_string(fake_dir.path().join(name)).unwrap();
+        assert_eq!(read("node.bak"), "first node");
+        assert_eq!(read("node.bak.2"), "second node");
+        assert_eq!(read("node.bak.3"), "third node");
     }
 
     #[test]
@@ -392,38 +1485,720 @@ mod tests {
         let fake_dir = tempdir().unwrap();
         let nonexistent_dir = fake_dir.path().join("fake-dir");
 
-        let path = link_node_bins(&nonexistent_dir).unwrap();
+        let path = link_node_bins(&nonexistent_dir, &DEFAULT_EXECUTABLES, false).unwrap();
         assert_eq!(path, nonexistent_dir);
 
         let link_entries: Vec<_> = fs::read_dir(nonexistent_dir)
             .unwrap()
             .map(|e| e.unwrap())
             .collect();
-        assert_eq!(link_entries.len(), 3);
+        assert_eq!(link_entries.len(), 4);
 
         let are_links: Vec<bool> = link_entries
             .iter()
             .map(|e| e.metadata().unwrap().file_type().is_symlink())
             .collect();
-        let expected = vec![true, true, true];
+        let expected = vec![true, true, true, true];
         assert_eq!(are_links, expected);
     }
 
+    #[test]
+    fn link_custom_executables() {
+        let fake_dir = tempdir().unwrap();
+        let executables = [NODE_EXECUTABLE, YARN_EXECUTABLE, PNPM_EXECUTABLE];
+        link_node_bins(fake_dir.path(), &executables, false).unwrap();
+
+        let mut linked: Vec<_> = fs::read_dir(fake_dir.path())
+            .unwrap()
+            .map(|e| e.unwrap())
+            .filter(|e| e.metadata().unwrap().file_type().is_symlink())
+            .map(|e| e.file_name())
+            .collect();
+        linked.sort();
+        assert_eq!(linked, vec!["node", "pnpm", "yarn"]);
+    }
+
+    #[test]
+    fn unlink_removes_nodeup_links() {
+        let fake_dir = tempdir().unwrap();
+        link_node_bins(fake_dir.path(), &SHIM_EXECUTABLES, false).unwrap();
+
+        let removed = unlink_node_bins(fake_dir.path()).unwrap();
+        assert_eq!(removed.len(), SHIM_EXECUTABLES.len());
+        assert_eq!(fs::read_dir(fake_dir.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn unlink_leaves_other_files() {
+        let fake_dir = tempdir().unwrap();
+        let other_binary = fake_dir.path().join("other-node");
+        File::create(&other_binary).unwrap();
+
+        let real_node = fake_dir.path().join(NODE_EXECUTABLE);
+        File::create(&real_node).unwrap();
+        let other_link = fake_dir.path().join(NPM_EXECUTABLE);
+        symlink(&other_binary, &other_link).unwrap();
+        let nodeup_link = fake_dir.path().join(NPX_EXECUTABLE);
+        symlink(std::env::current_exe().unwrap(), &nodeup_link).unwrap();
+        let stale_link = fake_dir.path().join(COREPACK_EXECUTABLE);
+        symlink(fake_dir.path().join("old/bin/nodeup"), &stale_link).unwrap();
+
+        let removed = unlink_node_bins(fake_dir.path()).unwrap();
+        assert_eq!(removed, vec![nodeup_link, stale_link]);
+        assert!(real_node.is_file());
+        assert!(is_link(&other_link).unwrap());
+    }
+
+    #[test]
+    fn alias_names() {
+        assert!(is_valid_alias("work"));
+        assert!(is_valid_alias("legacy-api"));
+        assert!(!is_valid_alias("lts"));
+        assert!(!is_valid_alias("lts/gallium"));
+        assert!(!is_valid_alias("18"));
+        assert!(!is_valid_alias("v18.16.0"));
+        assert!(!is_valid_alias("^16.1"));
+        assert!(!is_valid_alias("my alias"));
+        assert!(!is_valid_alias(""));
+    }
+
+    #[test]
+    fn remove_uninstalled_version() {
+        let fake_dir = tempdir().unwrap();
+        let installed: Target = "node-v14.0.0-linux-x64".parse().unwrap();
+        fs::create_dir(fake_dir.path().join(installed.to_string())).unwrap();
+
+        let mut config = Config::load_from(&fake_dir.path().join("settings.toml")).unwrap();
+
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        match remove_node_at(fake_dir.path(), &mut config, target.clone(), false) {
+            Err(NodeupError::NotInstalled {
+                target: missing,
+                installed: found,
+                ..
+            }) => {
+                assert_eq!(missing, target);
+                assert_eq!(found, vec![installed]);
+            }
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn remove_default_version() {
+        let fake_dir = tempdir().unwrap();
+        let config_file = fake_dir.path().join("settings.toml");
+        let mut config = Config::load_from(&config_file).unwrap();
+        let target: Target = "node-v18.17.0-linux-x64".parse().unwrap();
+        fs::create_dir(fake_dir.path().join(target.to_string())).unwrap();
+        config
+            .set_override(target.clone(), PathBuf::from("default"))
+            .unwrap();
+
+        match remove_node_at(fake_dir.path(), &mut config, target.clone(), false) {
+            Err(NodeupError::VersionInUse { users, .. }) => {
+                assert_eq!(users, vec![OverrideScope::Default])
+            }
+            other => panic!("Expected the version to be in use, got: {:?}", other),
+        }
+        assert!(is_installed_at(fake_dir.path(), &target));
+
+        let removed = remove_node_at(fake_dir.path(), &mut config, target.clone(), true).unwrap();
+        assert_eq!(removed, vec![OverrideScope::Default]);
+        assert!(!is_installed_at(fake_dir.path(), &target));
+        let reloaded = Config::load_from(&config_file).unwrap();
+        assert!(reloaded.override_list().is_empty());
+    }
+
+    #[test]
+    fn reinstall_replaces_partial_install() {
+        let fake_dir = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        let target_path = fake_dir.path().join(target.to_string());
+        fs::create_dir_all(target_path.join("lib")).unwrap();
+        File::create(target_path.join("lib").join("partial")).unwrap();
+
+        reinstall_at(fake_dir.path(), target.clone(), |dir, target| {
+            let bin = dir.join(target.to_string()).join("bin");
+            fs::create_dir_all(&bin).unwrap();
+            File::create(bin.join(NODE_EXECUTABLE)).unwrap();
+            Ok(())
+        })
+        .unwrap();
+
+        assert!(!target_path.join("lib").exists());
+        assert!(target_path.join("bin").join(NODE_EXECUTABLE).exists());
+    }
+
+    #[test]
+    fn reinstall_missing_version_installs_it() {
+        let fake_dir = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        let mut installed = false;
+        reinstall_at(fake_dir.path(), target.clone(), |dir, target| {
+            installed = true;
+            fs::create_dir_all(dir.join(target.to_string())).unwrap();
+            Ok(())
+        })
+        .unwrap();
+        assert!(installed);
+        assert!(fake_dir.path().join(target.to_string()).is_dir());
+    }
+
+    #[test]
+    fn failed_reinstall_keeps_old_install() {
+        let fake_dir = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        let node = fake_dir
+            .path()
+            .join(target.to_string())
+            .join("bin")
+            .join(NODE_EXECUTABLE);
+        fs::create_dir_all(node.parent().unwrap()).unwrap();
+        File::create(&node).unwrap();
+
+        let result = reinstall_at(fake_dir.path(), target, |_, _| {
+            Err(RegistryError::Offline {
+                url: String::from("https://nodejs.org/dist"),
+            })
+        });
+        assert!(matches!(result, Err(NodeupError::Registry { .. })));
+        assert!(node.exists());
+        assert_eq!(fs::read_dir(fake_dir.path()).unwrap().count(), 1);
+    }
+
+    #[test]
+    fn installed_targets_for_host() {
+        let fake_dir = tempdir().unwrap();
+        let host = Target::from_version("v18.17.0".parse().unwrap());
+        let other_arch = match Architecture::default() {
+            Architecture::X64 => "arm64",
+            _ => "x64",
+        };
+        let other_os = match OperatingSystem::default() {
+            OperatingSystem::Linux => "darwin",
+            _ => "linux",
+        };
+        let names = [
+            host.to_string(),
+            format!("node-v18.17.0-{}-{}", host.os(), other_arch),
+            format!("node-v16.20.1-{}-{}", other_os, host.arch()),
+        ];
+        for name in names.iter() {
+            fs::create_dir(fake_dir.path().join(name)).unwrap();
+        }
+
+        assert_eq!(installed_versions(fake_dir.path()).unwrap().len(), 3);
+        assert_eq!(
+            installed_versions_for_host(fake_dir.path()).unwrap(),
+            vec![host]
+        );
+    }
+
+    #[test]
+    fn missing_download_dir_has_nothing_installed() {
+        let fake_dir = tempdir().unwrap();
+        let download_dir = fake_dir.path().join("never-created");
+
+        assert_eq!(installed_versions(&download_dir).unwrap(), Vec::new());
+        assert_eq!(
+            installed_versions_for_host(&download_dir).unwrap(),
+            Vec::new()
+        );
+    }
+
     #[test]
     fn get_installed_targets() {
+        for os in OperatingSystem::ALL.iter().copied() {
+            let fake_dir = tempdir().unwrap();
+            let names = [
+                format!("node-v10.2.3-{}-x64", os),
+                format!("node-v14.0.0-{}-x64", os),
+                format!("node-v12.18.3-{}-arm64", os),
+                format!("node-v14.0.0-rc.1-{}-x64", os),
+            ];
+            for name in names.iter() {
+                File::create(fake_dir.path().join(name)).unwrap();
+            }
+
+            let targets = installed_versions(fake_dir.path()).unwrap();
+            let oldest = Target::new(
+                os,
+                Version {
+                    major: 10,
+                    minor: 2,
+                    patch: 3,
+                    prerelease: None,
+                },
+            );
+            assert_eq!(targets.last(), Some(&oldest));
+            assert!(targets.iter().all(|target| target.os() == os));
+
+            let targets: Vec<String> = targets.iter().map(|t| t.to_string()).collect();
+            assert_eq!(
+                targets,
+                vec![
+                    format!("node-v14.0.0-{}-x64", os),
+                    format!("node-v14.0.0-rc.1-{}-x64", os),
+                    format!("node-v12.18.3-{}-arm64", os),
+                    format!("node-v10.2.3-{}-x64", os),
+                ]
+            );
+        }
+    }
+
+    #[test]
+    fn installed_targets_across_oses() {
         let fake_dir = tempdir().unwrap();
-        let fake_target = Target::new(
-            OperatingSystem::Linux,
-            Version {
-                major: 10,
-                minor: 2,
-                patch: 3,
-            },
+        let names = [
+            "node-v10.2.3-linux-x64",
+            "node-v12.18.3-win-x64",
+            "node-v12.18.3-darwin-x64",
+            "node-v12.18.3-freebsd-x64",
+        ];
+        for name in names.iter() {
+            File::create(fake_dir.path().join(name)).unwrap();
+        }
+
+        let targets: Vec<String> = installed_versions(fake_dir.path())
+            .unwrap()
+            .iter()
+            .map(|t| t.to_string())
+            .collect();
+        assert_eq!(
+            targets,
+            vec![
+                "node-v12.18.3-darwin-x64",
+                "node-v12.18.3-freebsd-x64",
+                "node-v12.18.3-win-x64",
+                "node-v10.2.3-linux-x64",
+            ]
         );
-        let fake_target_path = fake_dir.path().join(format!("{}", fake_target));
-        File::create(&fake_target_path).unwrap();
+    }
+
+    #[test]
+    fn installed_targets_for_os() {
+        let fake_dir = tempdir().unwrap();
+        let names = [
+            "node-v18.17.0-linux-x64",
+            "node-v18.17.0-darwin-arm64",
+            "node-v16.20.0-darwin-x64",
+            "node-v16.20.0-win-x64",
+        ];
+        for name in names.iter() {
+            fs::create_dir(fake_dir.path().join(name)).unwrap();
+        }
 
-        let targets = installed_versions(&fake_dir.path()).unwrap();
-        assert_eq!(targets, vec![fake_target]);
+        let targets: Vec<String> =
+            installed_versions_for_os(fake_dir.path(), OperatingSystem::Darwin)
+                .unwrap()
+                .iter()
+                .map(|t| t.to_string())
+                .collect();
+        assert_eq!(
+            targets,
+            vec!["node-v18.17.0-darwin-arm64", "node-v16.20.0-darwin-x64"]
+        );
+        assert!(
+            installed_versions_for_os(fake_dir.path(), OperatingSystem::FreeBSD)
+                .unwrap()
+                .is_empty()
+        );
+    }
+
+    #[test]
+    fn installed_target() {
+        let download_dir = tempdir().unwrap();
+        let target = Target::from_version("v16.1.0".parse().unwrap());
+        fs::create_dir_all(download_dir.path().join(target.to_string()).join("bin")).unwrap();
+
+        assert!(is_installed_at(download_dir.path(), &target));
+    }
+
+    #[test]
+    fn uninstalled_target() {
+        let download_dir = tempdir().unwrap();
+        let target = Target::from_version("v16.1.0".parse().unwrap());
+        let other = Target::from_version("v18.17.0".parse().unwrap());
+        fs::create_dir_all(download_dir.path().join(other.to_string())).unwrap();
+        File::create(download_dir.path().join(target.to_string())).unwrap();
+
+        assert!(!is_installed_at(download_dir.path(), &target));
+        assert!(!is_installed_at(
+            &download_dir.path().join("missing"),
+            &target
+        ));
+    }
+
+    #[test]
+    fn override_requires_installed_target() {
+        let download_dir = tempdir().unwrap();
+        let installed = Target::from_version("v16.1.0".parse().unwrap());
+        let missing = Target::from_version("v18.17.0".parse().unwrap());
+        fs::create_dir_all(download_dir.path().join(installed.to_string())).unwrap();
+
+        ensure_installed_at(download_dir.path(), &installed, ErrorTask::Override).unwrap();
+        match ensure_installed_at(download_dir.path(), &missing, ErrorTask::ChangingDefault) {
+            Err(error @ NodeupError::NotInstalled { .. }) => {
+                assert!(error.to_string().contains("pass --force"), "{}", error)
+            }
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn exit_codes() {
+        let task = ErrorTask::Override;
+        let target = Target::from_version("v16.1.0".parse().unwrap());
+        let io_error = || io::Error::from(ErrorKind::PermissionDenied);
+        let local_error = || LocalError::NotFound("missing");
+
+        let cases = vec![
+            (
+                NodeupError::Config {
+                    source: ConfigError::NoBackup {
+                        path: PathBuf::from("settings.toml"),
+                    },
+                    task,
+                },
+                2,
+            ),
+            (
+                NodeupError::Config {
+                    source: ConfigError::Local(local_error()),
+                    task,
+                },
+                3,
+            ),
+            (
+                NodeupError::Config {
+                    source: ConfigError::Registry(RegistryError::Local(local_error())),
+                    task,
+                },
+                4,
+            ),
+            (
+                NodeupError::Local {
+                    source: local_error(),
+                    task,
+                },
+                3,
+            ),
+            (
+                NodeupError::IO {
+                    source: io_error(),
+                    task,
+                    path: PathBuf::from("/"),
+                },
+                3,
+            ),
+            (
+                NodeupError::Linking {
+                    source: LinkingError::AlreadyExists {
+                        path: PathBuf::from("/"),
+                    },
+                    task,
+                },
+                3,
+            ),
+            (
+                NodeupError::Registry {
+                    source: RegistryError::Timeout {
+                        url: String::from("https://nodejs.org/dist/index.json"),
+                    },
+                    task,
+                },
+                4,
+            ),
+            (NodeupError::NoVersionFound, 5),
+            (
+                NodeupError::NotInstalled {
+                    target,
+                    installed: Vec::new(),
+                    task,
+                },
+                6,
+            ),
+            (
+                NodeupError::InvalidAlias {
+                    name: String::from("lts"),
+                },
+                1,
+            ),
+            (
+                NodeupError::AliasNotFound {
+                    name: String::from("work"),
+                },
+                1,
+            ),
+        ];
+
+        for (error, code) in cases {
+            assert_eq!(error.exit_code(), code, "{:?}", error);
+        }
+    }
+
+    #[test]
+    fn exec_with_uninstalled_version() {
+        let download_dir = tempdir().unwrap();
+        let installed = Target::from_version("v18.17.0".parse().unwrap());
+        let missing = Target::from_version("v16.20.0".parse().unwrap());
+        fs::create_dir_all(download_dir.path().join(installed.to_string())).unwrap();
+
+        let args = vec![String::from("--version")].into_iter();
+        match exec_with_at(download_dir.path(), missing.clone(), NODE_EXECUTABLE, args) {
+            Err(NodeupError::NotInstalled {
+                target,
+                installed: found,
+                ..
+            }) => {
+                assert_eq!(target, missing);
+                assert_eq!(found, vec![installed]);
+            }
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn exec_with_exit_status() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let download_dir = tempdir().unwrap();
+        let target = Target::from_version("v16.20.0".parse().unwrap());
+        let bin_dir = download_dir.path().join(target.to_string()).join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let node = bin_dir.join(NODE_EXECUTABLE);
+        fs::write(&node, "#!/bin/sh\nexit 4\n").unwrap();
+        fs::set_permissions(&node, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let status = exec_with_at(
+            download_dir.path(),
+            target,
+            NODE_EXECUTABLE,
+            std::iter::empty(),
+        )
+        .unwrap();
+        assert_eq!(status.code(), Some(4));
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn spawned_bin_exit_status() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let project = tempdir().unwrap();
+        fs::write(project.path().join(".nvmrc"), "v16.20.0\n").unwrap();
+        let config = Config::load_from(&project.path().join("settings.toml")).unwrap();
+        let download_dir = tempdir().unwrap();
+        let target = Target::from_version("v16.20.0".parse().unwrap());
+        let bin_dir = download_dir.path().join(target.to_string()).join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        let node = bin_dir.join(NODE_EXECUTABLE);
+        fs::write(&node, "#!/bin/sh\nexit \"$1\"\n").unwrap();
+        fs::set_permissions(&node, fs::Permissions::from_mode(0o755)).unwrap();
+
+        let spawn = |code: &str| {
+            let args = vec![code.to_string()].into_iter();
+            execute_bin_spawn_at(
+                &config,
+                project.path(),
+                download_dir.path(),
+                NODE_EXECUTABLE,
+                args,
+            )
+            .unwrap()
+        };
+        assert_eq!(spawn("3").code(), Some(3));
+        assert!(spawn("0").success());
+    }
+
+    #[test]
+    fn execute_pinned_but_uninstalled_version() {
+        let project = tempdir().unwrap();
+        fs::write(project.path().join(".nvmrc"), "v16.20.0\n").unwrap();
+        let config = Config::load_from(&project.path().join("settings.toml")).unwrap();
+        let download_dir = tempdir().unwrap();
+
+        let args = vec![String::from("--version")].into_iter();
+        let error = execute_bin_spawn_at(
+            &config,
+            project.path(),
+            download_dir.path(),
+            NODE_EXECUTABLE,
+            args,
+        )
+        .unwrap_err();
+        match &error {
+            NodeupError::NotInstalled {
+                target, installed, ..
+            } => {
+                assert_eq!(target.version(), &"v16.20.0".parse().unwrap());
+                assert!(installed.is_empty());
+            }
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+        assert!(error.to_string().contains("nodeup versions add v16.20.0"));
+    }
+
+    #[test]
+    fn npm_uses_global_prefix() {
+        let prefix = Path::new("/home/me/.npm-global");
+        let bin_dir = Path::new("/nodeup/node-v18.17.0-linux-x64/bin");
+        let prefix_var = |command: &Command| {
+            command
+                .get_envs()
+                .find(|(name, _)| *name == NPM_PREFIX_VAR)
+                .and_then(|(_, value)| value.map(PathBuf::from))
+        };
+
+        let npm = bin_command(&bin_dir.join(NPM_EXECUTABLE), Some(prefix));
+        assert_eq!(prefix_var(&npm), Some(prefix.to_path_buf()));
+        let npx = bin_command(&bin_dir.join(NPX_EXECUTABLE), Some(prefix));
+        assert_eq!(prefix_var(&npx), Some(prefix.to_path_buf()));
+
+        let node = bin_command(&bin_dir.join(NODE_EXECUTABLE), Some(prefix));
+        assert_eq!(prefix_var(&node), None);
+        let unset = bin_command(&bin_dir.join(NPM_EXECUTABLE), None);
+        assert_eq!(prefix_var(&unset), None);
+    }
+
+    #[test]
+    fn path_to_bin() {
+        let download_dir = tempdir().unwrap();
+        let installed = Target::from_version("v18.16.0".parse().unwrap());
+        let bin_dir = download_dir.path().join(installed.to_string()).join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        File::create(bin_dir.join(NPM_EXECUTABLE)).unwrap();
+
+        let npm = bin_path_at(
+            download_dir.path(),
+            &installed,
+            NPM_EXECUTABLE,
+            ErrorTask::Which,
+        )
+        .unwrap();
+        assert_eq!(npm, bin_dir.join(NPM_EXECUTABLE));
+
+        match bin_path_at(
+            download_dir.path(),
+            &installed,
+            PNPM_EXECUTABLE,
+            ErrorTask::Which,
+        ) {
+            Err(NodeupError::BinNotFound { bin, .. }) => assert_eq!(bin, PNPM_EXECUTABLE),
+            other => panic!("Expected a missing bin, got: {:?}", other),
+        }
+
+        let missing = Target::from_version("v16.20.0".parse().unwrap());
+        match bin_path_at(
+            download_dir.path(),
+            &missing,
+            NPM_EXECUTABLE,
+            ErrorTask::Which,
+        ) {
+            Err(NodeupError::NotInstalled { target, .. }) => assert_eq!(target, missing),
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bin_dir_of_installed_version() {
+        let download_dir = tempdir().unwrap();
+        let installed = Target::from_version("v18.16.0".parse().unwrap());
+        let bin_dir = download_dir.path().join(installed.to_string()).join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+
+        assert_eq!(
+            version_bin_dir_at(download_dir.path(), &installed).unwrap(),
+            bin_dir
+        );
+
+        let missing = Target::from_version("v16.20.0".parse().unwrap());
+        match version_bin_dir_at(download_dir.path(), &missing) {
+            Err(NodeupError::NotInstalled { target, .. }) => assert_eq!(target, missing),
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn bins_of_installed_version() {
+        let download_dir = tempdir().unwrap();
+        let installed = Target::from_version("v18.16.0".parse().unwrap());
+        let bin_dir = download_dir.path().join(installed.to_string()).join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        for bin in &["npm", "node", "corepack"] {
+            File::create(bin_dir.join(bin)).unwrap();
+        }
+
+        let bins = version_bins_at(download_dir.path(), &installed).unwrap();
+        assert_eq!(bins, vec!["corepack", "node", "npm"]);
+
+        let missing = Target::from_version("v16.20.0".parse().unwrap());
+        match version_bins_at(download_dir.path(), &missing) {
+            Err(NodeupError::NotInstalled { target, .. }) => assert_eq!(target, missing),
+            other => panic!("Expected a not installed error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn windows_install_layout() {
+        let download_dir = tempdir().unwrap();
+        let installed = Target::new(OperatingSystem::Windows, "v18.16.0".parse().unwrap());
+        let install = download_dir.path().join(installed.to_string());
+        fs::create_dir_all(install.join("node_modules")).unwrap();
+        File::create(install.join("node.exe")).unwrap();
+        File::create(install.join("npm.cmd")).unwrap();
+
+        let bin =
+            |name| bin_path_at(download_dir.path(), &installed, name, ErrorTask::Which).unwrap();
+        assert_eq!(bin(NODE_EXECUTABLE), install.join("node.exe"));
+        assert_eq!(bin(NPM_EXECUTABLE), install.join("npm.cmd"));
+        assert_eq!(bin("npm.cmd"), install.join("npm.cmd"));
+        assert_eq!(
+            version_bin_dir_at(download_dir.path(), &installed).unwrap(),
+            install
+        );
+        assert_eq!(
+            version_bins_at(download_dir.path(), &installed).unwrap(),
+            vec!["node.exe", "npm.cmd"]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn hook_sees_target() {
+        let dir = tempdir().unwrap();
+        let script = dir.path().join("hook.sh");
+        let out = dir.path().join("out");
+        fs::write(
+            &script,
+            format!(
+                "echo \"$NODEUP_TARGET $NODEUP_VERSION\" > {}",
+                out.display()
+            ),
+        )
+        .unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        let command = format!("sh {}", script.display());
+        run_hook_command(Hook::PostInstall, &command, &target).unwrap();
+
+        let written = fs::read_to_string(&out).unwrap();
+        assert_eq!(written, "node-v16.1.0-linux-x64 v16.1.0\n");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn failing_hook() {
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        match run_hook_command(Hook::PreInstall, "exit 3", &target) {
+            Err(NodeupError::HookFailed { hook, status, .. }) => {
+                assert_eq!(hook, Hook::PreInstall);
+                assert_eq!(status.code(), Some(3));
+            }
+            other => panic!("Expected the hook to fail, got: {:?}", other),
+        }
     }
 }
@@ -1,14 +1,19 @@
-use crate::target::Target;
-use std::{env, fs, io, path::PathBuf};
+use crate::target::{OperatingSystem, Target};
+use std::{
+    env, fs, io,
+    path::{Component, Path, PathBuf},
+};
 use tempfile::NamedTempFile;
 use thiserror::Error;
 
 const CONFIG_FILE_NAME: &str = "settings.toml";
 const NODEUP: &str = "nodeup";
+const ARCHIVE_CACHE_DIR_NAME: &str = "archives";
 
 const CONFIG_DIR_NOT_FOUND: &str = "Can't find an appropriate directory for config. Searched $NODEUP_CONFIG_DIR/settings.toml -> $XDG_CONFIG_HOME/nodeup/settings.toml -> $HOME/.config/nodeup/settings.toml";
 const DOWNLOAD_DIR_NOT_FOUND: &str = "Can't find an appropriate directory for node binaries. Searched $NODEUP_DOWNLOADS -> $XDG_DATA_HOME/nodeup -> $HOME/.local/share/nodeup";
-const LINKS_DIR_NOT_FOUND: &str = "Can't find an appropriate directory for nodeup symlinks. Searched $NODEUP_LINKS -> $XDG_BIN_HOME/nodeup/links -> $HOME/.local/bin";
+const CACHE_DIR_NOT_FOUND: &str = "Can't find an appropriate directory for the nodeup cache. Searched $NODEUP_CACHE -> $XDG_DATA_HOME/nodeup/cache -> $HOME/.local/share/nodeup/cache";
+const LINKS_DIR_NOT_FOUND: &str = "Can't find an appropriate directory for nodeup symlinks. Searched $NODEUP_LINKS -> $HOME/.local/bin on unix or {FOLDERID_RoamingAppData}\\nodeup\\links on windows";
 
 type LocalResult<T> = Result<T, LocalError>;
 
@@ -19,6 +24,41 @@ pub enum LocalError {
 
     #[error("IO Error when trying to access: {path:?}: {source}")]
     IO { source: io::Error, path: PathBuf },
+
+    #[error("${var} is set to {path:?}, which can't be expanded since the home directory can't be found")]
+    HomeNotFound { var: &'static str, path: PathBuf },
+}
+
+// Directories set in the environment are often copied from shell configs where ~ would've been
+// expanded, and a relative path would change meaning with every directory nodeup runs in
+fn dir_from_env(var: &'static str) -> LocalResult<Option<PathBuf>> {
+    let path = match env::var_os(var) {
+        Some(path) => PathBuf::from(path),
+        None => return Ok(None),
+    };
+
+    let mut components = path.components();
+    let expanded = match components.next() {
+        Some(Component::Normal(first)) if first == "~" || first == "$HOME" => {
+            match dirs::home_dir() {
+                Some(home) => home.join(components.as_path()),
+                None => return Err(LocalError::HomeNotFound { var, path }),
+            }
+        }
+        _ => path.clone(),
+    };
+
+    absolute(expanded).map(Some)
+}
+
+fn absolute(path: PathBuf) -> LocalResult<PathBuf> {
+    if path.is_absolute() {
+        return Ok(path);
+    }
+
+    env::current_dir()
+        .map(|cwd| cwd.join(&path))
+        .map_err(|source| LocalError::IO { source, path })
 }
 
 /// Returns the location of the directory where node downloads are stored
@@ -31,8 +71,7 @@ pub enum LocalError {
 /// |2|$XDG_DATA_HOME/nodeup    |$HOME/library/Application Support/nodeup|{FOLDERID_RoamingAppData}\nodeup|
 /// |3|$Home/.local/share/nodeup|
 pub fn download_dir() -> LocalResult<PathBuf> {
-    let nodeup_bin = env::var_os("NODEUP_DOWNLOADS").map(|dir| PathBuf::from(&dir));
-    if let Some(nodeup_bin) = nodeup_bin {
+    if let Some(nodeup_bin) = dir_from_env("NODEUP_DOWNLOADS")? {
         return Ok(nodeup_bin);
     }
 
@@ -45,6 +84,52 @@ pub fn target_path(target: &Target) -> LocalResult<PathBuf> {
     download_dir().map(|dir| dir.join(target.to_string()))
 }
 
+/// The directory containing the node, npm, and npx executables of a target
+pub fn bin_dir(target: &Target) -> LocalResult<PathBuf> {
+    target_path(target).map(|dir| install_bin_dir(&dir, target))
+}
+
+/// The directory containing the executables of the target installed at `install`. Windows builds
+/// keep them at the top of the install instead of in bin
+pub fn install_bin_dir(install: &Path, target: &Target) -> PathBuf {
+    match target.os() {
+        OperatingSystem::Windows => install.to_path_buf(),
+        _ => install.join("bin"),
+    }
+}
+
+/// The file names an executable can have in an install of the target. Windows builds ship node as
+/// node.exe and the rest, like npm, as .cmd scripts
+pub fn executable_names(target: &Target, executable: &str) -> Vec<String> {
+    match target.os() {
+        OperatingSystem::Windows if Path::new(executable).extension().is_none() => {
+            vec![format!("{}.exe", executable), format!("{}.cmd", executable)]
+        }
+        _ => vec![executable.to_string()],
+    }
+}
+
+/// Returns the location of the directory where responses from the node registry are cached
+///
+/// ### Order of preference for cache directory
+///
+/// | |Linux                          |Mac                                           |Windows                               |
+/// |-|-------------------------------|----------------------------------------------|--------------------------------------|
+/// |1|$NODEUP_CACHE                  |$NODEUP_CACHE                                 |$NODEUP_CACHE                         |
+/// |2|$XDG_DATA_HOME/nodeup/cache    |$HOME/Library/Application Support/nodeup/cache|{FOLDERID_RoamingAppData}\nodeup\cache|
+/// |3|$HOME/.local/share/nodeup/cache|
+pub fn cache_dir() -> LocalResult<PathBuf> {
+    dir_from_env("NODEUP_CACHE")?
+        .or_else(|| dirs::data_dir().map(|dir| dir.join(NODEUP).join("cache")))
+        .ok_or(LocalError::NotFound(CACHE_DIR_NOT_FOUND))
+}
+
+/// Downloaded node archives are kept in the cache directory so installing a version again doesn't
+/// need the network
+pub fn archive_cache_dir() -> LocalResult<PathBuf> {
+    cache_dir().map(|dir| dir.join(ARCHIVE_CACHE_DIR_NAME))
+}
+
 /// Returns the location of the nodeup config directory
 ///
 /// ### Order of preference for config directory
@@ -55,9 +140,8 @@ pub fn target_path(target: &Target) -> LocalResult<PathBuf> {
 /// |2|$XDG_CONFIG_HOME/nodeup|$HOME/Library/Application Support/nodeup|{FOLDERID_RoamingAppData}\nodeup|
 /// |3|$HOME/.config          |
 pub fn config_dir() -> LocalResult<PathBuf> {
-    let config_dir = env::var_os("NODEUP_CONFIG")
-        .map(PathBuf::from)
-        .or_else(|| dirs::config_dir().map(|dir| dir.join(NODEUP)));
+    let config_dir =
+        dir_from_env("NODEUP_CONFIG")?.or_else(|| dirs::config_dir().map(|dir| dir.join(NODEUP)));
 
     match config_dir {
         Some(config_dir) => {
@@ -87,18 +171,24 @@ pub fn transitory_config_file() -> LocalResult<NamedTempFile> {
     })
 }
 
-/// Returns the location of the node, npm, and npx symlinks to nodeup
+/// Returns the location of the node, npm, and npx links to nodeup
 ///
 /// ### Order of preference for links directory
 ///
-/// | |Linux           |Mac             |Windows      |
-/// |-|----------------|----------------|-------------|
-/// |1|$NODEUP_LINKS   |$NODEUP_LINKS   |$NODEUP_LINKS|
-/// |2|$HOME/.local/bin|$HOME/.local/bin|TODO         |
+/// | |Linux           |Mac             |Windows                               |
+/// |-|----------------|----------------|--------------------------------------|
+/// |1|$NODEUP_LINKS   |$NODEUP_LINKS   |$NODEUP_LINKS                         |
+/// |2|$HOME/.local/bin|$HOME/.local/bin|{FOLDERID_RoamingAppData}\nodeup\links|
 pub fn links() -> LocalResult<PathBuf> {
+    let links_dir = dir_from_env("NODEUP_LINKS")?;
+
     #[cfg(unix)]
-    env::var_os("NODEUP_LINKS")
-        .map(PathBuf::from)
-        .or_else(|| dirs::home_dir().map(|dir| dir.join(".local").join("bin")))
-        .ok_or(LocalError::NotFound(LINKS_DIR_NOT_FOUND))
+    let links_dir =
+        links_dir.or_else(|| dirs::home_dir().map(|dir| dir.join(".local").join("bin")));
+
+    #[cfg(windows)]
+    let links_dir =
+        links_dir.or_else(|| dirs::data_dir().map(|dir| dir.join(NODEUP).join("links")));
+
+    links_dir.ok_or(LocalError::NotFound(LINKS_DIR_NOT_FOUND))
 }
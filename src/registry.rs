@@ -1,23 +1,61 @@
 use flate2::read::GzDecoder;
-use log::debug;
-use reqwest::{blocking, StatusCode};
+use log::{debug, warn};
+use reqwest::{blocking, header, StatusCode};
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::{
-    io,
-    path::{Path, PathBuf},
+    collections::HashMap,
+    env, fs, io,
+    io::{Cursor, Read},
+    path::{Component, Path, PathBuf},
+    sync::Mutex,
+    thread,
+    time::{Duration, Instant},
 };
 use tar::Archive;
 use thiserror::Error;
+use zip::{result::ZipError, ZipArchive};
 
-use crate::target::{Target, Version};
+use crate::{
+    local::{self, LocalError},
+    target::{Libc, OperatingSystem, Target, Version, VersionReq},
+};
 
 const BASE_URL: &str = "https://nodejs.org/dist/";
+// musl builds aren't published with the official releases
+const UNOFFICIAL_BASE_URL: &str = "https://unofficial-builds.nodejs.org/download/release/";
+// Identifies nodeup in mirror logs instead of reqwest's default agent
+const USER_AGENT: &str = concat!("nodeup/", env!("CARGO_PKG_VERSION"));
+const INDEX_FILE_NAME: &str = "index.json";
+const INDEX_ETAG_FILE_NAME: &str = "index.json.etag";
+const DEFAULT_INDEX_TTL: Duration = Duration::from_secs(60 * 60);
+const LTS_FILE_NAME: &str = "latest-lts";
+const DEFAULT_LTS_TTL: Duration = Duration::from_secs(24 * 60 * 60);
+const DEFAULT_CONNECT_TIMEOUT: Duration = Duration::from_secs(10);
+const DEFAULT_READ_TIMEOUT: Duration = Duration::from_secs(30);
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(300);
+/// Number of downloads run at once when installing several versions
+pub const DEFAULT_CONCURRENCY: usize = 3;
+/// Environment variable capping how many downloads run at once, ex/ to avoid being rate limited
+pub const MAX_CONCURRENCY_VAR: &str = "NODEUP_MAX_CONCURRENCY";
+
+/// Environment variable that turns on offline mode when set to anything but an empty string or 0
+pub const OFFLINE_VAR: &str = "NODEUP_OFFLINE";
 
 #[derive(Debug, Error)]
 pub enum RegistryError {
+    #[error(transparent)]
+    Local(#[from] LocalError),
+
     #[error("Error making request to {:?}: {source}", source.url())]
     Request { source: reqwest::Error },
 
+    #[error("Can't fetch {url:?} in offline mode. Drop --offline and unset $NODEUP_OFFLINE to allow network access")]
+    Offline { url: String },
+
+    #[error("Timed out waiting on {url:?}. The timeouts can be raised by setting $NODEUP_CONNECT_TIMEOUT, $NODEUP_READ_TIMEOUT, and $NODEUP_TIMEOUT in seconds")]
+    Timeout { url: String },
+
     #[error("Unexpected response from {url:?}: {source}")]
     UnexpectedResponse {
         source: serde_json::Error,
@@ -27,9 +65,34 @@ pub enum RegistryError {
     #[error("Error writing the download out to disk at location: {path:?}: {source}")]
     IO { source: io::Error, path: PathBuf },
 
+    #[error("Error extracting the zip archive to {path:?}: {source}")]
+    Zip { source: ZipError, path: PathBuf },
+
+    #[error("There is no lts release named {codename:?}. Available lts releases are: {}", available.join(", "))]
+    UnknownCodename {
+        codename: String,
+        available: Vec<String>,
+    },
+
+    #[error("No version of node matching {req} is available")]
+    NoMatchingVersion { req: VersionReq },
+
     #[error("Target {target} does not exist")]
     InvalidTarget { target: Target },
 
+    #[error("The archive at {path:?} doesn't contain {target}. Found: {}", found.join(", "))]
+    ArchiveMismatch {
+        path: PathBuf,
+        target: Target,
+        found: Vec<String>,
+    },
+
+    #[error("The archive entry {entry:?} would be extracted outside of the install directory")]
+    UnsafeArchivePath { entry: PathBuf },
+
+    #[error("The archive for {target} didn't unpack to {path:?} containing a node executable")]
+    UnexpectedArchiveLayout { target: Target, path: PathBuf },
+
     #[error("Unexpected result from {url:?}: {code}")]
     UnexpectedResult {
         url: String,
@@ -50,108 +113,2222 @@ enum LTSVersion {
     No(bool),
 }
 
-pub fn download_node_toolchain(location: &Path, target: Target) -> Result<(), RegistryError> {
-    let url = get_node_download_url(target);
-    debug!("Downloading node at url: {}", target);
+/// How much was downloaded to install a target. Archives reused from the archive cache aren't
+/// downloaded, so nothing is counted for them
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct DownloadStats {
+    pub bytes: u64,
+    pub duration: Duration,
+}
 
-    let tar_gzip = blocking::get(&url).map_err(|source| RegistryError::Request { source })?;
-    match tar_gzip.status() {
-        StatusCode::OK => {
-            let tar = GzDecoder::new(tar_gzip);
-            let mut arc = Archive::new(tar);
-            arc.unpack(location).map_err(|source| RegistryError::IO {
-                source,
-                path: location.to_path_buf(),
-            })?;
-            Ok(())
+impl DownloadStats {
+    pub fn bytes_per_second(&self) -> f64 {
+        match self.duration.as_secs_f64() {
+            secs if secs > 0.0 => self.bytes as f64 / secs,
+            _ => 0.0,
         }
-        StatusCode::NOT_FOUND => Err(RegistryError::InvalidTarget { target }),
-        code => Err(RegistryError::UnexpectedResult { url, code }),
     }
 }
 
-pub fn get_latest_lts() -> Result<Version, RegistryError> {
-    let url = format!("{}index.json", BASE_URL);
-    debug!("Fetching node lts from: {}", url);
+/// A client for the node distribution registry. The http client is shared by every request so
+/// connections are reused, ex/ when the index is fetched right before a download.
+pub struct Registry {
+    client: blocking::Client,
+    base_url: String,
+    unofficial_base_url: String,
+    /// Where downloaded archives are kept, the archive cache from `local` when not set
+    archive_cache: Option<PathBuf>,
+    /// Every request fails instead of reaching the network. Only cached archives and a cached index
+    /// can be used
+    offline: bool,
+    /// The most downloads run at once by batch operations
+    max_concurrency: usize,
+}
+
+impl Registry {
+    pub fn new() -> Result<Self, RegistryError> {
+        Ok(Registry {
+            client: http_client()?,
+            base_url: BASE_URL.to_string(),
+            unofficial_base_url: UNOFFICIAL_BASE_URL.to_string(),
+            archive_cache: None,
+            offline: offline_from_env(),
+            max_concurrency: max_concurrency_from_env().unwrap_or(DEFAULT_CONCURRENCY),
+        })
+    }
+
+    /// A registry serving official and unofficial builds from the given url, ex/ a mirror
+    pub fn with_base_url(base_url: &str) -> Result<Self, RegistryError> {
+        let base_url = with_trailing_slash(base_url);
+
+        Ok(Registry {
+            client: http_client()?,
+            unofficial_base_url: base_url.clone(),
+            base_url,
+            archive_cache: None,
+            offline: offline_from_env(),
+            max_concurrency: max_concurrency_from_env().unwrap_or(DEFAULT_CONCURRENCY),
+        })
+    }
+
+    /// Keeps downloaded archives in the given directory instead of the default archive cache
+    pub fn with_archive_cache(mut self, dir: &Path) -> Self {
+        self.archive_cache = Some(dir.to_path_buf());
+        self
+    }
+
+    /// Turns offline mode on or off, overriding $NODEUP_OFFLINE
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    pub fn is_offline(&self) -> bool {
+        self.offline
+    }
+
+    /// Caps how many downloads batch operations run at once, overriding $NODEUP_MAX_CONCURRENCY
+    pub fn with_max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency.max(1);
+        self
+    }
+
+    pub fn max_concurrency(&self) -> usize {
+        self.max_concurrency
+    }
+
+    fn ensure_online(&self, url: &str) -> Result<(), RegistryError> {
+        ensure_online(self.offline, url)
+    }
+
+    /// Installs the target, reusing its archive from the archive cache when it's there and intact
+    pub fn download_node_toolchain(
+        &self,
+        location: &Path,
+        target: Target,
+    ) -> Result<DownloadStats, RegistryError> {
+        let url = self.node_download_url(&target);
+        let cache_dir = match &self.archive_cache {
+            Some(dir) => dir.clone(),
+            None => local::archive_cache_dir()?,
+        };
+        let file_name = url.rsplit('/').next().unwrap_or_default();
+        let cache_file = cache_dir.join(file_name);
 
-    let resp = blocking::get(&url).map_err(|source| RegistryError::Request { source })?;
+        let stats = match is_cached_archive_intact(&cache_file) {
+            true => {
+                debug!("Using cached archive at: {}", cache_file.display());
+                DownloadStats::default()
+            }
+            false => self.download_archive(&url, &target, &cache_file)?,
+        };
 
-    let all_versions: Vec<AvailableVersion> =
-        serde_json::from_reader(resp).map_err(|source| RegistryError::UnexpectedResponse {
+        let archive = fs::File::open(&cache_file).map_err(|source| RegistryError::IO {
             source,
-            url: url.to_string(),
+            path: cache_file.clone(),
         })?;
+        install_archive(archive, location, &target)?;
+        Ok(stats)
+    }
 
-    let latest_lts = all_versions
-        .into_iter()
-        .filter_map(|v| match v.lts {
-            LTSVersion::Yes(_) => Some(
-                Version::parse(&v.version)
-                    .unwrap_or_else(|_| panic!("Error parsing verson from node registry: {:?}", v)),
+    // The archive is written to a temporary file first so an interrupted download is never mistaken
+    // for a cached archive. Its checksum is kept next to it to catch archives damaged later on
+    fn download_archive(
+        &self,
+        url: &str,
+        target: &Target,
+        cache_file: &Path,
+    ) -> Result<DownloadStats, RegistryError> {
+        self.ensure_online(url)?;
+        debug!("Downloading node at url: {}", url);
+
+        let started = Instant::now();
+        let mut archive = get(&self.client, url)?;
+        check_archive_status(archive.status(), url, target)?;
+
+        let cache_dir = cache_file.parent().unwrap_or(cache_file);
+        let io_error = |source| RegistryError::IO {
+            source,
+            path: cache_dir.to_path_buf(),
+        };
+        fs::create_dir_all(cache_dir).map_err(io_error)?;
+        let mut staged = tempfile::NamedTempFile::new_in(cache_dir).map_err(io_error)?;
+        let bytes = archive.copy_to(&mut staged).map_err(request_error(url))?;
+        let stats = DownloadStats {
+            bytes,
+            duration: started.elapsed(),
+        };
+
+        let checksum = sha256_file(staged.path()).map_err(io_error)?;
+        fs::write(checksum_path(cache_file), checksum).map_err(io_error)?;
+        staged.persist(cache_file).map_err(|e| RegistryError::IO {
+            source: e.error,
+            path: cache_file.to_path_buf(),
+        })?;
+        Ok(stats)
+    }
+
+    /// Installs every target using up to `jobs` downloads at a time, but never more than the
+    /// registry's max concurrency. Every target is attempted even if others fail, and the results
+    /// are returned in the order the targets were given.
+    pub fn download_many(
+        &self,
+        location: &Path,
+        targets: Vec<Target>,
+        jobs: usize,
+    ) -> Vec<(Target, Result<DownloadStats, RegistryError>)> {
+        let queue = Mutex::new(targets.into_iter().enumerate());
+        let results = Mutex::new(Vec::new());
+        thread::scope(|scope| {
+            for _ in 0..jobs.clamp(1, self.max_concurrency) {
+                scope.spawn(|| loop {
+                    let next = queue.lock().expect("Download queue poisoned").next();
+                    let (position, target) = match next {
+                        Some(next) => next,
+                        None => break,
+                    };
+
+                    let result = self.download_node_toolchain(location, target.clone());
+                    results
+                        .lock()
+                        .expect("Download results poisoned")
+                        .push((position, target, result));
+                });
+            }
+        });
+
+        let mut results = results.into_inner().expect("Download results poisoned");
+        results.sort_by_key(|(position, _, _)| *position);
+        results
+            .into_iter()
+            .map(|(_, target, result)| (target, result))
+            .collect()
+    }
+
+    /// Returns the newest lts version. The result is cached separately from the index so the
+    /// common case doesn't need to parse the whole index.
+    pub fn get_latest_lts(&self) -> Result<Version, RegistryError> {
+        let cache_file = local::cache_dir()?.join(LTS_FILE_NAME);
+        load_latest_lts(&cache_file, lts_ttl(), || {
+            self.fetch_index(false).map(latest_lts)
+        })
+    }
+
+    /// Returns the newest stable release. Release candidates and nightlies are skipped
+    pub fn get_latest(&self) -> Result<Version, RegistryError> {
+        latest_stable(self.fetch_index(false)?)
+    }
+
+    /// Returns the newest release of the lts line with the given codename, ex/ gallium. Codenames
+    /// are matched case insensitively.
+    pub fn get_lts_by_codename(&self, codename: &str) -> Result<Version, RegistryError> {
+        let all_versions = self.fetch_index(false)?;
+        lts_by_codename(all_versions, codename)
+    }
+
+    /// Every lts line by codename along with its newest release, oldest line first
+    pub fn lts_lines(&self) -> Result<Vec<(String, Version)>, RegistryError> {
+        self.fetch_index(false).map(lts_lines_from)
+    }
+
+    /// Returns the newest version available from the node registry that satisfies the requirement
+    pub fn resolve_version_req(&self, req: &VersionReq) -> Result<Version, RegistryError> {
+        let all_versions = self.fetch_index(false)?;
+        newest_matching(all_versions, req)
+    }
+
+    /// Lists every version available from the node registry, newest first
+    pub fn list_versions(&self) -> Result<Vec<Version>, RegistryError> {
+        self.fetch_index(false)
+            .map(|index| available_versions(index, false))
+    }
+
+    /// Lists the long term support versions available from the node registry, newest first
+    pub fn list_lts_versions(&self) -> Result<Vec<Version>, RegistryError> {
+        self.fetch_index(false)
+            .map(|index| available_versions(index, true))
+    }
+
+    /// Fetches the index of available versions from the node registry, replacing the cached copy
+    /// Also clears the cached latest lts version so it's recomputed from the new index.
+    pub fn refresh_index(&self) -> Result<(), RegistryError> {
+        self.fetch_index(true)?;
+
+        let lts_cache = local::cache_dir()?.join(LTS_FILE_NAME);
+        match fs::remove_file(&lts_cache) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => warn!(
+                "Couldn't clear the cached lts version at {}: {}",
+                lts_cache.display(),
+                e
             ),
-            _ => None,
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn fetch_index(&self, refresh: bool) -> Result<Vec<AvailableVersion>, RegistryError> {
+        let cache_file = local::cache_dir()?.join(INDEX_FILE_NAME);
+        let url = self.index_url();
+        load_index(&cache_file, &url, index_ttl(), refresh, |etag| {
+            self.download_index(&url, etag)
         })
+    }
+
+    fn index_url(&self) -> String {
+        format!("{}{}", self.base_url, INDEX_FILE_NAME)
+    }
+
+    // The etag of the cached index is sent along so the registry can skip sending an index that
+    // hasn't changed
+    fn download_index(
+        &self,
+        url: &str,
+        etag: Option<&str>,
+    ) -> Result<IndexResponse, RegistryError> {
+        self.ensure_online(url)?;
+        debug!("Fetching node index from: {}", url);
+
+        let mut request = self.client.get(url).timeout(request_timeout());
+        if let Some(etag) = etag {
+            request = request.header(header::IF_NONE_MATCH, etag);
+        }
+        let resp = request.send().map_err(request_error(url))?;
+        match resp.status() {
+            StatusCode::OK => {
+                let etag = resp
+                    .headers()
+                    .get(header::ETAG)
+                    .and_then(|etag| etag.to_str().ok())
+                    .map(String::from);
+                let content = resp.bytes().map_err(request_error(url))?.to_vec();
+                Ok(IndexResponse::Modified { content, etag })
+            }
+            StatusCode::NOT_MODIFIED => Ok(IndexResponse::NotModified),
+            code => Err(RegistryError::UnexpectedResult {
+                url: url.to_string(),
+                code,
+            }),
+        }
+    }
+
+    /// The url the target's archive is downloaded from. musl builds are only available from the
+    /// unofficial builds
+    // musl builds: https://unofficial-builds.nodejs.org/download/release/v12.9.1/node-v12.9.1-linux-x64-musl.tar.gz
+    pub fn node_download_url(&self, target: &Target) -> String {
+        node_download_url(target, &self.base_url, &self.unofficial_base_url)
+    }
+}
+
+fn node_download_url(target: &Target, base_url: &str, unofficial_base_url: &str) -> String {
+    let base_url = match target.libc() {
+        Libc::Gnu => base_url,
+        Libc::Musl => unofficial_base_url,
+    };
+    target.download_url(base_url)
+}
+
+fn with_trailing_slash(base_url: &str) -> String {
+    match base_url.ends_with('/') {
+        true => base_url.to_string(),
+        false => format!("{}/", base_url),
+    }
+}
+
+// Every request to the registry goes through this check so offline mode fails before reaching out
+// rather than hanging on an unreachable network
+pub(crate) fn ensure_online(offline: bool, url: &str) -> Result<(), RegistryError> {
+    match offline {
+        true => Err(RegistryError::Offline {
+            url: url.to_string(),
+        }),
+        false => Ok(()),
+    }
+}
+
+fn check_archive_status(
+    status: StatusCode,
+    url: &str,
+    target: &Target,
+) -> Result<(), RegistryError> {
+    match status {
+        StatusCode::OK => Ok(()),
+        StatusCode::NOT_FOUND => Err(RegistryError::InvalidTarget {
+            target: target.clone(),
+        }),
+        code => Err(RegistryError::UnexpectedResult {
+            url: url.to_string(),
+            code,
+        }),
+    }
+}
+
+fn parse_index(content: &[u8], url: &str) -> Result<Vec<AvailableVersion>, RegistryError> {
+    serde_json::from_slice(content).map_err(|source| RegistryError::UnexpectedResponse {
+        source,
+        url: url.to_string(),
+    })
+}
+
+pub fn download_node_toolchain(
+    location: &Path,
+    target: Target,
+) -> Result<DownloadStats, RegistryError> {
+    Registry::new()?.download_node_toolchain(location, target)
+}
+
+/// Removes every archive kept by `download_node_toolchain`, returning how many were removed
+pub fn clean_archive_cache() -> Result<usize, RegistryError> {
+    let cache_dir = local::archive_cache_dir()?;
+    let entries = match fs::read_dir(&cache_dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(0),
+        Err(source) => {
+            return Err(RegistryError::IO {
+                source,
+                path: cache_dir,
+            })
+        }
+    };
+
+    let mut removed = 0;
+    for entry in entries {
+        let path = entry
+            .map_err(|source| RegistryError::IO {
+                source,
+                path: cache_dir.clone(),
+            })?
+            .path();
+        fs::remove_file(&path).map_err(|source| RegistryError::IO {
+            source,
+            path: path.clone(),
+        })?;
+        if path
+            .extension()
+            .is_none_or(|extension| extension != "sha256")
+        {
+            removed += 1;
+        }
+    }
+    Ok(removed)
+}
+
+// A cached archive is only used when its checksum still matches the one recorded when it was
+// downloaded
+fn is_cached_archive_intact(cache_file: &Path) -> bool {
+    let expected = match fs::read_to_string(checksum_path(cache_file)) {
+        Ok(expected) => expected,
+        Err(_) => return false,
+    };
+    match sha256_file(cache_file) {
+        Ok(actual) if actual == expected.trim() => true,
+        Ok(_) => {
+            warn!(
+                "Cached archive at {} is corrupt, downloading it again",
+                cache_file.display()
+            );
+            false
+        }
+        Err(_) => false,
+    }
+}
+
+// ex/ node-v12.9.1-linux-x64.tar.gz -> node-v12.9.1-linux-x64.tar.gz.sha256
+fn checksum_path(path: &Path) -> PathBuf {
+    let mut file_name = path.file_name().unwrap_or_default().to_os_string();
+    file_name.push(".sha256");
+    path.with_file_name(file_name)
+}
+
+fn sha256_file(path: &Path) -> io::Result<String> {
+    let mut file = fs::File::open(path)?;
+    let mut hasher = Sha256::new();
+    io::copy(&mut file, &mut hasher)?;
+    Ok(hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect())
+}
+
+/// Unpacks a gzipped tarball, the format node is distributed in for everything except windows
+pub fn unpack<R: Read>(tar_gzip: R, location: &Path) -> Result<(), RegistryError> {
+    let entries = unpack_with_progress(tar_gzip, location, |_| ())?;
+    debug!("Unpacked {} entries to: {}", entries, location.display());
+    Ok(())
+}
+
+/// Unpacks a gzipped tarball one entry at a time, calling `progress` with the number of entries
+/// extracted so far. Entries that would land outside of `location` are rejected before anything is
+/// written for them. Returns the number of entries extracted
+pub fn unpack_with_progress<R, F>(
+    tar_gzip: R,
+    location: &Path,
+    mut progress: F,
+) -> Result<usize, RegistryError>
+where
+    R: Read,
+    F: FnMut(usize),
+{
+    let io_error = |source| RegistryError::IO {
+        source,
+        path: location.to_path_buf(),
+    };
+    let tar = GzDecoder::new(tar_gzip);
+    let mut arc = Archive::new(tar);
+
+    let mut extracted = 0;
+    for entry in arc.entries().map_err(io_error)? {
+        let mut entry = entry.map_err(io_error)?;
+        let path = entry.path().map_err(io_error)?.into_owned();
+        ensure_enclosed(&path)?;
+        entry.unpack_in(location).map_err(io_error)?;
+        extracted += 1;
+        progress(extracted);
+    }
+    Ok(extracted)
+}
+
+// Archives are untrusted and installs run with the user's full permissions, so an entry like
+// ../../.bashrc must never be written. Entries may step back out of a directory they're in,
+// ex/ lib/../bin/node, as long as they never leave the directory they're unpacked to
+fn ensure_enclosed(entry: &Path) -> Result<(), RegistryError> {
+    let unsafe_path = || RegistryError::UnsafeArchivePath {
+        entry: entry.to_path_buf(),
+    };
+    let mut depth = 0usize;
+    for component in entry.components() {
+        match component {
+            Component::Normal(_) => depth += 1,
+            Component::CurDir => (),
+            Component::ParentDir => depth = depth.checked_sub(1).ok_or_else(unsafe_path)?,
+            Component::RootDir | Component::Prefix(_) => return Err(unsafe_path()),
+        }
+    }
+    Ok(())
+}
+
+/// Installs the target from a tarball that was already downloaded. The archive is only moved into
+/// place once its top level directory is known to match the target.
+pub fn install_from_file(
+    location: &Path,
+    archive: &Path,
+    target: &Target,
+) -> Result<(), RegistryError> {
+    let file = fs::File::open(archive).map_err(|source| RegistryError::IO {
+        source,
+        path: archive.to_path_buf(),
+    })?;
+
+    let staging = stage(location, |staging| unpack(file, staging))?;
+
+    let entries = fs::read_dir(staging.path()).map_err(|source| RegistryError::IO {
+        source,
+        path: staging.path().to_path_buf(),
+    })?;
+    let found: Vec<String> = entries
+        .filter_map(|entry| entry.ok())
+        .map(|entry| entry.file_name().to_string_lossy().into_owned())
+        .collect();
+
+    let expected = target.to_string();
+    if found != [expected.as_str()] {
+        return Err(RegistryError::ArchiveMismatch {
+            path: archive.to_path_buf(),
+            target: target.clone(),
+            found,
+        });
+    }
+
+    move_into_place(&staging, location, target)
+}
+
+// Downloads are unpacked next to the other versions and moved into place once they've been fully
+// extracted, so a failed install never leaves a partial version behind
+fn install_archive<R: Read>(
+    archive: R,
+    location: &Path,
+    target: &Target,
+) -> Result<(), RegistryError> {
+    let staging = stage(location, |staging| match target.os() {
+        OperatingSystem::Windows => unpack_zip(archive, staging),
+        _ => unpack(archive, staging),
+    })?;
+    move_into_place(&staging, location, target)
+}
+
+// The staging directory is removed when it's dropped, including when unpacking fails
+fn stage<F>(location: &Path, unpack: F) -> Result<tempfile::TempDir, RegistryError>
+where
+    F: FnOnce(&Path) -> Result<(), RegistryError>,
+{
+    fs::create_dir_all(location).map_err(|source| RegistryError::IO {
+        source,
+        path: location.to_path_buf(),
+    })?;
+    let staging = tempfile::tempdir_in(location).map_err(|source| RegistryError::IO {
+        source,
+        path: location.to_path_buf(),
+    })?;
+    unpack(staging.path())?;
+    Ok(staging)
+}
+
+fn move_into_place(
+    staging: &tempfile::TempDir,
+    location: &Path,
+    target: &Target,
+) -> Result<(), RegistryError> {
+    check_layout(staging.path(), target)?;
+
+    let unpacked = staging.path().join(target.to_string());
+    let destination = location.join(target.to_string());
+    match fs::rename(&unpacked, &destination) {
+        Ok(()) => Ok(()),
+        // Another install of the same version finished first
+        Err(_) if destination.is_dir() && crate::verify_install_at(location, target) => {
+            debug!("{} was already installed", destination.display());
+            Ok(())
+        }
+        // Whatever is left there is missing node, so it's replaced with the new install
+        Err(_) if destination.is_dir() => {
+            debug!("Replacing broken install at {}", destination.display());
+            fs::remove_dir_all(&destination)
+                .and_then(|()| fs::rename(&unpacked, &destination))
+                .map_err(|source| RegistryError::IO {
+                    source,
+                    path: destination,
+                })
+        }
+        Err(source) => Err(RegistryError::IO {
+            source,
+            path: destination,
+        }),
+    }
+}
+
+// A mislabeled archive would otherwise be installed under a name that doesn't match its contents
+fn check_layout(staging: &Path, target: &Target) -> Result<(), RegistryError> {
+    let unpacked = staging.join(target.to_string());
+    let bin_dir = local::install_bin_dir(&unpacked, target);
+    let has_node = local::executable_names(target, "node")
+        .iter()
+        .any(|name| bin_dir.join(name).is_file());
+    match unpacked.is_dir() && has_node {
+        true => Ok(()),
+        false => Err(RegistryError::UnexpectedArchiveLayout {
+            target: target.clone(),
+            path: unpacked,
+        }),
+    }
+}
+
+// Zip archives need to be seekable so the whole download is buffered in memory before extracting
+fn unpack_zip<R: Read>(mut zip: R, location: &Path) -> Result<(), RegistryError> {
+    let mut content = Vec::new();
+    zip.read_to_end(&mut content)
+        .map_err(|source| RegistryError::IO {
+            source,
+            path: location.to_path_buf(),
+        })?;
+
+    let zip_error = |source| RegistryError::Zip {
+        source,
+        path: location.to_path_buf(),
+    };
+    let mut arc = ZipArchive::new(Cursor::new(content)).map_err(zip_error)?;
+    // The zip crate refuses these too but without saying which entry was the problem
+    for index in 0..arc.len() {
+        let file = arc.by_index(index).map_err(zip_error)?;
+        ensure_enclosed(Path::new(file.name()))?;
+    }
+    arc.extract(location).map_err(|source| RegistryError::Zip {
+        source,
+        path: location.to_path_buf(),
+    })
+}
+
+pub fn get_latest_lts() -> Result<Version, RegistryError> {
+    Registry::new()?.get_latest_lts()
+}
+
+pub fn get_lts_by_codename(codename: &str) -> Result<Version, RegistryError> {
+    Registry::new()?.get_lts_by_codename(codename)
+}
+
+pub fn get_latest() -> Result<Version, RegistryError> {
+    Registry::new()?.get_latest()
+}
+
+fn latest_stable(index: Vec<AvailableVersion>) -> Result<Version, RegistryError> {
+    let any = VersionReq::any();
+    index
+        .iter()
+        .filter_map(parse_available)
+        .filter(|version| any.matches(version))
         .max()
-        .expect("Received no lts versions from the node distribution registry");
+        .ok_or(RegistryError::NoMatchingVersion { req: any })
+}
+
+fn lts_by_codename(index: Vec<AvailableVersion>, codename: &str) -> Result<Version, RegistryError> {
+    let mut available: Vec<String> = Vec::new();
+    let mut latest: Option<Version> = None;
+
+    for v in index {
+        let name = match v.lts {
+            LTSVersion::Yes(ref name) => name.to_lowercase(),
+            LTSVersion::No(_) => continue,
+        };
 
-    Ok(latest_lts)
+        if name == codename.to_lowercase() {
+            latest = latest.max(parse_available(&v));
+        }
+
+        if !available.contains(&name) {
+            available.push(name);
+        }
+    }
+
+    latest.ok_or_else(|| RegistryError::UnknownCodename {
+        codename: codename.to_string(),
+        available,
+    })
 }
 
-// Full url example: https://nodejs.org/dist/v12.9.1/node-v12.9.1-linux-x64.tar.gz
-fn get_node_download_url(target: Target) -> String {
-    let full_url = format!("{}{}/{}.tar.gz", BASE_URL, target.version(), target);
-    full_url
+pub fn lts_lines() -> Result<Vec<(String, Version)>, RegistryError> {
+    Registry::new()?.lts_lines()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use crate::target::OperatingSystem;
-    use crate::target::Version;
-    use std::fs;
-    use tempfile::tempdir;
+fn lts_lines_from(index: Vec<AvailableVersion>) -> Vec<(String, Version)> {
+    let mut lines: HashMap<String, Version> = HashMap::new();
+    for v in index {
+        let name = match v.lts {
+            LTSVersion::Yes(ref name) => name.to_lowercase(),
+            LTSVersion::No(_) => continue,
+        };
 
-    #[test]
-    fn create_node_url() {
-        let version = Version {
-            major: 12,
-            minor: 9,
-            patch: 1,
+        let version = match parse_available(&v) {
+            Some(version) => version,
+            None => continue,
         };
+        let latest = lines.entry(name).or_insert_with(|| version.clone());
+        if version > *latest {
+            *latest = version;
+        }
+    }
 
-        let actual = get_node_download_url(Target::from_version(version));
+    let mut lines: Vec<(String, Version)> = lines.into_iter().collect();
+    lines.sort_by(|(_, a), (_, b)| a.cmp(b));
+    lines
+}
 
-        #[cfg(target_os = "linux")]
-        let expected = "https://nodejs.org/dist/v12.9.1/node-v12.9.1-linux-x64.tar.gz";
+/// Every lts line by codename from the cached index, however old it is, without reaching the
+/// registry. None when the index hasn't been cached
+pub fn cached_lts_lines() -> Result<Option<Vec<(String, Version)>>, RegistryError> {
+    let cache_file = local::cache_dir()?.join(INDEX_FILE_NAME);
+    Ok(read_cached_index(&cache_file).map(|(_, index)| lts_lines_from(index)))
+}
 
-        #[cfg(target_os = "macos")]
-        let expected = "https://nodejs.org/dist/v12.9.1/node-v12.9.1-darwin-x64.tar.gz";
-        assert_eq!(actual, expected);
+pub fn resolve_version_req(req: &VersionReq) -> Result<Version, RegistryError> {
+    Registry::new()?.resolve_version_req(req)
+}
+
+fn newest_matching(
+    index: Vec<AvailableVersion>,
+    req: &VersionReq,
+) -> Result<Version, RegistryError> {
+    available_versions(index, false)
+        .into_iter()
+        .find(|version| req.matches(version))
+        .ok_or(RegistryError::NoMatchingVersion { req: *req })
+}
+
+pub fn list_versions() -> Result<Vec<Version>, RegistryError> {
+    Registry::new()?.list_versions()
+}
+
+pub fn list_lts_versions() -> Result<Vec<Version>, RegistryError> {
+    Registry::new()?.list_lts_versions()
+}
+
+fn available_versions(index: Vec<AvailableVersion>, lts_only: bool) -> Vec<Version> {
+    let mut versions: Vec<Version> = index
+        .into_iter()
+        .filter(|v| !lts_only || matches!(v.lts, LTSVersion::Yes(_)))
+        .filter_map(|v| parse_available(&v))
+        .collect();
+
+    versions.sort_by(|a, b| b.cmp(a));
+    versions
+}
+
+// A malformed entry, ex/ from a mirror, is skipped rather than failing the whole index
+fn parse_available(v: &AvailableVersion) -> Option<Version> {
+    match Version::parse(&v.version) {
+        Ok(version) => Some(version),
+        Err(e) => {
+            warn!("Error parsing version from node registry: {:?}\n{}", v, e);
+            None
+        }
     }
+}
 
-    #[test]
-    #[ignore] // Take a little too long to run
-    fn download_node_to_temp_dir() {
-        let temp_dir = tempdir().unwrap();
-        let path = temp_dir.path();
+pub fn refresh_index() -> Result<(), RegistryError> {
+    Registry::new()?.refresh_index()
+}
 
-        let target = Target::new(
-            OperatingSystem::Linux,
-            Version {
-                major: 12,
-                minor: 0,
-                patch: 0,
-            },
+#[derive(Debug)]
+enum IndexResponse {
+    Modified {
+        content: Vec<u8>,
+        etag: Option<String>,
+    },
+    NotModified,
+}
+
+// The cached index is used until it's older than the ttl, after which it's fetched again. Failing to
+// read or write the cache isn't fatal since the index can always be fetched from the registry.
+// The etag of the cached index is kept next to it. When the registry says the index hasn't changed
+// the cached copy is reused and rewritten so it's fresh again. In offline mode an outdated cache is
+// used as is
+fn load_index<F>(
+    cache_file: &Path,
+    url: &str,
+    ttl: Duration,
+    refresh: bool,
+    fetch: F,
+) -> Result<Vec<AvailableVersion>, RegistryError>
+where
+    F: FnOnce(Option<&str>) -> Result<IndexResponse, RegistryError>,
+{
+    let etag_file = cache_file.with_file_name(INDEX_ETAG_FILE_NAME);
+    let (cached, etag) = match read_cached_index(cache_file) {
+        Some((_, index)) if !refresh && is_fresh(cache_file, ttl) => {
+            debug!("Using cached index at: {}", cache_file.display());
+            return Ok(index);
+        }
+        // The etag is only sent when there's a usable copy to fall back on
+        Some(cached) => (Some(cached), fs::read_to_string(&etag_file).ok()),
+        None => (None, None),
+    };
+
+    let response = match (fetch(etag.as_deref()), cached) {
+        // Offline a stale index beats no index at all
+        (Err(RegistryError::Offline { .. }), Some((_, index))) => {
+            warn!(
+                "Offline, using the outdated cached index at {}",
+                cache_file.display()
+            );
+            return Ok(index);
+        }
+        (response, cached) => (response?, cached),
+    };
+    let (content, etag) = match response {
+        (IndexResponse::NotModified, Some((content, index))) => {
+            debug!("Index is unchanged, reusing: {}", cache_file.display());
+            if let Err(e) = write_cache(cache_file, &content) {
+                warn!(
+                    "Couldn't refresh the cached index at {}: {}",
+                    cache_file.display(),
+                    e
+                );
+            }
+            return Ok(index);
+        }
+        (IndexResponse::NotModified, None) => {
+            return Err(RegistryError::UnexpectedResult {
+                url: url.to_string(),
+                code: StatusCode::NOT_MODIFIED,
+            })
+        }
+        (IndexResponse::Modified { content, etag }, _) => (content, etag),
+    };
+
+    let index = parse_index(&content, url)?;
+
+    if let Err(e) = write_cache(cache_file, &content) {
+        warn!(
+            "Couldn't cache the index at {}: {}",
+            cache_file.display(),
+            e
+        );
+    }
+    let written = match etag {
+        Some(etag) => write_cache(&etag_file, etag.as_bytes()),
+        None => match fs::remove_file(&etag_file) {
+            Err(e) if e.kind() != io::ErrorKind::NotFound => Err(e),
+            _ => Ok(()),
+        },
+    };
+    if let Err(e) = written {
+        warn!(
+            "Couldn't update the index etag at {}: {}",
+            etag_file.display(),
+            e
         );
+    }
 
-        download_node_toolchain(path, target).unwrap();
+    Ok(index)
+}
 
-        let downloaded_path = path.join("node-v12.0.0-linux-x64");
-        fs::read_dir(downloaded_path).unwrap();
+// The raw content is kept alongside the parsed index so it can be rewritten as is
+fn read_cached_index(cache_file: &Path) -> Option<(Vec<u8>, Vec<AvailableVersion>)> {
+    let content = match fs::read(cache_file) {
+        Ok(content) => content,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            warn!(
+                "Couldn't read cached index at {}: {}",
+                cache_file.display(),
+                e
+            );
+            return None;
+        }
+    };
+    match serde_json::from_slice(&content) {
+        Ok(index) => Some((content, index)),
+        Err(e) => {
+            warn!("Cached index at {} is corrupt: {}", cache_file.display(), e);
+            None
+        }
     }
+}
 
-    #[test]
-    fn latest_lts() {
-        get_latest_lts().unwrap();
+fn latest_lts(index: Vec<AvailableVersion>) -> Version {
+    index
+        .into_iter()
+        .filter_map(|v| match v.lts {
+            LTSVersion::Yes(_) => Some(
+                Version::parse(&v.version)
+                    .unwrap_or_else(|_| panic!("Error parsing verson from node registry: {:?}", v)),
+            ),
+            _ => None,
+        })
+        .max()
+        .expect("Received no lts versions from the node distribution registry")
+}
+
+// Works the same as the index cache, the version is reused until the cache file is older than the
+// ttl
+fn load_latest_lts<F>(
+    cache_file: &Path,
+    ttl: Duration,
+    compute: F,
+) -> Result<Version, RegistryError>
+where
+    F: FnOnce() -> Result<Version, RegistryError>,
+{
+    if is_fresh(cache_file, ttl) {
+        let cached = fs::read_to_string(cache_file).map(|content| content.trim().parse());
+        match cached {
+            Ok(Ok(version)) => {
+                debug!("Using cached lts version at: {}", cache_file.display());
+                return Ok(version);
+            }
+            Ok(Err(e)) => warn!(
+                "Cached lts version at {} is corrupt: {}",
+                cache_file.display(),
+                e
+            ),
+            Err(e) => warn!(
+                "Couldn't read cached lts version at {}: {}",
+                cache_file.display(),
+                e
+            ),
+        }
+    }
+
+    let version = compute()?;
+    if let Err(e) = write_cache(cache_file, version.to_string().as_bytes()) {
+        warn!(
+            "Couldn't cache the lts version at {}: {}",
+            cache_file.display(),
+            e
+        );
+    }
+    Ok(version)
+}
+
+fn is_fresh(path: &Path, ttl: Duration) -> bool {
+    fs::metadata(path)
+        .and_then(|metadata| metadata.modified())
+        .ok()
+        .and_then(|modified| modified.elapsed().ok())
+        .map(|age| age < ttl)
+        .unwrap_or(false)
+}
+
+fn write_cache(path: &Path, content: &[u8]) -> io::Result<()> {
+    if let Some(dir) = path.parent() {
+        fs::create_dir_all(dir)?;
+    }
+    fs::write(path, content)
+}
+
+// The ttl can be controlled with $NODEUP_INDEX_TTL which is specified in seconds
+fn index_ttl() -> Duration {
+    seconds_from_env("NODEUP_INDEX_TTL", DEFAULT_INDEX_TTL)
+}
+
+// The ttl can be controlled with $NODEUP_LTS_TTL which is specified in seconds
+fn lts_ttl() -> Duration {
+    seconds_from_env("NODEUP_LTS_TTL", DEFAULT_LTS_TTL)
+}
+
+fn seconds_from_env(var: &str, default: Duration) -> Duration {
+    match env::var(var) {
+        Ok(value) => match value.parse() {
+            Ok(seconds) => Duration::from_secs(seconds),
+            Err(e) => {
+                warn!("Ignoring invalid ${} {:?}: {}", var, value, e);
+                default
+            }
+        },
+        Err(_) => default,
+    }
+}
+
+/// The max concurrency set in $NODEUP_MAX_CONCURRENCY. Values that aren't a positive number are
+/// ignored
+pub fn max_concurrency_from_env() -> Option<usize> {
+    let value = env::var(MAX_CONCURRENCY_VAR).ok()?;
+    match value.parse() {
+        Ok(0) => {
+            warn!("Ignoring ${} of 0", MAX_CONCURRENCY_VAR);
+            None
+        }
+        Ok(max_concurrency) => Some(max_concurrency),
+        Err(e) => {
+            warn!(
+                "Ignoring invalid ${} {:?}: {}",
+                MAX_CONCURRENCY_VAR, value, e
+            );
+            None
+        }
+    }
+}
+
+pub(crate) fn offline_from_env() -> bool {
+    env::var_os(OFFLINE_VAR).is_some_and(|value| !value.is_empty() && value != "0")
+}
+
+// Archives can take a while to download on a slow connection, so the client only limits how long
+// each read waits. Requests for the index and other metadata add an overall limit with
+// `request_timeout`
+pub(crate) fn http_client() -> Result<blocking::Client, RegistryError> {
+    build_client(
+        seconds_from_env("NODEUP_CONNECT_TIMEOUT", DEFAULT_CONNECT_TIMEOUT),
+        seconds_from_env("NODEUP_READ_TIMEOUT", DEFAULT_READ_TIMEOUT),
+    )
+}
+
+// The blocking client applies its timeout to every wait on the server, which makes it a read timeout
+fn build_client(
+    connect_timeout: Duration,
+    read_timeout: Duration,
+) -> Result<blocking::Client, RegistryError> {
+    blocking::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(connect_timeout)
+        .timeout(read_timeout)
+        .build()
+        .map_err(|source| RegistryError::Request { source })
+}
+
+/// How long a request for the index or other metadata can take from connecting to the end of the
+/// response
+pub(crate) fn request_timeout() -> Duration {
+    seconds_from_env("NODEUP_TIMEOUT", DEFAULT_TIMEOUT)
+}
+
+fn get(client: &blocking::Client, url: &str) -> Result<blocking::Response, RegistryError> {
+    client.get(url).send().map_err(request_error(url))
+}
+
+// Errors reading the body of a response don't know the url, so it's passed in to report timeouts
+pub(crate) fn request_error(url: &str) -> impl Fn(reqwest::Error) -> RegistryError + '_ {
+    move |source| match source.is_timeout() {
+        true => RegistryError::Timeout {
+            url: url.to_string(),
+        },
+        false => RegistryError::Request { source },
+    }
+}
+
+/// An async client for the node distribution registry, for embedding nodeup in tools that already run
+/// on tokio. It shares its urls and parsing with `Registry` but doesn't use the index or archive
+/// caches, every call goes to the registry.
+#[cfg(feature = "async")]
+pub struct AsyncRegistry {
+    client: reqwest::Client,
+    read_timeout: Duration,
+    base_url: String,
+    unofficial_base_url: String,
+    offline: bool,
+}
+
+#[cfg(feature = "async")]
+impl AsyncRegistry {
+    pub fn new() -> Result<Self, RegistryError> {
+        Ok(AsyncRegistry {
+            client: async_http_client()?,
+            read_timeout: seconds_from_env("NODEUP_READ_TIMEOUT", DEFAULT_READ_TIMEOUT),
+            base_url: BASE_URL.to_string(),
+            unofficial_base_url: UNOFFICIAL_BASE_URL.to_string(),
+            offline: offline_from_env(),
+        })
+    }
+
+    /// A registry serving official and unofficial builds from the given url, ex/ a mirror
+    pub fn with_base_url(base_url: &str) -> Result<Self, RegistryError> {
+        let base_url = with_trailing_slash(base_url);
+
+        Ok(AsyncRegistry {
+            client: async_http_client()?,
+            read_timeout: seconds_from_env("NODEUP_READ_TIMEOUT", DEFAULT_READ_TIMEOUT),
+            unofficial_base_url: base_url.clone(),
+            base_url,
+            offline: offline_from_env(),
+        })
+    }
+
+    /// Turns offline mode on or off, overriding $NODEUP_OFFLINE
+    pub fn with_offline(mut self, offline: bool) -> Self {
+        self.offline = offline;
+        self
+    }
+
+    // Unpacking is blocking work, so it's moved off of the async runtime
+    pub async fn download_node_toolchain(
+        &self,
+        location: &Path,
+        target: Target,
+    ) -> Result<DownloadStats, RegistryError> {
+        let url = node_download_url(&target, &self.base_url, &self.unofficial_base_url);
+        ensure_online(self.offline, &url)?;
+        debug!("Downloading node at url: {}", url);
+
+        let started = Instant::now();
+        let mut resp = self
+            .read(&url, self.client.get(&url).send())
+            .await?
+            .map_err(request_error(&url))?;
+        check_archive_status(resp.status(), &url, &target)?;
+        let mut archive = Vec::new();
+        while let Some(chunk) = self
+            .read(&url, resp.chunk())
+            .await?
+            .map_err(request_error(&url))?
+        {
+            archive.extend_from_slice(&chunk);
+        }
+        let stats = DownloadStats {
+            bytes: archive.len() as u64,
+            duration: started.elapsed(),
+        };
+
+        let location = location.to_path_buf();
+        tokio::task::spawn_blocking(move || {
+            install_archive(Cursor::new(archive), &location, &target)
+        })
+        .await
+        .expect("Unpacking the archive panicked")?;
+        Ok(stats)
+    }
+
+    // The async client can't limit each read on its own, so every wait on the server is wrapped to
+    // match the read timeout of the blocking client
+    async fn read<F: std::future::Future>(
+        &self,
+        url: &str,
+        wait: F,
+    ) -> Result<F::Output, RegistryError> {
+        tokio::time::timeout(self.read_timeout, wait)
+            .await
+            .map_err(|_| RegistryError::Timeout {
+                url: url.to_string(),
+            })
+    }
+
+    /// Returns the newest lts version
+    pub async fn get_latest_lts(&self) -> Result<Version, RegistryError> {
+        let url = format!("{}{}", self.base_url, INDEX_FILE_NAME);
+        ensure_online(self.offline, &url)?;
+        debug!("Fetching node index from: {}", url);
+
+        let resp = self
+            .client
+            .get(&url)
+            .timeout(request_timeout())
+            .send()
+            .await
+            .map_err(request_error(&url))?;
+        match resp.status() {
+            StatusCode::OK => {}
+            code => return Err(RegistryError::UnexpectedResult { url, code }),
+        }
+        let content = resp.bytes().await.map_err(request_error(&url))?;
+        parse_index(&content, &url).map(latest_lts)
+    }
+}
+
+#[cfg(feature = "async")]
+pub async fn download_node_toolchain_async(
+    location: &Path,
+    target: Target,
+) -> Result<DownloadStats, RegistryError> {
+    AsyncRegistry::new()?
+        .download_node_toolchain(location, target)
+        .await
+}
+
+#[cfg(feature = "async")]
+pub async fn get_latest_lts_async() -> Result<Version, RegistryError> {
+    AsyncRegistry::new()?.get_latest_lts().await
+}
+
+// Archive downloads get their read timeout from `AsyncRegistry::read` instead of the client
+#[cfg(feature = "async")]
+fn async_http_client() -> Result<reqwest::Client, RegistryError> {
+    reqwest::Client::builder()
+        .user_agent(USER_AGENT)
+        .connect_timeout(seconds_from_env(
+            "NODEUP_CONNECT_TIMEOUT",
+            DEFAULT_CONNECT_TIMEOUT,
+        ))
+        .build()
+        .map_err(|source| RegistryError::Request { source })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::target::OperatingSystem;
+    use crate::target::Version;
+    use std::fs;
+    use tempfile::tempdir;
+
+    const FIXTURE_TARBALL: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/node-v16.1.0-linux-x64.tar.gz"
+    );
+    const FIXTURE_TARBALL_18: &str = concat!(
+        env!("CARGO_MANIFEST_DIR"),
+        "/tests/fixtures/node-v18.0.0-linux-x64.tar.gz"
+    );
+    const FIXTURE_INDEX: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/index.json");
+    const INDEX_URL: &str = "https://nodejs.org/dist/index.json";
+
+    #[derive(Debug, PartialEq)]
+    struct MockRequest {
+        path: String,
+        user_agent: Option<String>,
+        if_none_match: Option<String>,
+    }
+
+    const MOCK_ETAG: &str = "mock-index";
+
+    // Serves one request per route until the returned handle is joined, recording the requests so
+    // tests can check which urls were hit and how. Routes are (path, status, body) and requests for
+    // any other path get a 404.
+    fn mock_registry(
+        mut routes: Vec<(&'static str, &'static str, Vec<u8>)>,
+    ) -> (String, std::thread::JoinHandle<Vec<MockRequest>>) {
+        use std::io::{BufRead, BufReader, Write};
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let mut requests = Vec::new();
+            for _ in 0..routes.len() {
+                let (stream, _) = listener.accept().unwrap();
+                let mut reader = BufReader::new(stream);
+                let mut request_line = String::new();
+                reader.read_line(&mut request_line).unwrap();
+                let path = request_line.split(' ').nth(1).unwrap_or("").to_string();
+                let mut user_agent = None;
+                let mut if_none_match = None;
+                let mut header = String::new();
+                while reader.read_line(&mut header).unwrap() > 2 {
+                    let mut parts = header.splitn(2, ':');
+                    if let (Some(name), Some(value)) = (parts.next(), parts.next()) {
+                        if name.eq_ignore_ascii_case("user-agent") {
+                            user_agent = Some(value.trim().to_string());
+                        } else if name.eq_ignore_ascii_case("if-none-match") {
+                            if_none_match = Some(value.trim().to_string());
+                        }
+                    }
+                    header.clear();
+                }
+                // Routes are used once so the same path can respond differently each time
+                let (status, body) = routes
+                    .iter()
+                    .position(|(route, _, _)| *route == path)
+                    .map(|position| {
+                        let (_, status, body) = routes.remove(position);
+                        (status, body)
+                    })
+                    .unwrap_or(("404 Not Found", Vec::new()));
+                requests.push(MockRequest {
+                    path,
+                    user_agent,
+                    if_none_match,
+                });
+
+                let mut stream = reader.into_inner();
+                write!(
+                    stream,
+                    "HTTP/1.1 {}\r\nETag: \"{}\"\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+                    status,
+                    MOCK_ETAG,
+                    body.len()
+                )
+                .unwrap();
+                stream.write_all(&body).unwrap();
+            }
+            requests
+        });
+        (base_url, server)
+    }
+
+    #[test]
+    fn download_index_from_mock_registry() {
+        let index = fs::read(FIXTURE_INDEX).unwrap();
+        let (base_url, server) = mock_registry(vec![("/index.json", "200 OK", index.clone())]);
+        let registry = Registry::with_base_url(&base_url).unwrap();
+
+        let downloaded = registry
+            .download_index(&registry.index_url(), None)
+            .unwrap();
+
+        match downloaded {
+            IndexResponse::Modified { content, etag } => {
+                assert_eq!(content, index);
+                assert_eq!(etag, Some(format!("\"{}\"", MOCK_ETAG)));
+            }
+            IndexResponse::NotModified => panic!("The index should have been sent"),
+        }
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/index.json");
+    }
+
+    #[test]
+    fn requests_identify_nodeup() {
+        let (base_url, server) = mock_registry(vec![("/index.json", "200 OK", b"[]".to_vec())]);
+        let registry = Registry::with_base_url(&base_url).unwrap();
+
+        registry
+            .download_index(&registry.index_url(), None)
+            .unwrap();
+
+        let requests = server.join().unwrap();
+        let expected = format!("nodeup/{}", env!("CARGO_PKG_VERSION"));
+        assert_eq!(requests[0].user_agent, Some(expected));
+    }
+
+    #[test]
+    fn download_toolchain_from_mock_registry() {
+        let tarball = fs::read(FIXTURE_TARBALL).unwrap();
+        let (base_url, server) = mock_registry(vec![(
+            "/v16.1.0/node-v16.1.0-linux-x64.tar.gz",
+            "200 OK",
+            tarball,
+        )]);
+        let archives = tempdir().unwrap();
+        let registry = Registry::with_base_url(base_url.trim_end_matches('/'))
+            .unwrap()
+            .with_archive_cache(archives.path());
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        let stats = registry
+            .download_node_toolchain(location.path(), target.clone())
+            .unwrap();
+
+        let node = location.path().join(target.to_string()).join("bin/node");
+        assert!(node.is_file());
+        let fixture_size = fs::metadata(FIXTURE_TARBALL).unwrap().len();
+        assert_eq!(stats.bytes, fixture_size);
+        let requests = server.join().unwrap();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].path, "/v16.1.0/node-v16.1.0-linux-x64.tar.gz");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn download_toolchain_async_from_mock_registry() {
+        let tarball = fs::read(FIXTURE_TARBALL).unwrap();
+        let (base_url, server) = mock_registry(vec![(
+            "/v16.1.0/node-v16.1.0-linux-x64.tar.gz",
+            "200 OK",
+            tarball,
+        )]);
+        let registry = AsyncRegistry::with_base_url(&base_url).unwrap();
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        let stats = registry
+            .download_node_toolchain(location.path(), target.clone())
+            .await
+            .unwrap();
+
+        let node = location.path().join(target.to_string()).join("bin/node");
+        assert!(node.is_file());
+        assert_eq!(stats.bytes, fs::metadata(FIXTURE_TARBALL).unwrap().len());
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].path, "/v16.1.0/node-v16.1.0-linux-x64.tar.gz");
+    }
+
+    #[cfg(feature = "async")]
+    #[tokio::test]
+    async fn stalled_async_download_times_out() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial")
+                .unwrap();
+            std::thread::sleep(Duration::from_secs(1));
+        });
+        let mut registry = AsyncRegistry::with_base_url(&base_url).unwrap();
+        registry.read_timeout = Duration::from_millis(200);
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        match registry
+            .download_node_toolchain(location.path(), target)
+            .await
+        {
+            Err(RegistryError::Timeout { url }) => assert!(url.starts_with(&base_url)),
+            other => panic!("Expected a timeout, got: {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn offline_install_makes_no_request() {
+        let (base_url, server) = mock_registry(vec![]);
+        let archives = tempdir().unwrap();
+        let registry = Registry::with_base_url(&base_url)
+            .unwrap()
+            .with_archive_cache(archives.path())
+            .with_offline(true);
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        match registry.download_node_toolchain(location.path(), target.clone()) {
+            Err(RegistryError::Offline { url }) => {
+                assert_eq!(url, registry.node_download_url(&target))
+            }
+            other => panic!("Expected an offline error, got: {:?}", other),
+        }
+        assert!(server.join().unwrap().is_empty());
+        assert!(!location.path().join(target.to_string()).exists());
+    }
+
+    #[test]
+    fn download_many_from_mock_registry() {
+        let (base_url, server) = mock_registry(vec![
+            (
+                "/v16.1.0/node-v16.1.0-linux-x64.tar.gz",
+                "200 OK",
+                fs::read(FIXTURE_TARBALL).unwrap(),
+            ),
+            (
+                "/v18.0.0/node-v18.0.0-linux-x64.tar.gz",
+                "200 OK",
+                fs::read(FIXTURE_TARBALL_18).unwrap(),
+            ),
+            (
+                "/v20.0.0/node-v20.0.0-linux-x64.tar.gz",
+                "404 Not Found",
+                Vec::new(),
+            ),
+        ]);
+        let archives = tempdir().unwrap();
+        let registry = Registry::with_base_url(&base_url)
+            .unwrap()
+            .with_archive_cache(archives.path());
+        let location = tempdir().unwrap();
+        let targets: Vec<Target> = vec![
+            "node-v16.1.0-linux-x64",
+            "node-v20.0.0-linux-x64",
+            "node-v18.0.0-linux-x64",
+        ]
+        .into_iter()
+        .map(|target| target.parse().unwrap())
+        .collect();
+
+        let results = registry.download_many(location.path(), targets.clone(), 2);
+        server.join().unwrap();
+
+        let installed: Vec<&Target> = results.iter().map(|(target, _)| target).collect();
+        assert_eq!(installed, targets.iter().collect::<Vec<_>>());
+        assert!(results[0].1.is_ok());
+        assert!(matches!(
+            results[1].1,
+            Err(RegistryError::InvalidTarget { .. })
+        ));
+        assert!(results[2].1.is_ok());
+        for target in &[&targets[0], &targets[2]] {
+            let node = location.path().join(target.to_string()).join("bin/node");
+            assert!(node.is_file());
+        }
+        assert_eq!(fs::read_dir(location.path()).unwrap().count(), 2);
+    }
+
+    // Answers every request with a 404 after a pause, from a thread per connection, and returns the
+    // most requests that were being handled at once
+    fn counting_registry(requests: usize) -> (String, std::thread::JoinHandle<usize>) {
+        use std::io::{BufRead, BufReader, Write};
+        use std::sync::{
+            atomic::{AtomicUsize, Ordering},
+            Arc,
+        };
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let base_url = format!("http://{}/", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let active = Arc::new(AtomicUsize::new(0));
+            let most = Arc::new(AtomicUsize::new(0));
+            let handlers: Vec<_> = (0..requests)
+                .map(|_| {
+                    let (stream, _) = listener.accept().unwrap();
+                    let (active, most) = (active.clone(), most.clone());
+                    std::thread::spawn(move || {
+                        let now = active.fetch_add(1, Ordering::SeqCst) + 1;
+                        most.fetch_max(now, Ordering::SeqCst);
+                        let mut reader = BufReader::new(stream);
+                        let mut line = String::new();
+                        while reader.read_line(&mut line).unwrap() > 2 {
+                            line.clear();
+                        }
+                        std::thread::sleep(Duration::from_millis(100));
+                        active.fetch_sub(1, Ordering::SeqCst);
+                        let mut stream = reader.into_inner();
+                        write!(
+                            stream,
+                            "HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\nConnection: close\r\n\r\n"
+                        )
+                        .unwrap();
+                    })
+                })
+                .collect();
+            handlers
+                .into_iter()
+                .for_each(|handler| handler.join().unwrap());
+            most.load(Ordering::SeqCst)
+        });
+        (base_url, server)
+    }
+
+    #[test]
+    fn download_many_respects_max_concurrency() {
+        let versions = ["14.0.0", "15.0.0", "16.0.0", "17.0.0", "18.0.0"];
+        let (base_url, server) = counting_registry(versions.len());
+        let archives = tempdir().unwrap();
+        let registry = Registry::with_base_url(&base_url)
+            .unwrap()
+            .with_archive_cache(archives.path())
+            .with_max_concurrency(2);
+        let location = tempdir().unwrap();
+        let targets = versions
+            .iter()
+            .map(|version| format!("node-v{}-linux-x64", version).parse().unwrap())
+            .collect();
+
+        let results = registry.download_many(location.path(), targets, 10);
+        assert_eq!(results.len(), versions.len());
+        assert_eq!(server.join().unwrap(), 2);
+    }
+
+    #[test]
+    fn cached_archive_is_reused() {
+        let tarball = fs::read(FIXTURE_TARBALL).unwrap();
+        let (base_url, server) = mock_registry(vec![(
+            "/v16.1.0/node-v16.1.0-linux-x64.tar.gz",
+            "200 OK",
+            tarball,
+        )]);
+        let archives = tempdir().unwrap();
+        let registry = Registry::with_base_url(&base_url)
+            .unwrap()
+            .with_archive_cache(archives.path());
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        let first = tempdir().unwrap();
+        let downloaded = registry
+            .download_node_toolchain(first.path(), target.clone())
+            .unwrap();
+        // The mock registry stops listening after its only route was requested
+        assert_eq!(server.join().unwrap().len(), 1);
+        let second = tempdir().unwrap();
+        let cached = registry
+            .download_node_toolchain(second.path(), target.clone())
+            .unwrap();
+
+        let node = second.path().join(target.to_string()).join("bin/node");
+        assert!(node.is_file());
+        assert!(archives
+            .path()
+            .join("node-v16.1.0-linux-x64.tar.gz.sha256")
+            .is_file());
+        assert_ne!(downloaded.bytes, 0);
+        assert_eq!(cached, DownloadStats::default());
+    }
+
+    #[test]
+    fn corrupt_cached_archive_is_ignored() {
+        let archives = tempdir().unwrap();
+        let archive = archives.path().join("node-v16.1.0-linux-x64.tar.gz");
+        fs::copy(FIXTURE_TARBALL, &archive).unwrap();
+        fs::write(checksum_path(&archive), sha256_file(&archive).unwrap()).unwrap();
+        assert!(is_cached_archive_intact(&archive));
+
+        fs::write(&archive, "truncated").unwrap();
+        assert!(!is_cached_archive_intact(&archive));
+    }
+
+    #[test]
+    fn missing_toolchain_from_mock_registry() {
+        let (base_url, server) = mock_registry(vec![("/missing", "404 Not Found", Vec::new())]);
+        let archives = tempdir().unwrap();
+        let registry = Registry::with_base_url(&base_url)
+            .unwrap()
+            .with_archive_cache(archives.path());
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        match registry.download_node_toolchain(location.path(), target) {
+            Err(RegistryError::InvalidTarget { .. }) => {}
+            other => panic!("Expected an invalid target, got: {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn slow_server_times_out() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/index.json", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (_stream, _) = listener.accept().unwrap();
+            std::thread::sleep(Duration::from_secs(1));
+        });
+
+        let client = build_client(Duration::from_secs(1), Duration::from_millis(200)).unwrap();
+        match get(&client, &url) {
+            Err(RegistryError::Timeout { url: timed_out }) => assert_eq!(timed_out, url),
+            other => panic!("Expected a timeout, got: {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn stalled_body_times_out() {
+        use std::io::Write;
+
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}/node.tar.gz", listener.local_addr().unwrap());
+        let server = std::thread::spawn(move || {
+            let (mut stream, _) = listener.accept().unwrap();
+            let mut request = [0; 1024];
+            let _ = stream.read(&mut request).unwrap();
+            stream
+                .write_all(b"HTTP/1.1 200 OK\r\nContent-Length: 100\r\n\r\npartial")
+                .unwrap();
+            std::thread::sleep(Duration::from_secs(1));
+        });
+
+        let client = build_client(Duration::from_secs(1), Duration::from_millis(200)).unwrap();
+        let mut resp = get(&client, &url).unwrap();
+        match resp.copy_to(&mut io::sink()).map_err(request_error(&url)) {
+            Err(RegistryError::Timeout { url: timed_out }) => assert_eq!(timed_out, url),
+            other => panic!("Expected a timeout, got: {:?}", other),
+        }
+        server.join().unwrap();
+    }
+
+    #[test]
+    fn install_from_local_tarball() {
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+        install_from_file(location.path(), Path::new(FIXTURE_TARBALL), &target).unwrap();
+
+        let node = location.path().join(target.to_string()).join("bin/node");
+        assert!(node.is_file());
+        let entries: Vec<_> = fs::read_dir(location.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[test]
+    fn install_from_mismatched_tarball() {
+        let location = tempdir().unwrap();
+        let target: Target = "node-v18.0.0-linux-x64".parse().unwrap();
+
+        let result = install_from_file(location.path(), Path::new(FIXTURE_TARBALL), &target);
+        match result {
+            Err(RegistryError::ArchiveMismatch { found, .. }) => {
+                assert_eq!(found, vec!["node-v16.1.0-linux-x64"])
+            }
+            other => panic!("Expected an archive mismatch, got: {:?}", other),
+        }
+        assert_eq!(fs::read_dir(location.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn mislabeled_download_is_rejected() {
+        let location = tempdir().unwrap();
+        let target: Target = "node-v18.0.0-linux-x64".parse().unwrap();
+        let archive = fs::File::open(FIXTURE_TARBALL).unwrap();
+
+        match install_archive(archive, location.path(), &target) {
+            Err(RegistryError::UnexpectedArchiveLayout { target: found, .. }) => {
+                assert_eq!(found, target)
+            }
+            other => panic!("Expected an unexpected layout, got: {:?}", other),
+        }
+        assert_eq!(fs::read_dir(location.path()).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn create_node_url() {
+        let version = Version {
+            major: 12,
+            minor: 9,
+            patch: 1,
+            prerelease: None,
+        };
+
+        let actual = Registry::new()
+            .unwrap()
+            .node_download_url(&Target::from_version(version));
+
+        #[cfg(target_os = "linux")]
+        let expected = "https://nodejs.org/dist/v12.9.1/node-v12.9.1-linux-x64.tar.gz";
+
+        #[cfg(target_os = "macos")]
+        let expected = "https://nodejs.org/dist/v12.9.1/node-v12.9.1-darwin-x64.tar.gz";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn create_node_url_for_os() {
+        let version = Version {
+            major: 12,
+            minor: 9,
+            patch: 1,
+            prerelease: None,
+        };
+
+        let actual = Registry::new()
+            .unwrap()
+            .node_download_url(&Target::new(OperatingSystem::Windows, version.clone()));
+        let expected = "https://nodejs.org/dist/v12.9.1/node-v12.9.1-win-x64.zip";
+        assert_eq!(actual, expected);
+
+        let actual = Registry::new()
+            .unwrap()
+            .node_download_url(&Target::new(OperatingSystem::Linux, version.clone()));
+        let expected = "https://nodejs.org/dist/v12.9.1/node-v12.9.1-linux-x64.tar.gz";
+        assert_eq!(actual, expected);
+
+        let musl = Target::new(OperatingSystem::Linux, version).with_libc(Libc::Musl);
+        let actual = Registry::new().unwrap().node_download_url(&musl);
+        let expected = "https://unofficial-builds.nodejs.org/download/release/v12.9.1/node-v12.9.1-linux-x64-musl.tar.gz";
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    #[ignore] // Take a little too long to run
+    fn download_node_to_temp_dir() {
+        let temp_dir = tempdir().unwrap();
+        let path = temp_dir.path();
+
+        let target = Target::new(
+            OperatingSystem::Linux,
+            Version {
+                major: 12,
+                minor: 0,
+                patch: 0,
+                prerelease: None,
+            },
+        );
+
+        download_node_toolchain(path, target).unwrap();
+
+        let downloaded_path = path.join("node-v12.0.0-linux-x64");
+        fs::read_dir(downloaded_path).unwrap();
+    }
+
+    #[test]
+    fn latest_lts() {
+        get_latest_lts().unwrap();
+    }
+
+    const INDEX: &str = include_str!("../tests/fixtures/index.json");
+
+    #[test]
+    fn fresh_index_cache_is_reused() {
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(INDEX_FILE_NAME);
+        fs::write(&cache_file, INDEX).unwrap();
+
+        let index = load_index(&cache_file, INDEX_URL, DEFAULT_INDEX_TTL, false, |_| {
+            panic!("The cached index should have been used")
+        })
+        .unwrap();
+        assert_eq!(index.len(), 13);
+    }
+
+    fn modified(content: &str) -> IndexResponse {
+        IndexResponse::Modified {
+            content: content.as_bytes().to_vec(),
+            etag: None,
+        }
+    }
+
+    #[test]
+    fn unchanged_index_is_reused() {
+        let index = fs::read(FIXTURE_INDEX).unwrap();
+        let (base_url, server) = mock_registry(vec![
+            ("/index.json", "200 OK", index),
+            ("/index.json", "304 Not Modified", Vec::new()),
+        ]);
+        let registry = Registry::with_base_url(&base_url).unwrap();
+        let url = registry.index_url();
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(INDEX_FILE_NAME);
+
+        let fetched = load_index(&cache_file, &url, DEFAULT_INDEX_TTL, true, |etag| {
+            registry.download_index(&url, etag)
+        })
+        .unwrap();
+        let reused = load_index(&cache_file, &url, DEFAULT_INDEX_TTL, true, |etag| {
+            registry.download_index(&url, etag)
+        })
+        .unwrap();
+
+        let requests = server.join().unwrap();
+        assert_eq!(requests[0].if_none_match, None);
+        assert_eq!(
+            requests[1].if_none_match,
+            Some(format!("\"{}\"", MOCK_ETAG))
+        );
+        assert_eq!(fetched.len(), 13);
+        assert_eq!(reused.len(), 13);
+        assert!(is_fresh(&cache_file, DEFAULT_INDEX_TTL));
+    }
+
+    #[test]
+    fn stale_index_cache_is_refetched() {
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(INDEX_FILE_NAME);
+        fs::write(&cache_file, "[]").unwrap();
+
+        let index = load_index(
+            &cache_file,
+            INDEX_URL,
+            Duration::from_secs(0),
+            false,
+            |_| Ok(modified(INDEX)),
+        )
+        .unwrap();
+        assert_eq!(index.len(), 13);
+        assert_eq!(fs::read_to_string(&cache_file).unwrap(), INDEX);
+    }
+
+    #[test]
+    fn list_available_versions() {
+        let index = serde_json::from_str(INDEX).unwrap();
+        let versions = available_versions(index, false);
+        assert_eq!(versions.len(), 13);
+        assert_eq!(versions.first(), Some(&Version::parse("v20.5.0").unwrap()));
+        assert_eq!(versions.last(), Some(&Version::parse("v10.24.1").unwrap()));
+        assert!(versions.windows(2).all(|pair| pair[0] > pair[1]));
+    }
+
+    #[test]
+    fn list_available_lts_versions() {
+        let index = serde_json::from_str(INDEX).unwrap();
+        let actual = available_versions(index, true);
+        let expected: Vec<Version> = vec![
+            "v18.17.0",
+            "v18.16.1",
+            "v16.20.1",
+            "v16.20.0",
+            "v14.21.3",
+            "v14.21.2",
+            "v12.22.12",
+            "v12.18.3",
+            "v10.24.1",
+        ]
+        .into_iter()
+        .map(|v| Version::parse(v).unwrap())
+        .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn lts_codename() {
+        let index = serde_json::from_str(INDEX).unwrap();
+        let actual = lts_by_codename(index, "gallium").unwrap();
+        assert_eq!(actual, Version::parse("v16.20.1").unwrap());
+
+        let index = serde_json::from_str(INDEX).unwrap();
+        let actual = lts_by_codename(index, "Hydrogen").unwrap();
+        assert_eq!(actual, Version::parse("v18.17.0").unwrap());
+    }
+
+    #[test]
+    fn latest_skips_prereleases() {
+        let mut index: Vec<AvailableVersion> = serde_json::from_str(INDEX).unwrap();
+        for version in &["v21.0.0-rc.1", "v21.0.0-nightly20230801d4d7ae2d1b"] {
+            index.push(AvailableVersion {
+                version: version.to_string(),
+                lts: LTSVersion::No(false),
+            });
+        }
+
+        assert_eq!(
+            latest_stable(index).unwrap(),
+            Version::parse("v20.5.0").unwrap()
+        );
+
+        let prereleases = serde_json::from_str(r#"[{"version": "v21.0.0-rc.1", "lts": false}]"#);
+        assert!(matches!(
+            latest_stable(prereleases.unwrap()),
+            Err(RegistryError::NoMatchingVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn lts_lines_by_codename() {
+        let index = serde_json::from_str(INDEX).unwrap();
+        let actual = lts_lines_from(index);
+        let expected: Vec<(String, Version)> = vec![
+            ("dubnium", "v10.24.1"),
+            ("erbium", "v12.22.12"),
+            ("fermium", "v14.21.3"),
+            ("gallium", "v16.20.1"),
+            ("hydrogen", "v18.17.0"),
+        ]
+        .into_iter()
+        .map(|(name, version)| (name.to_string(), Version::parse(version).unwrap()))
+        .collect();
+        assert_eq!(actual, expected);
+    }
+
+    #[test]
+    fn malformed_lts_entry_is_skipped() {
+        let index = serde_json::from_str(
+            r#"[
+                {"version": "v18.17.0", "lts": "Hydrogen"},
+                {"version": "eighteen", "lts": "Hydrogen"}
+            ]"#,
+        )
+        .unwrap();
+        let expected = vec![(
+            String::from("hydrogen"),
+            Version::parse("v18.17.0").unwrap(),
+        )];
+        assert_eq!(lts_lines_from(index), expected);
+    }
+
+    #[test]
+    fn malformed_codename_entry_is_skipped() {
+        let index = serde_json::from_str(
+            r#"[
+                {"version": "sixteen", "lts": "Gallium"},
+                {"version": "v16.20.1", "lts": "Gallium"}
+            ]"#,
+        )
+        .unwrap();
+        assert_eq!(
+            lts_by_codename(index, "gallium").unwrap(),
+            Version::parse("v16.20.1").unwrap()
+        );
+    }
+
+    #[test]
+    fn unknown_lts_codename() {
+        let index = serde_json::from_str(INDEX).unwrap();
+        match lts_by_codename(index, "argon") {
+            Err(RegistryError::UnknownCodename {
+                codename,
+                available,
+            }) => {
+                assert_eq!(codename, "argon");
+                assert_eq!(
+                    available,
+                    vec!["hydrogen", "gallium", "fermium", "erbium", "dubnium"]
+                );
+            }
+            other => panic!("Expected an unknown codename error, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn resolve_version_reqs() {
+        let resolve = |req| {
+            let index = serde_json::from_str(INDEX).unwrap();
+            newest_matching(index, &VersionReq::parse(req).unwrap())
+        };
+
+        assert_eq!(resolve("16").unwrap(), Version::parse("v16.20.1").unwrap());
+        assert_eq!(
+            resolve("16.x").unwrap(),
+            Version::parse("v16.20.1").unwrap()
+        );
+        assert_eq!(
+            resolve("~18.16").unwrap(),
+            Version::parse("v18.16.1").unwrap()
+        );
+        assert_eq!(
+            resolve("^18.2").unwrap(),
+            Version::parse("v18.17.0").unwrap()
+        );
+        assert_eq!(
+            resolve("^12").unwrap(),
+            Version::parse("v12.22.12").unwrap()
+        );
+        assert!(matches!(
+            resolve("11"),
+            Err(RegistryError::NoMatchingVersion { .. })
+        ));
+    }
+
+    #[test]
+    fn resolve_major_and_minor_shorthands() {
+        let resolve = |req| {
+            let index = serde_json::from_str(INDEX).unwrap();
+            newest_matching(index, &VersionReq::parse(req).unwrap()).unwrap()
+        };
+
+        assert_eq!(resolve("18"), Version::parse("v18.17.0").unwrap());
+        assert_eq!(resolve("18.16"), Version::parse("v18.16.1").unwrap());
+        assert_eq!(resolve("v18.16"), Version::parse("v18.16.1").unwrap());
+        assert_eq!(resolve("16.20"), Version::parse("v16.20.1").unwrap());
+        // Only the shorthands go to the index, a full version is installed as given
+        assert!(Version::parse("18.16").is_err());
+    }
+
+    #[test]
+    fn offline_uses_stale_index_cache() {
+        let (base_url, server) = mock_registry(vec![]);
+        let registry = Registry::with_base_url(&base_url)
+            .unwrap()
+            .with_offline(true);
+        let url = registry.index_url();
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(INDEX_FILE_NAME);
+        fs::write(&cache_file, INDEX).unwrap();
+
+        let index = load_index(&cache_file, &url, Duration::from_secs(0), false, |etag| {
+            registry.download_index(&url, etag)
+        })
+        .unwrap();
+        assert_eq!(index.len(), 13);
+        assert!(server.join().unwrap().is_empty());
+
+        fs::remove_file(&cache_file).unwrap();
+        let uncached = load_index(&cache_file, &url, DEFAULT_INDEX_TTL, false, |etag| {
+            registry.download_index(&url, etag)
+        });
+        assert!(matches!(uncached, Err(RegistryError::Offline { .. })));
+    }
+
+    #[test]
+    fn refresh_ignores_fresh_index_cache() {
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(INDEX_FILE_NAME);
+        fs::write(&cache_file, "[]").unwrap();
+
+        let index = load_index(&cache_file, INDEX_URL, DEFAULT_INDEX_TTL, true, |_| {
+            Ok(modified(INDEX))
+        })
+        .unwrap();
+        assert_eq!(index.len(), 13);
+    }
+
+    #[test]
+    fn fresh_lts_cache_is_used() {
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(LTS_FILE_NAME);
+        fs::write(&cache_file, "14.17.0\n").unwrap();
+
+        let version = load_latest_lts(&cache_file, DEFAULT_LTS_TTL, || {
+            panic!("The cached lts version should have been used")
+        })
+        .unwrap();
+        assert_eq!(version, "14.17.0".parse().unwrap());
+    }
+
+    #[test]
+    fn stale_lts_cache_is_recomputed() {
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join(LTS_FILE_NAME);
+        fs::write(&cache_file, "14.17.0").unwrap();
+
+        let version = load_latest_lts(&cache_file, Duration::from_secs(0), || {
+            let index = serde_json::from_str(INDEX).unwrap();
+            Ok(super::latest_lts(index))
+        })
+        .unwrap();
+        assert_eq!(version, "18.17.0".parse().unwrap());
+        assert_eq!(fs::read_to_string(&cache_file).unwrap(), "v18.17.0");
+    }
+
+    #[test]
+    fn corrupt_lts_cache_is_recomputed() {
+        let cache_dir = tempdir().unwrap();
+        let cache_file = cache_dir.path().join("nested").join(LTS_FILE_NAME);
+        fs::create_dir_all(cache_file.parent().unwrap()).unwrap();
+        fs::write(&cache_file, "not a version").unwrap();
+
+        let version = load_latest_lts(&cache_file, DEFAULT_LTS_TTL, || {
+            Ok("18.17.0".parse().unwrap())
+        })
+        .unwrap();
+        assert_eq!(version, "18.17.0".parse().unwrap());
+        assert_eq!(fs::read_to_string(&cache_file).unwrap(), "v18.17.0");
+    }
+
+    #[test]
+    fn corrupt_download_leaves_nothing_behind() {
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        let tarball = fs::read(FIXTURE_TARBALL).unwrap();
+        let truncated = &tarball[..tarball.len() / 2];
+
+        assert!(install_archive(truncated, location.path(), &target).is_err());
+        assert!(install_archive(&b"not a tarball"[..], location.path(), &target).is_err());
+        assert_eq!(fs::read_dir(location.path()).unwrap().count(), 0);
+    }
+
+    // tar::Builder refuses to write paths containing .. so the name is written into the header directly
+    fn tarball_with_entry(name: &str) -> Vec<u8> {
+        let mut header = tar::Header::new_old();
+        header.as_old_mut().name[..name.len()].copy_from_slice(name.as_bytes());
+        header.set_size(4);
+        header.set_mode(0o644);
+        header.set_entry_type(tar::EntryType::Regular);
+        header.set_cksum();
+
+        let mut builder = tar::Builder::new(flate2::write::GzEncoder::new(
+            Vec::new(),
+            flate2::Compression::default(),
+        ));
+        builder.append(&header, &b"evil"[..]).unwrap();
+        builder.into_inner().unwrap().finish().unwrap()
+    }
+
+    #[test]
+    fn unpack_reports_progress() {
+        let dir = tempdir().unwrap();
+        let archive = fs::File::open(FIXTURE_TARBALL).unwrap();
+        let mut reported = Vec::new();
+        let extracted =
+            unpack_with_progress(archive, dir.path(), |count| reported.push(count)).unwrap();
+
+        assert!(extracted > 0);
+        assert_eq!(reported, (1..=extracted).collect::<Vec<_>>());
+        assert!(dir.path().join("node-v16.1.0-linux-x64/bin/node").is_file());
+    }
+
+    #[test]
+    fn parent_dir_entry_is_rejected() {
+        let dir = tempdir().unwrap();
+        let location = dir.path().join("versions");
+        fs::create_dir(&location).unwrap();
+        let archive = tarball_with_entry("../evil");
+
+        match unpack(Cursor::new(archive), &location) {
+            Err(RegistryError::UnsafeArchivePath { entry }) => {
+                assert_eq!(entry, PathBuf::from("../evil"))
+            }
+            other => panic!("Expected the entry to be rejected, got: {:?}", other),
+        }
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn escaping_entries_are_rejected() {
+        for name in &["../../.bashrc", "node/../../evil", "/tmp/evil"] {
+            let dir = tempdir().unwrap();
+            let archive = tarball_with_entry(name);
+            assert!(
+                matches!(
+                    unpack(Cursor::new(archive), dir.path()),
+                    Err(RegistryError::UnsafeArchivePath { .. })
+                ),
+                "{} should be rejected",
+                name
+            );
+        }
+
+        let dir = tempdir().unwrap();
+        assert!(unpack(Cursor::new(tarball_with_entry("node/../bin")), dir.path()).is_ok());
+    }
+
+    #[test]
+    fn escaping_zip_entry_is_rejected() {
+        use std::io::Write;
+
+        let mut zip = zip::ZipWriter::new(Cursor::new(Vec::new()));
+        zip.start_file("../evil", zip::write::FileOptions::default())
+            .unwrap();
+        zip.write_all(b"evil").unwrap();
+        let archive = zip.finish().unwrap().into_inner();
+
+        let dir = tempdir().unwrap();
+        let location = dir.path().join("versions");
+        fs::create_dir(&location).unwrap();
+        match unpack_zip(Cursor::new(archive), &location) {
+            Err(RegistryError::UnsafeArchivePath { entry }) => {
+                assert_eq!(entry, PathBuf::from("../evil"))
+            }
+            other => panic!("Expected the entry to be rejected, got: {:?}", other),
+        }
+        assert!(!dir.path().join("evil").exists());
+    }
+
+    #[test]
+    fn install_over_existing_version() {
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        let tarball = fs::read(FIXTURE_TARBALL).unwrap();
+
+        install_archive(&tarball[..], location.path(), &target).unwrap();
+        install_archive(&tarball[..], location.path(), &target).unwrap();
+
+        let entries: Vec<_> = fs::read_dir(location.path()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+        assert!(location
+            .path()
+            .join(target.to_string())
+            .join("bin/node")
+            .is_file());
+    }
+
+    #[test]
+    fn install_over_broken_version() {
+        let location = tempdir().unwrap();
+        let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+        let broken = location.path().join(target.to_string());
+        fs::create_dir_all(broken.join("lib")).unwrap();
+        fs::write(broken.join("lib/leftover"), "partial").unwrap();
+        let tarball = fs::read(FIXTURE_TARBALL).unwrap();
+
+        install_archive(&tarball[..], location.path(), &target).unwrap();
+
+        assert!(broken.join("bin/node").is_file());
+        assert!(!broken.join("lib/leftover").exists());
+        assert!(crate::verify_install_at(location.path(), &target));
     }
 }
@@ -0,0 +1,319 @@
+use log::debug;
+use reqwest::{blocking, StatusCode};
+use serde::Deserialize;
+use std::{
+    env, fs, io,
+    path::{Path, PathBuf},
+    process::Command,
+};
+use thiserror::Error;
+
+use crate::{
+    registry::{self, RegistryError},
+    target::{Architecture, OperatingSystem, Version, VersionError},
+};
+
+const LATEST_RELEASE_URL: &str = "https://api.github.com/repos/monkslc/nodeup/releases/latest";
+
+#[derive(Debug, Error)]
+pub enum SelfUpdateError {
+    #[error(transparent)]
+    Registry(#[from] RegistryError),
+
+    #[error("The release {tag:?} isn't a version nodeup understands: {source}")]
+    InvalidTag { tag: String, source: VersionError },
+
+    #[error("Unexpected response from {url:?}: {source}")]
+    UnexpectedResponse {
+        source: serde_json::Error,
+        url: String,
+    },
+
+    #[error("Release {tag} doesn't include a build for this machine. Looked for: {}", names.join(", "))]
+    NoAsset { tag: String, names: Vec<String> },
+
+    #[error("Error replacing nodeup at {path:?}: {source}")]
+    IO { source: io::Error, path: PathBuf },
+
+    #[error("The nodeup downloaded from {url:?} doesn't run on this machine: {reason}")]
+    BrokenDownload { url: String, reason: String },
+}
+
+#[derive(Debug, Deserialize)]
+struct Release {
+    tag_name: String,
+    assets: Vec<Asset>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Asset {
+    name: String,
+    browser_download_url: String,
+}
+
+/// What `update` did
+#[derive(Debug, PartialEq)]
+pub enum Updated {
+    AlreadyLatest(Version),
+    Updated { from: Version, to: Version },
+}
+
+/// The version of nodeup that's running
+pub fn current_version() -> Version {
+    Version::parse(env!("CARGO_PKG_VERSION")).expect("The crate version is always a valid version")
+}
+
+/// Whether the release tagged `tag`, ex/ v0.0.8, is newer than the running version
+pub fn is_update(current: &Version, tag: &str) -> Result<bool, SelfUpdateError> {
+    let latest = Version::parse(tag).map_err(|source| SelfUpdateError::InvalidTag {
+        tag: tag.to_string(),
+        source,
+    })?;
+    Ok(latest > *current)
+}
+
+/// Replaces the running nodeup with the latest release when it's newer
+pub fn update() -> Result<Updated, SelfUpdateError> {
+    registry::ensure_online(registry::offline_from_env(), LATEST_RELEASE_URL)?;
+    let client = registry::http_client()?;
+
+    let current = current_version();
+    let release = latest_release(&client)?;
+    if !is_update(&current, &release.tag_name)? {
+        return Ok(Updated::AlreadyLatest(current));
+    }
+
+    let names = asset_names(OperatingSystem::default(), Architecture::default());
+    let asset = names
+        .iter()
+        .find_map(|name| release.assets.iter().find(|asset| asset.name == *name))
+        .ok_or_else(|| SelfUpdateError::NoAsset {
+            tag: release.tag_name.clone(),
+            names: names.clone(),
+        })?;
+
+    let nodeup = env::current_exe().map_err(|source| SelfUpdateError::IO {
+        source,
+        path: PathBuf::from("Looking for current executable"),
+    })?;
+    replace_executable(&client, &asset.browser_download_url, &nodeup)?;
+
+    Ok(Updated::Updated {
+        from: current,
+        to: Version::parse(&release.tag_name).expect("Checked by is_update"),
+    })
+}
+
+fn latest_release(client: &blocking::Client) -> Result<Release, SelfUpdateError> {
+    debug!(
+        "Fetching the latest nodeup release from: {}",
+        LATEST_RELEASE_URL
+    );
+    let resp = client
+        .get(LATEST_RELEASE_URL)
+        .timeout(registry::request_timeout())
+        .send()
+        .map_err(registry::request_error(LATEST_RELEASE_URL))?;
+    check_status(resp.status(), LATEST_RELEASE_URL)?;
+    let content = resp
+        .bytes()
+        .map_err(registry::request_error(LATEST_RELEASE_URL))?;
+    serde_json::from_slice(&content).map_err(|source| SelfUpdateError::UnexpectedResponse {
+        source,
+        url: LATEST_RELEASE_URL.to_string(),
+    })
+}
+
+// Builds for an architecture are named after it, ex/ nodeup-linux-arm64. The builds published so far
+// are only named after the os and come from the deploy runners, x64 linux and arm64 macos, so those
+// names only fit machines with the same architecture
+fn asset_names(os: OperatingSystem, arch: Architecture) -> Vec<String> {
+    let os = match os {
+        OperatingSystem::Darwin => String::from("macos"),
+        OperatingSystem::Windows => String::from("windows"),
+        os => os.to_string(),
+    };
+    let suffix = match cfg!(windows) {
+        true => ".exe",
+        false => "",
+    };
+    let mut names = vec![format!("nodeup-{}-{}{}", os, arch, suffix)];
+    let runner_arch = match os.as_str() {
+        "linux" => Some(Architecture::X64),
+        "macos" => Some(Architecture::Arm64),
+        _ => None,
+    };
+    if runner_arch == Some(arch) {
+        names.push(format!("nodeup-{}{}", os, suffix));
+    }
+    names
+}
+
+// The new executable is written next to the old one and renamed over it so nodeup is never left
+// half written. It has to run before it replaces anything so a build for the wrong machine or a
+// truncated download doesn't leave nodeup unusable. A running executable can be renamed on every
+// platform but only replaced in place on unix, so on Windows the old one is moved aside first
+fn replace_executable(
+    client: &blocking::Client,
+    url: &str,
+    nodeup: &Path,
+) -> Result<(), SelfUpdateError> {
+    debug!("Downloading nodeup from: {}", url);
+    let mut resp = client
+        .get(url)
+        .timeout(registry::request_timeout())
+        .send()
+        .map_err(registry::request_error(url))?;
+    check_status(resp.status(), url)?;
+
+    let dir = nodeup.parent().unwrap_or_else(|| Path::new("."));
+    let io_error = |source| SelfUpdateError::IO {
+        source,
+        path: dir.to_path_buf(),
+    };
+    let mut staged = tempfile::Builder::new()
+        .suffix(env::consts::EXE_SUFFIX)
+        .tempfile_in(dir)
+        .map_err(io_error)?;
+    resp.copy_to(&mut staged)
+        .map_err(registry::request_error(url))?;
+
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(staged.path(), fs::Permissions::from_mode(0o755)).map_err(io_error)?;
+    }
+
+    // Closes the file so it can be run
+    let staged = staged.into_temp_path();
+    check_runs(&staged, url)?;
+
+    #[cfg(windows)]
+    {
+        let old = nodeup.with_extension("exe.old");
+        let _ = fs::remove_file(&old);
+        fs::rename(nodeup, &old).map_err(|source| SelfUpdateError::IO {
+            source,
+            path: nodeup.to_path_buf(),
+        })?;
+    }
+
+    staged.persist(nodeup).map_err(|e| SelfUpdateError::IO {
+        source: e.error,
+        path: nodeup.to_path_buf(),
+    })?;
+    Ok(())
+}
+
+// A working build prints its name and version, ex/ nodeup 0.0.8
+fn check_runs(nodeup: &Path, url: &str) -> Result<(), SelfUpdateError> {
+    let broken = |reason: String| SelfUpdateError::BrokenDownload {
+        url: url.to_string(),
+        reason,
+    };
+    let output = Command::new(nodeup)
+        .arg("--version")
+        .output()
+        .map_err(|e| broken(e.to_string()))?;
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    match output.status.success() && stdout.starts_with("nodeup ") {
+        true => {
+            debug!("Downloaded {}", stdout.trim());
+            Ok(())
+        }
+        false => Err(broken(format!(
+            "`nodeup --version` exited with {} and printed {:?}",
+            output.status,
+            stdout.trim()
+        ))),
+    }
+}
+
+fn check_status(status: StatusCode, url: &str) -> Result<(), SelfUpdateError> {
+    match status {
+        StatusCode::OK => Ok(()),
+        code => Err(RegistryError::UnexpectedResult {
+            url: url.to_string(),
+            code,
+        }
+        .into()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn update_available() {
+        let current = Version::parse("0.1.0").unwrap();
+        assert!(is_update(&current, "v0.1.1").unwrap());
+        assert!(is_update(&current, "v0.2.0").unwrap());
+        assert!(is_update(&current, "1.0.0").unwrap());
+        assert!(!is_update(&current, "v0.1.0").unwrap());
+        assert!(!is_update(&current, "v0.0.7").unwrap());
+        assert!(!is_update(&current, "v0.1.0-rc.1").unwrap());
+        assert!(matches!(
+            is_update(&current, "nightly"),
+            Err(SelfUpdateError::InvalidTag { .. })
+        ));
+    }
+
+    #[test]
+    fn host_asset_names() {
+        let suffix = if cfg!(windows) { ".exe" } else { "" };
+        assert_eq!(
+            asset_names(OperatingSystem::Linux, Architecture::X64),
+            vec![
+                format!("nodeup-linux-x64{}", suffix),
+                format!("nodeup-linux{}", suffix)
+            ]
+        );
+        assert_eq!(
+            asset_names(OperatingSystem::Darwin, Architecture::Arm64),
+            vec![
+                format!("nodeup-macos-arm64{}", suffix),
+                format!("nodeup-macos{}", suffix)
+            ]
+        );
+
+        // The os only builds are for a different architecture
+        assert_eq!(
+            asset_names(OperatingSystem::Linux, Architecture::Arm64),
+            vec![format!("nodeup-linux-arm64{}", suffix)]
+        );
+        assert_eq!(
+            asset_names(OperatingSystem::Darwin, Architecture::X64),
+            vec![format!("nodeup-macos-x64{}", suffix)]
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn downloads_have_to_run() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = tempfile::tempdir().unwrap();
+        let script = |name: &str, content: &str| {
+            let path = dir.path().join(name);
+            fs::write(&path, content).unwrap();
+            fs::set_permissions(&path, fs::Permissions::from_mode(0o755)).unwrap();
+            path
+        };
+
+        let working = script("working", "#!/bin/sh\necho nodeup 0.0.8\n");
+        assert!(check_runs(&working, "url").is_ok());
+
+        let truncated = script("truncated", "\x7fELF");
+        assert!(matches!(
+            check_runs(&truncated, "url"),
+            Err(SelfUpdateError::BrokenDownload { .. })
+        ));
+
+        let failing = script("failing", "#!/bin/sh\nexit 1\n");
+        assert!(matches!(
+            check_runs(&failing, "url"),
+            Err(SelfUpdateError::BrokenDownload { .. })
+        ));
+    }
+}
@@ -0,0 +1,215 @@
+use std::{fmt, path::Path, str::FromStr};
+use thiserror::Error;
+
+use crate::target::OperatingSystem;
+
+#[derive(Debug, Error)]
+pub enum ShellError {
+    #[error("Unsupported shell: {0}. Supported shells are: bash, zsh, and fish")]
+    Unsupported(String),
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    Fish,
+    Zsh,
+}
+
+// Each hook asks nodeup for the bin directory of the active version and swaps it onto the front of
+// the PATH, removing the bin directory of the previously active version
+const BASH_HOOK: &str = r#"_nodeup_hook() {
+  local bin_dir
+  bin_dir="$(nodeup override which --bin-dir 2>/dev/null)"
+  [ "$bin_dir" = "$_NODEUP_BIN_DIR" ] && return
+  if [ -n "$_NODEUP_BIN_DIR" ]; then
+    PATH=":$PATH:"
+    PATH="${PATH//:"$_NODEUP_BIN_DIR":/:}"
+    PATH="${PATH#:}"
+    PATH="${PATH%:}"
+  fi
+  if [ -n "$bin_dir" ]; then
+    PATH="$bin_dir:$PATH"
+  fi
+  export PATH
+  export _NODEUP_BIN_DIR="$bin_dir"
+}
+"#;
+
+const BASH_INIT: &str = r#"if [[ ";${PROMPT_COMMAND:-};" != *";_nodeup_hook;"* ]]; then
+  PROMPT_COMMAND="_nodeup_hook${PROMPT_COMMAND:+;$PROMPT_COMMAND}"
+fi
+"#;
+
+const ZSH_INIT: &str = r#"autoload -U add-zsh-hook
+add-zsh-hook chpwd _nodeup_hook
+_nodeup_hook
+"#;
+
+const FISH_INIT: &str = r#"function _nodeup_hook --on-variable PWD
+    set -l bin_dir (nodeup override which --bin-dir 2>/dev/null)
+    if test "$bin_dir" = "$_NODEUP_BIN_DIR"
+        return
+    end
+    if test -n "$_NODEUP_BIN_DIR"
+        set -l index (contains -i -- $_NODEUP_BIN_DIR $PATH)
+        and set -e PATH[$index]
+    end
+    if test -n "$bin_dir"
+        set -gx PATH $bin_dir $PATH
+    end
+    set -gx _NODEUP_BIN_DIR $bin_dir
+end
+_nodeup_hook
+"#;
+
+/// A script that, when evaluated by the shell, switches the node on the PATH whenever the current
+/// directory changes
+pub fn init_script(shell: Shell) -> String {
+    match shell {
+        Shell::Bash => format!("{}{}", BASH_HOOK, BASH_INIT),
+        Shell::Zsh => format!("{}{}", BASH_HOOK, ZSH_INIT),
+        Shell::Fish => FISH_INIT.to_string(),
+    }
+}
+
+/// Exports for an .envrc that put the bin directory on the front of the PATH. direnv restores the
+/// PATH itself when leaving the directory, so unlike the shell hooks nothing has to be removed
+pub fn direnv_exports(bin_dir: &Path) -> String {
+    format!("export PATH={}:\"$PATH\"\n", double_quoted(bin_dir))
+}
+
+/// A complete statement putting the bin directory on the front of the PATH, for setting up CI jobs
+/// with `eval "$(nodeup env)"`. Windows gets the PowerShell form
+pub fn env_assignment(bin_dir: &Path, os: OperatingSystem) -> String {
+    match os {
+        OperatingSystem::Windows => format!(
+            "$env:Path = '{};' + $env:Path\n",
+            bin_dir.to_string_lossy().replace('\'', "''")
+        ),
+        _ => format!("PATH={}:\"$PATH\"\n", double_quoted(bin_dir)),
+    }
+}
+
+// Characters that keep their meaning inside double quotes are escaped so the path is used as is
+fn double_quoted(path: &Path) -> String {
+    let mut quoted = String::from("\"");
+    for c in path.to_string_lossy().chars() {
+        if matches!(c, '"' | '\\' | '$' | '`') {
+            quoted.push('\\');
+        }
+        quoted.push(c);
+    }
+    quoted.push('"');
+    quoted
+}
+
+impl FromStr for Shell {
+    type Err = ShellError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        match content {
+            "bash" => Ok(Shell::Bash),
+            "fish" => Ok(Shell::Fish),
+            "zsh" => Ok(Shell::Zsh),
+            _ => Err(ShellError::Unsupported(content.to_string())),
+        }
+    }
+}
+
+impl fmt::Display for Shell {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Shell::Bash => write!(f, "bash"),
+            Shell::Fish => write!(f, "fish"),
+            Shell::Zsh => write!(f, "zsh"),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn bash_hook() {
+        let script = init_script("bash".parse().unwrap());
+        assert!(script.contains("_nodeup_hook()"));
+        assert!(script.contains("PROMPT_COMMAND=\"_nodeup_hook"));
+        assert!(script.contains("nodeup override which --bin-dir"));
+    }
+
+    // Quoting the old bin dir keeps characters like * from being matched as a pattern
+    #[cfg(unix)]
+    #[test]
+    fn bash_hook_swaps_bin_dir() {
+        let script = format!(
+            "{}nodeup() {{ echo /n/new; }}\n_NODEUP_BIN_DIR='/n/a*'\nPATH='/n/a*:/n/abc:/usr/bin'\n_nodeup_hook\nprintf %s \"$PATH\"",
+            BASH_HOOK
+        );
+        let output = std::process::Command::new("bash")
+            .args(["--noprofile", "--norc", "-c", &script])
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+        assert_eq!(
+            String::from_utf8_lossy(&output.stdout),
+            "/n/new:/n/abc:/usr/bin"
+        );
+    }
+
+    #[test]
+    fn zsh_hook() {
+        let script = init_script("zsh".parse().unwrap());
+        assert!(script.contains("_nodeup_hook()"));
+        assert!(script.contains("add-zsh-hook chpwd _nodeup_hook"));
+        assert!(!script.contains("PROMPT_COMMAND"));
+    }
+
+    #[test]
+    fn fish_hook() {
+        let script = init_script("fish".parse().unwrap());
+        assert!(script.contains("function _nodeup_hook --on-variable PWD"));
+        assert!(script.contains("nodeup override which --bin-dir"));
+    }
+
+    #[test]
+    fn direnv_path_export() {
+        let bin_dir = Path::new("/home/me/.local/share/nodeup/node-v18.17.0-linux-x64/bin");
+        assert_eq!(
+            direnv_exports(bin_dir),
+            "export PATH=\"/home/me/.local/share/nodeup/node-v18.17.0-linux-x64/bin\":\"$PATH\"\n"
+        );
+        assert_eq!(
+            direnv_exports(Path::new("/odd \"$dir\"/bin")),
+            "export PATH=\"/odd \\\"\\$dir\\\"/bin\":\"$PATH\"\n"
+        );
+    }
+
+    #[test]
+    fn unix_env_assignment() {
+        let bin_dir = Path::new("/opt/nodeup/node-v18.17.0-linux-x64/bin");
+        assert_eq!(
+            env_assignment(bin_dir, OperatingSystem::Linux),
+            "PATH=\"/opt/nodeup/node-v18.17.0-linux-x64/bin\":\"$PATH\"\n"
+        );
+        assert_eq!(
+            env_assignment(Path::new("/it's/bin"), OperatingSystem::Darwin),
+            "PATH=\"/it's/bin\":\"$PATH\"\n"
+        );
+    }
+
+    #[test]
+    fn windows_env_assignment() {
+        let bin_dir = Path::new(r"C:\Users\o'neil\nodeup\node-v18.17.0-win-x64");
+        assert_eq!(
+            env_assignment(bin_dir, OperatingSystem::Windows),
+            "$env:Path = 'C:\\Users\\o''neil\\nodeup\\node-v18.17.0-win-x64;' + $env:Path\n"
+        );
+    }
+
+    #[test]
+    fn unsupported_shell() {
+        assert!("powershell".parse::<Shell>().is_err());
+    }
+}
@@ -1,8 +1,10 @@
-use log::debug;
+use log::{debug, warn};
 use serde::{Deserialize, Serialize};
 use std::{
     cmp::{Ord, Ordering, PartialOrd},
-    fmt,
+    fmt, fs,
+    str::FromStr,
+    sync::OnceLock,
 };
 use thiserror::Error;
 
@@ -18,6 +20,12 @@ pub enum ParseError {
 
     #[error("Not a valid number: {content:?}")]
     InvalidNumber { content: String },
+
+    #[error("Empty identifier found in: {content:?}")]
+    EmptyIdentifier { content: String },
+
+    #[error("Unexpected input after the version: {content:?}")]
+    TrailingInput { content: String },
 }
 
 #[derive(Debug, Error)]
@@ -30,6 +38,12 @@ pub enum VersionError {
 
     #[error("Couldn't parse patch version: {source}")]
     Patch { source: ParseError },
+
+    #[error("Couldn't parse prerelease: {source}")]
+    Prerelease { source: ParseError },
+
+    #[error("Only another wildcard can follow a wildcard: {content:?}")]
+    ComponentAfterWildcard { content: String },
 }
 
 #[derive(Debug, Error)]
@@ -51,74 +65,237 @@ pub enum TargetError {
         #[from]
         source: OperatingSystemError,
     },
+
+    #[error("Failed to parse architecture: {source}")]
+    Architecture {
+        #[from]
+        source: ArchitectureError,
+    },
 }
 
 #[derive(Debug, Error)]
 pub enum OperatingSystemError {
-    #[error("Unrecognized operating system: {0}. Valid values are: linux, macos, and windows")]
+    #[error(
+        "Unrecognized operating system: {0}. Valid values are: linux, macos, windows, and freebsd"
+    )]
+    Unrecognized(String),
+}
+
+/// Environment variable that picks the architecture versions are installed for, ex/ x64 to run
+/// versions under emulation on an arm64 mac
+pub const ARCH_VAR: &str = "NODEUP_ARCH";
+
+#[derive(Debug, Error)]
+pub enum ArchitectureError {
+    #[error(
+        "Unrecognized architecture: {0}. Valid values are: x64, arm64, armv7l, x86, and ppc64le"
+    )]
     Unrecognized(String),
 }
 
-#[derive(Debug, PartialEq, Eq, Copy, Clone, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Serialize, Deserialize)]
 pub struct Target {
     os: OperatingSystem,
+
+    #[serde(default)]
+    arch: Architecture,
+
+    // Only meaningful on linux. Targets saved before this existed were all glibc builds
+    #[serde(default, skip_serializing_if = "Libc::is_gnu")]
+    libc: Libc,
+
+    // Tables have to come after plain values for the config to serialize as toml
     version: Version,
 }
 
-#[derive(Debug, Clone, Copy, PartialEq, Eq, Deserialize, Serialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub struct Version {
     pub major: usize,
     pub minor: usize,
     pub patch: usize,
+
+    // ex/ rc.1 in v14.0.0-rc.1
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prerelease: Option<String>,
+}
+
+/// A requirement describing a range of versions. The supported forms are:
+///
+/// | Requirement   | Matches           |
+/// |---------------|-------------------|
+/// | 16 or 16.x    | >=16.0.0 <17.0.0  |
+/// | 16.1 or 16.1.x| >=16.1.0 <16.2.0  |
+/// | ~16.1.2       | >=16.1.2 <16.2.0  |
+/// | ^16.1.2       | >=16.1.2 <17.0.0  |
+/// | >=16.1        | >=16.1.0          |
+/// | >16.1         | >=16.2.0          |
+/// | <16.1         | <16.1.0           |
+/// | <=16.1        | <16.2.0           |
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct VersionReq {
+    op: VersionOp,
+    major: usize,
+    minor: Option<usize>,
+    patch: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum VersionOp {
+    Exact,
+    Tilde,
+    Caret,
+    Greater,
+    GreaterEq,
+    Less,
+    LessEq,
 }
 
+/// A set of requirements in the form used by the engines field of package.json, ex/ >=14 <19 || 20.
+/// Requirements separated by spaces must all match and any of the groups separated by || can match.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VersionRange {
+    alternatives: Vec<Vec<VersionReq>>,
+}
+
+/// The C library a linux build of node is linked against. musl builds are only published as
+/// unofficial builds and carry a -musl suffix, ex/ node-v16.1.0-linux-x64-musl
+#[derive(Debug, Default, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Libc {
+    #[default]
+    Gnu,
+    Musl,
+}
+
+/// The operating systems nodeup runs on and installs node for. The default is the os nodeup was
+/// built for, which has to be one of linux, macos, windows, or freebsd.
 #[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
 pub enum OperatingSystem {
     Darwin,
     Linux,
     Windows,
+    /// node doesn't publish FreeBSD builds, but versions built from source can still be installed
+    FreeBSD,
+}
+
+/// The cpu architectures node publishes builds for. The default is the architecture nodeup was
+/// built for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Deserialize, Serialize)]
+pub enum Architecture {
+    X64,
+    Arm64,
+    Armv7l,
+    X86,
+    Ppc64le,
 }
 
 impl Target {
     pub fn new(os: OperatingSystem, version: Version) -> Self {
-        Target { os, version }
+        Target {
+            os,
+            version,
+            arch: Default::default(),
+            libc: Libc::Gnu,
+        }
     }
 
-    // content is expected to look like: node-v12.9.1-linux-x64
-    pub fn parse(content: &str) -> Result<Self, TargetError> {
-        debug!("Target parsing content: {}", content);
-        // skip "node-"
-        let rest = &content[5..];
+    pub fn with_arch(mut self, arch: Architecture) -> Self {
+        self.arch = arch;
+        self
+    }
 
-        let end_index = rest
-            .chars()
-            .position(|ch| ch == '-')
-            .unwrap_or_else(|| rest.len());
-        let (version_string, rest) = (&rest[..end_index], &rest[end_index..]);
-        let version = Version::parse(version_string)?;
+    /// Selects the libc the build is linked against. Ignored for anything other than linux
+    pub fn with_libc(mut self, libc: Libc) -> Self {
+        if self.os == OperatingSystem::Linux {
+            self.libc = libc;
+        }
+        self
+    }
 
-        let (_, rest) = parse_dash(rest).map_err(|source| TargetError::Separator {
-            after: "version",
+    // content is expected to look like: node-v12.9.1-linux-x64 or node-v12.9.1-linux-x64-musl
+    // The version can contain dashes itself, ex/ node-v14.0.0-rc.1-linux-x64, so the os and arch
+    // are split off from the end. Versions installed before the arch was part of the name, ex/
+    // node-v12.9.1-linux, are for the default arch
+    pub fn parse(content: &str) -> Result<Self, TargetError> {
+        debug!("Target parsing content: {}", content);
+        let rest = parse_prefix("node-", content).map_err(|source| TargetError::Separator {
+            after: "node",
             source,
         })?;
+        let (rest, libc) = match rest.strip_suffix("-musl") {
+            Some(rest) => (rest, Libc::Musl),
+            None => (rest, Libc::Gnu),
+        };
 
-        let end_index = rest
-            .chars()
-            .position(|ch| ch == '-')
-            .unwrap_or_else(|| rest.len());
-        let (os_string, _) = (&rest[..end_index], &rest[end_index..]);
-        let os = OperatingSystem::parse(os_string)?;
+        let arch_less = rest
+            .rsplit_once('-')
+            .and_then(|(version_string, os_string)| {
+                OperatingSystem::parse(os_string)
+                    .ok()
+                    .map(|os| (version_string, os))
+            });
+        let (version_string, os, arch) = match arch_less {
+            Some((version_string, os)) => (version_string, os, Architecture::default()),
+            None => {
+                let mut segments = rest.rsplitn(3, '-');
+                let arch_string = segments.next().unwrap_or_default();
+                let os_string = segments.next().ok_or(TargetError::Separator {
+                    after: "version",
+                    source: ParseError::UnexpectedEndOfInput,
+                })?;
+                let version_string = segments.next().ok_or(TargetError::Separator {
+                    after: "node",
+                    source: ParseError::UnexpectedEndOfInput,
+                })?;
+                let os = OperatingSystem::parse(os_string)?;
+                (version_string, os, Architecture::parse(arch_string)?)
+            }
+        };
 
-        // TODO: add parsing arch
-        Ok(Target::new(os, version))
+        let version = Version::parse(version_string)?;
+
+        Ok(Target::new(os, version).with_arch(arch).with_libc(libc))
     }
 
+    /// The version built for this machine
     pub fn from_version(version: Version) -> Self {
         Target::new(Default::default(), version)
+            .with_arch(Architecture::host())
+            .with_libc(Libc::host())
+    }
+
+    pub fn version(&self) -> &Version {
+        &self.version
+    }
+
+    pub fn os(&self) -> OperatingSystem {
+        self.os
+    }
+
+    pub fn libc(&self) -> Libc {
+        self.libc
+    }
+
+    pub fn arch(&self) -> Architecture {
+        self.arch
     }
 
-    pub fn version(&self) -> Version {
-        self.version
+    /// The url of the target's archive on a registry, ex/ https://nodejs.org/dist/
+    // Full url example: https://nodejs.org/dist/v12.9.1/node-v12.9.1-linux-x64.tar.gz
+    // Windows builds are distributed as zips: https://nodejs.org/dist/v12.9.1/node-v12.9.1-win-x64.zip
+    pub fn download_url(&self, base: &str) -> String {
+        let extension = match self.os {
+            OperatingSystem::Windows => "zip",
+            _ => "tar.gz",
+        };
+        format!("{}{}/{}.{}", base, self.version, self, extension)
+    }
+}
+
+impl FromStr for Target {
+    type Err = TargetError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        Target::parse(content)
     }
 }
 
@@ -128,7 +305,11 @@ impl Target {
  */
 impl fmt::Display for Target {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "node-{}-{}-x64", self.version(), self.os)
+        write!(f, "node-{}-{}-{}", self.version(), self.os, self.arch())?;
+        match self.libc {
+            Libc::Gnu => Ok(()),
+            Libc::Musl => write!(f, "-musl"),
+        }
     }
 }
 
@@ -143,6 +324,24 @@ impl fmt::Display for OperatingSystem {
             Darwin => write!(f, "darwin"),
             Linux => write!(f, "linux"),
             Windows => write!(f, "win"),
+            FreeBSD => write!(f, "freebsd"),
+        }
+    }
+}
+
+/*
+ * Display is implemented so the arch is formatted according to how it appears in the node download
+ * url
+ */
+impl fmt::Display for Architecture {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        use Architecture::*;
+        match self {
+            X64 => write!(f, "x64"),
+            Arm64 => write!(f, "arm64"),
+            Armv7l => write!(f, "armv7l"),
+            X86 => write!(f, "x86"),
+            Ppc64le => write!(f, "ppc64le"),
         }
     }
 }
@@ -161,14 +360,46 @@ impl Version {
         let (minor, rest) = parse_number(rest).map_err(|source| VersionError::Minor { source })?;
         let (_, rest) = parse_dot(rest).map_err(|source| VersionError::Patch { source })?;
 
-        let (patch, _) = parse_number(rest).map_err(|source| VersionError::Patch { source })?;
+        let (patch, rest) = parse_number(rest).map_err(|source| VersionError::Patch { source })?;
+
+        let (prerelease, rest) = match parse_dash(rest) {
+            Ok((_, rest)) => {
+                let (prerelease, rest) =
+                    parse_prerelease(rest).map_err(|source| VersionError::Prerelease { source })?;
+                (Some(prerelease.to_string()), rest)
+            }
+            Err(_) => (None, rest),
+        };
+
+        if !rest.is_empty() {
+            let source = ParseError::TrailingInput {
+                content: rest.to_string(),
+            };
+            return Err(match prerelease {
+                Some(_) => VersionError::Prerelease { source },
+                None => VersionError::Patch { source },
+            });
+        }
 
         Ok(Version {
             major,
             minor,
             patch,
+            prerelease,
         })
     }
+
+    pub fn satisfies(&self, req: &VersionReq) -> bool {
+        req.matches(self)
+    }
+}
+
+impl FromStr for Version {
+    type Err = VersionError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        Version::parse(content)
+    }
 }
 
 impl PartialOrd for Version {
@@ -181,7 +412,10 @@ impl Ord for Version {
     fn cmp(&self, other: &Self) -> Ordering {
         match self.major.cmp(&other.major) {
             Ordering::Equal => match self.minor.cmp(&other.minor) {
-                Ordering::Equal => self.patch.cmp(&other.patch),
+                Ordering::Equal => match self.patch.cmp(&other.patch) {
+                    Ordering::Equal => compare_prerelease(&self.prerelease, &other.prerelease),
+                    o => o,
+                },
                 o => o,
             },
             o => o,
@@ -189,23 +423,303 @@ impl Ord for Version {
     }
 }
 
+// Follows semver precedence. A prerelease sorts before its release, and prereleases are compared
+// identifier by identifier with numeric identifiers sorting before alphanumeric ones.
+fn compare_prerelease(a: &Option<String>, b: &Option<String>) -> Ordering {
+    let (a, b) = match (a, b) {
+        (None, None) => return Ordering::Equal,
+        (None, Some(_)) => return Ordering::Greater,
+        (Some(_), None) => return Ordering::Less,
+        (Some(a), Some(b)) => (a, b),
+    };
+
+    let mut a_identifiers = a.split('.');
+    let mut b_identifiers = b.split('.');
+    loop {
+        let ordering = match (a_identifiers.next(), b_identifiers.next()) {
+            (None, None) => return Ordering::Equal,
+            (None, Some(_)) => return Ordering::Less,
+            (Some(_), None) => return Ordering::Greater,
+            (Some(a), Some(b)) => match (a.parse::<usize>(), b.parse::<usize>()) {
+                (Ok(a), Ok(b)) => a.cmp(&b),
+                (Ok(_), Err(_)) => Ordering::Less,
+                (Err(_), Ok(_)) => Ordering::Greater,
+                (Err(_), Err(_)) => a.cmp(b),
+            },
+        };
+
+        if ordering != Ordering::Equal {
+            return ordering;
+        }
+    }
+}
+
 impl fmt::Display for Version {
     fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)
+        write!(f, "v{}.{}.{}", self.major, self.minor, self.patch)?;
+        match &self.prerelease {
+            Some(prerelease) => write!(f, "-{}", prerelease),
+            None => Ok(()),
+        }
+    }
+}
+
+impl VersionReq {
+    /// Matches every stable version, ex/ when looking for the newest release
+    pub fn any() -> Self {
+        VersionReq {
+            op: VersionOp::GreaterEq,
+            major: 0,
+            minor: None,
+            patch: None,
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<VersionReq, VersionError> {
+        debug!("Parsing VersionReq: {}", content);
+        let (op, rest) = if let Some(rest) = content.strip_prefix(">=") {
+            (VersionOp::GreaterEq, rest)
+        } else if let Some(rest) = content.strip_prefix("<=") {
+            (VersionOp::LessEq, rest)
+        } else if let Some(rest) = content.strip_prefix('>') {
+            (VersionOp::Greater, rest)
+        } else if let Some(rest) = content.strip_prefix('<') {
+            (VersionOp::Less, rest)
+        } else if let Some(rest) = content.strip_prefix('=') {
+            (VersionOp::Exact, rest)
+        } else if let Some(rest) = content.strip_prefix('^') {
+            (VersionOp::Caret, rest)
+        } else if let Some(rest) = content.strip_prefix('~') {
+            (VersionOp::Tilde, rest)
+        } else {
+            (VersionOp::Exact, content)
+        };
+
+        let rest = match rest.chars().next() {
+            Some('v') => &rest[1..],
+            _ => rest,
+        };
+
+        let (major, rest) = parse_number(rest).map_err(|source| VersionError::Major { source })?;
+
+        let (minor, rest) =
+            parse_component(rest).map_err(|source| VersionError::Minor { source })?;
+
+        let (patch, rest) =
+            parse_component(rest).map_err(|source| VersionError::Patch { source })?;
+
+        // Only a wildcard leaves the minor version out when there's anything after it, ex/ 16.x.5
+        if minor.is_none() && patch.is_some() {
+            return Err(VersionError::ComponentAfterWildcard {
+                content: content.to_string(),
+            });
+        }
+
+        if !rest.is_empty() {
+            return Err(VersionError::Patch {
+                source: ParseError::TrailingInput {
+                    content: rest.to_string(),
+                },
+            });
+        }
+
+        Ok(VersionReq {
+            op,
+            major,
+            minor,
+            patch,
+        })
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        // Prereleases have to be asked for explicitly by their exact version
+        if version.prerelease.is_some() {
+            return false;
+        }
+
+        // Comparisons against a partial version treat it as covering every version it leaves out,
+        // so >16.1 means past every 16.1.x release and <=16.1 means up to the last one
+        let current = (version.major, version.minor, version.patch);
+        let floor = (self.major, self.minor.unwrap_or(0), self.patch.unwrap_or(0));
+        let past = match (self.minor, self.patch) {
+            (None, _) => (self.major + 1, 0, 0),
+            (Some(minor), None) => (self.major, minor + 1, 0),
+            (Some(minor), Some(patch)) => (self.major, minor, patch + 1),
+        };
+
+        let same_major = version.major == self.major;
+        let same_minor = self.minor.is_none_or(|minor| version.minor == minor);
+        let same_patch = self.patch.is_none_or(|patch| version.patch == patch);
+
+        match self.op {
+            VersionOp::Greater => current >= past,
+            VersionOp::GreaterEq => current >= floor,
+            VersionOp::Less => current < floor,
+            VersionOp::LessEq => current < past,
+            VersionOp::Exact => same_major && same_minor && same_patch,
+            VersionOp::Tilde => current >= floor && same_major && same_minor,
+            // Caret allows changes that don't modify the left-most non-zero component
+            VersionOp::Caret => {
+                current >= floor
+                    && match (self.major, self.minor) {
+                        (0, Some(0)) if self.patch.is_some() => {
+                            same_major && same_minor && same_patch
+                        }
+                        (0, Some(_)) => same_major && same_minor,
+                        _ => same_major,
+                    }
+            }
+        }
+    }
+}
+
+impl VersionRange {
+    pub fn parse(content: &str) -> Result<VersionRange, VersionError> {
+        let alternatives = content
+            .split("||")
+            .map(|alternative| {
+                comparators(alternative)
+                    .iter()
+                    .filter(|req| !matches!(req.as_str(), "*" | "x" | "X"))
+                    .map(|req| VersionReq::parse(req))
+                    .collect::<Result<Vec<_>, _>>()
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        Ok(VersionRange { alternatives })
+    }
+
+    pub fn matches(&self, version: &Version) -> bool {
+        self.alternatives
+            .iter()
+            .any(|reqs| reqs.iter().all(|req| req.matches(version)))
+    }
+}
+
+// Splits a range into its comparators. An operator may be separated from its version by whitespace,
+// ex/ >= 16, so an operator on its own is joined with the version that follows it
+fn comparators(alternative: &str) -> Vec<String> {
+    let mut comparators = Vec::new();
+    let mut operator = String::new();
+    for token in alternative.split_whitespace() {
+        operator.push_str(token);
+        if !token
+            .chars()
+            .all(|ch| matches!(ch, '<' | '>' | '=' | '^' | '~'))
+        {
+            comparators.push(std::mem::take(&mut operator));
+        }
+    }
+
+    // A trailing operator without a version is left in so parsing it fails
+    if !operator.is_empty() {
+        comparators.push(operator);
+    }
+    comparators
+}
+
+impl FromStr for VersionRange {
+    type Err = VersionError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        VersionRange::parse(content)
+    }
+}
+
+impl fmt::Display for VersionRange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let alternatives: Vec<String> = self
+            .alternatives
+            .iter()
+            .map(|reqs| match reqs.is_empty() {
+                true => String::from("*"),
+                false => {
+                    let reqs: Vec<String> = reqs.iter().map(|req| req.to_string()).collect();
+                    reqs.join(" ")
+                }
+            })
+            .collect();
+        write!(f, "{}", alternatives.join(" || "))
+    }
+}
+
+impl FromStr for VersionReq {
+    type Err = VersionError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        VersionReq::parse(content)
+    }
+}
+
+impl fmt::Display for VersionReq {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.op {
+            VersionOp::Exact => (),
+            VersionOp::Tilde => write!(f, "~")?,
+            VersionOp::Caret => write!(f, "^")?,
+            VersionOp::Greater => write!(f, ">")?,
+            VersionOp::GreaterEq => write!(f, ">=")?,
+            VersionOp::Less => write!(f, "<")?,
+            VersionOp::LessEq => write!(f, "<=")?,
+        };
+
+        // Wildcards read oddly after a comparison, ex/ >=16.x, so they're left off
+        let comparison = matches!(
+            self.op,
+            VersionOp::Greater | VersionOp::GreaterEq | VersionOp::Less | VersionOp::LessEq
+        );
+        write!(f, "{}", self.major)?;
+        match (self.minor, self.patch, comparison) {
+            (Some(minor), Some(patch), _) => write!(f, ".{}.{}", minor, patch),
+            (Some(minor), None, true) => write!(f, ".{}", minor),
+            (Some(minor), None, false) => write!(f, ".{}.x", minor),
+            (None, _, true) => Ok(()),
+            (None, _, false) => write!(f, ".x"),
+        }
     }
 }
 
 impl OperatingSystem {
+    /// Every operating system nodeup knows about
+    pub const ALL: [OperatingSystem; 4] = [
+        OperatingSystem::Linux,
+        OperatingSystem::Darwin,
+        OperatingSystem::Windows,
+        OperatingSystem::FreeBSD,
+    ];
+
+    /// Accepts the names used in node download urls along with the common names for each os
     pub fn parse(content: &str) -> Result<Self, OperatingSystemError> {
         match content {
             "linux" => Ok(OperatingSystem::Linux),
-            "win" => Ok(OperatingSystem::Windows),
-            "darwin" => Ok(OperatingSystem::Darwin),
+            "win" | "windows" => Ok(OperatingSystem::Windows),
+            "darwin" | "macos" | "osx" => Ok(OperatingSystem::Darwin),
+            "freebsd" => Ok(OperatingSystem::FreeBSD),
             _ => Err(OperatingSystemError::Unrecognized(content.to_string())),
         }
     }
 }
 
+impl FromStr for OperatingSystem {
+    type Err = OperatingSystemError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        OperatingSystem::parse(content)
+    }
+}
+
+// Without this, building for any other os fails with a missing Default impl far from the cause
+#[cfg(not(any(
+    target_os = "linux",
+    target_os = "windows",
+    target_os = "macos",
+    target_os = "freebsd"
+)))]
+compile_error!(
+    "nodeup can only be built for linux, macos, windows, and freebsd. Porting it to another os \
+     starts with adding a variant for it to OperatingSystem in src/target.rs"
+);
+
 impl Default for OperatingSystem {
     #[cfg(target_os = "linux")]
     fn default() -> Self {
@@ -221,13 +735,96 @@ impl Default for OperatingSystem {
     fn default() -> Self {
         OperatingSystem::Darwin
     }
+
+    #[cfg(target_os = "freebsd")]
+    fn default() -> Self {
+        OperatingSystem::FreeBSD
+    }
+}
+
+impl Architecture {
+    /// The architecture of this machine, or the one set in $NODEUP_ARCH
+    pub fn host() -> Self {
+        match std::env::var(ARCH_VAR) {
+            Ok(arch) => Architecture::parse(&arch).unwrap_or_else(|e| {
+                warn!("Ignoring ${}: {}", ARCH_VAR, e);
+                Architecture::default()
+            }),
+            Err(_) => Architecture::default(),
+        }
+    }
+
+    pub fn parse(content: &str) -> Result<Self, ArchitectureError> {
+        match content {
+            "x64" => Ok(Architecture::X64),
+            "arm64" => Ok(Architecture::Arm64),
+            "armv7l" => Ok(Architecture::Armv7l),
+            "x86" => Ok(Architecture::X86),
+            "ppc64le" => Ok(Architecture::Ppc64le),
+            _ => Err(ArchitectureError::Unrecognized(content.to_string())),
+        }
+    }
+}
+
+impl FromStr for Architecture {
+    type Err = ArchitectureError;
+
+    fn from_str(content: &str) -> Result<Self, Self::Err> {
+        Architecture::parse(content)
+    }
+}
+
+impl Default for Architecture {
+    fn default() -> Self {
+        if cfg!(target_arch = "aarch64") {
+            Architecture::Arm64
+        } else if cfg!(target_arch = "arm") {
+            Architecture::Armv7l
+        } else if cfg!(target_arch = "x86") {
+            Architecture::X86
+        } else if cfg!(target_arch = "powerpc64") {
+            Architecture::Ppc64le
+        } else {
+            Architecture::X64
+        }
+    }
+}
+
+impl Libc {
+    /// The libc of the machine nodeup is running on. musl distros such as alpine ship the musl
+    /// dynamic loader at /lib/ld-musl-<arch>.so.1 while glibc distros don't. /lib is only read the
+    /// first time
+    pub fn host() -> Self {
+        static HOST: OnceLock<Libc> = OnceLock::new();
+        *HOST.get_or_init(|| {
+            if cfg!(target_env = "musl") {
+                return Libc::Musl;
+            }
+
+            let has_musl_loader = fs::read_dir("/lib")
+                .map(|entries| {
+                    entries
+                        .filter_map(|entry| entry.ok())
+                        .any(|entry| entry.file_name().to_string_lossy().starts_with("ld-musl-"))
+                })
+                .unwrap_or(false);
+            match has_musl_loader {
+                true => Libc::Musl,
+                false => Libc::Gnu,
+            }
+        })
+    }
+
+    fn is_gnu(&self) -> bool {
+        *self == Libc::Gnu
+    }
 }
 
 pub fn parse_number(content: &str) -> ParseResult<(usize, &str)> {
     let end_index = content
         .chars()
         .position(|ch| !ch.is_ascii_digit())
-        .unwrap_or_else(|| content.len());
+        .unwrap_or(content.len());
 
     let (major_string, rest) = (&content[..end_index], &content[end_index..]);
 
@@ -240,6 +837,48 @@ pub fn parse_number(content: &str) -> ParseResult<(usize, &str)> {
     Ok((major, rest))
 }
 
+// Parses an optional ".number" where the number can also be a wildcard of x or *
+fn parse_component(content: &str) -> ParseResult<(Option<usize>, &str)> {
+    if content.is_empty() {
+        return Ok((None, content));
+    }
+
+    let (_, rest) = parse_dot(content)?;
+    match rest.chars().next() {
+        Some('x') | Some('X') | Some('*') => Ok((None, &rest[1..])),
+        _ => parse_number(rest).map(|(number, rest)| (Some(number), rest)),
+    }
+}
+
+// Prerelease identifiers are made up of alphanumerics and dashes separated by dots, ex/ rc.1
+fn parse_prerelease(content: &str) -> ParseResult<(&str, &str)> {
+    let end_index = content
+        .chars()
+        .position(|ch| !(ch.is_ascii_alphanumeric() || ch == '-' || ch == '.'))
+        .unwrap_or(content.len());
+
+    let (prerelease, rest) = (&content[..end_index], &content[end_index..]);
+    if prerelease
+        .split('.')
+        .any(|identifier| identifier.is_empty())
+    {
+        return Err(ParseError::EmptyIdentifier {
+            content: content.to_string(),
+        });
+    }
+
+    Ok((prerelease, rest))
+}
+
+fn parse_prefix<'a>(prefix: &str, content: &'a str) -> ParseResult<&'a str> {
+    let mut rest = content;
+    for expected in prefix.chars() {
+        let (_, next) = take_char(expected, rest)?;
+        rest = next;
+    }
+    Ok(rest)
+}
+
 fn parse_dot(content: &str) -> ParseResult<(char, &str)> {
     take_char('.', content)
 }
@@ -269,6 +908,7 @@ mod tests {
             major: 12,
             minor: 15,
             patch: 1,
+            prerelease: None,
         };
 
         let content = "12.15.1";
@@ -278,6 +918,384 @@ mod tests {
         let content = "v12.15.1";
         let actual = Version::parse(content).unwrap();
         assert_eq!(actual, expected);
+
+        for content in &["v16.1.0abc", "v16.1.0 ", "v16.1.0-rc.1+build"] {
+            match Version::parse(content) {
+                Err(VersionError::Patch {
+                    source: ParseError::TrailingInput { .. },
+                })
+                | Err(VersionError::Prerelease {
+                    source: ParseError::TrailingInput { .. },
+                }) => (),
+                other => panic!("Expected trailing input for {}, got: {:?}", content, other),
+            }
+        }
+    }
+
+    #[test]
+    fn parse_prerelease_version() {
+        let actual = Version::parse("v14.0.0-rc.1").unwrap();
+        let expected = Version {
+            major: 14,
+            minor: 0,
+            patch: 0,
+            prerelease: Some(String::from("rc.1")),
+        };
+        assert_eq!(actual, expected);
+        assert_eq!(actual.to_string(), "v14.0.0-rc.1");
+
+        let actual = Version::parse("v15.0.0-nightly20200101a1b2c3d4").unwrap();
+        assert_eq!(
+            actual.prerelease,
+            Some(String::from("nightly20200101a1b2c3d4"))
+        );
+        assert_eq!(actual.to_string(), "v15.0.0-nightly20200101a1b2c3d4");
+
+        let actual = Version::parse("v13.0.0-v8-canary20190901").unwrap();
+        assert_eq!(actual.prerelease, Some(String::from("v8-canary20190901")));
+
+        let actual = Version::parse("v14.0.0").unwrap();
+        assert_eq!(actual.prerelease, None);
+        assert_eq!(actual.to_string(), "v14.0.0");
+
+        assert!(Version::parse("v14.0.0-").is_err());
+        assert!(Version::parse("v14.0.0-rc..1").is_err());
+    }
+
+    #[test]
+    fn version_serde_round_trip() {
+        for content in &["v18.17.0", "v14.0.0-rc.1"] {
+            let version = Version::parse(content).unwrap();
+            let serialized = toml::to_string(&version).unwrap();
+            let deserialized: Version = toml::from_str(&serialized).unwrap();
+            assert_eq!(deserialized, version);
+        }
+    }
+
+    #[test]
+    fn target_arch_serde() {
+        // Targets saved before arch support only have an os and version
+        let old: Target = toml::from_str(
+            r#"
+            os = "Linux"
+            version = { major = 16, minor = 20, patch = 1 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(old.arch(), Architecture::default());
+
+        let new: Target = toml::from_str(
+            r#"
+            os = "Linux"
+            arch = "Arm64"
+            version = { major = 18, minor = 17, patch = 0 }
+            "#,
+        )
+        .unwrap();
+        assert_eq!(new.arch(), Architecture::Arm64);
+
+        for target in &[old, "node-v18.17.0-linux-armv7l".parse().unwrap()] {
+            let serialized = toml::to_string(target).unwrap();
+            assert!(serialized.contains("arch = "));
+            let deserialized: Target = toml::from_str(&serialized).unwrap();
+            assert_eq!(&deserialized, target);
+            assert_eq!(deserialized.arch(), target.arch());
+        }
+    }
+
+    #[test]
+    fn prerelease_ordering() {
+        let version = |v| Version::parse(v).unwrap();
+
+        assert!(version("14.0.0-rc.1") < version("14.0.0"));
+        assert!(version("13.14.0") < version("14.0.0-rc.1"));
+        assert!(version("14.0.0-rc.1") < version("14.0.0-rc.2"));
+        assert!(version("14.0.0-rc.2") < version("14.0.0-rc.10"));
+        assert!(version("14.0.0-rc") < version("14.0.0-rc.1"));
+        assert!(version("14.0.0-1") < version("14.0.0-alpha"));
+        assert!(version("14.0.0-alpha") < version("14.0.0-beta"));
+    }
+
+    #[test]
+    fn compare_versions() {
+        let version = |v| Version::parse(v).unwrap();
+
+        assert_eq!(
+            version("18.16.0").cmp(&version("v18.16.0")),
+            Ordering::Equal
+        );
+        assert_eq!(version("16.20.1").cmp(&version("18.0.0")), Ordering::Less);
+        assert_eq!(
+            version("18.0.10").cmp(&version("18.0.9")),
+            Ordering::Greater
+        );
+        assert_eq!(version("18.0.9").cmp(&version("18.0.10")), Ordering::Less);
+        assert_eq!(
+            version("18.10.0").cmp(&version("18.9.99")),
+            Ordering::Greater
+        );
+    }
+
+    #[test]
+    fn version_satisfies() {
+        let version = |v| Version::parse(v).unwrap();
+        let req = |r| VersionReq::parse(r).unwrap();
+
+        assert!(version("18.17.0").satisfies(&req(">=18")));
+        assert!(version("18.0.10").satisfies(&req("~18.0.10")));
+        assert!(!version("16.20.1").satisfies(&req(">=18")));
+        assert!(!version("18.0.9").satisfies(&req("~18.0.10")));
+    }
+
+    #[test]
+    fn parse_prerelease_target() {
+        let target_string = "node-v14.0.0-rc.1-linux-x64";
+        let actual = Target::parse(target_string).unwrap();
+        let expected = Target::new(
+            OperatingSystem::Linux,
+            Version::parse("v14.0.0-rc.1").unwrap(),
+        );
+        assert_eq!(actual, expected);
+        assert_eq!(actual.to_string(), target_string);
+
+        let target_string = "node-v13.0.0-v8-canary20190901-darwin-x64";
+        let actual = Target::parse(target_string).unwrap();
+        assert_eq!(actual.to_string(), target_string);
+    }
+
+    #[test]
+    fn target_round_trip_for_each_os() {
+        for os in OperatingSystem::ALL.iter().copied() {
+            for version in &["v18.16.0", "v14.0.0-rc.1"] {
+                let target = Target::new(os, Version::parse(version).unwrap())
+                    .with_arch(Architecture::Arm64);
+                let name = target.to_string();
+                assert_eq!(name, format!("node-{}-{}-arm64", version, os));
+
+                let parsed = Target::parse(&name).unwrap();
+                assert_eq!(parsed, target);
+                assert_eq!(parsed.os(), os);
+                assert_eq!(parsed.to_string(), name);
+            }
+        }
+    }
+
+    #[test]
+    fn os_aliases() {
+        let aliases = vec![
+            ("linux", OperatingSystem::Linux),
+            ("win", OperatingSystem::Windows),
+            ("windows", OperatingSystem::Windows),
+            ("darwin", OperatingSystem::Darwin),
+            ("macos", OperatingSystem::Darwin),
+            ("osx", OperatingSystem::Darwin),
+        ];
+
+        for (name, os) in aliases {
+            assert_eq!(OperatingSystem::parse(name).unwrap(), os);
+        }
+        assert_eq!(OperatingSystem::Darwin.to_string(), "darwin");
+        assert_eq!(OperatingSystem::Windows.to_string(), "win");
+        assert!(OperatingSystem::parse("beos").is_err());
+    }
+
+    #[test]
+    fn freebsd_target() {
+        assert_eq!(
+            OperatingSystem::parse("freebsd").unwrap(),
+            OperatingSystem::FreeBSD
+        );
+        assert_eq!(OperatingSystem::FreeBSD.to_string(), "freebsd");
+
+        let target = Target::parse("node-v18.17.0-freebsd-x64").unwrap();
+        assert_eq!(target.os(), OperatingSystem::FreeBSD);
+        assert_eq!(target.to_string(), "node-v18.17.0-freebsd-x64");
+    }
+
+    #[test]
+    fn parse_arch() {
+        let archs = vec![
+            ("x64", Architecture::X64),
+            ("arm64", Architecture::Arm64),
+            ("armv7l", Architecture::Armv7l),
+            ("x86", Architecture::X86),
+            ("ppc64le", Architecture::Ppc64le),
+        ];
+
+        for (name, arch) in archs {
+            let content = format!("node-v16.1.0-linux-{}", name);
+            let target = Target::parse(&content).unwrap();
+            assert_eq!(target.arch(), arch);
+            assert_eq!(target.to_string(), content);
+
+            let musl = format!("{}-musl", content);
+            assert_eq!(Target::parse(&musl).unwrap().to_string(), musl);
+        }
+
+        match Target::parse("node-v16.1.0-linux-sparc") {
+            Err(TargetError::Architecture {
+                source: ArchitectureError::Unrecognized(arch),
+            }) => assert_eq!(arch, "sparc"),
+            other => panic!("Expected an unrecognized architecture, got: {:?}", other),
+        }
+    }
+
+    #[test]
+    fn musl_target() {
+        let version = Version::parse("v16.1.0").unwrap();
+        let glibc = Target::new(OperatingSystem::Linux, version.clone());
+        assert_eq!(glibc.libc(), Libc::Gnu);
+        assert_eq!(glibc.to_string(), "node-v16.1.0-linux-x64");
+
+        let musl = glibc.with_libc(Libc::Musl);
+        assert_eq!(musl.to_string(), "node-v16.1.0-linux-x64-musl");
+        assert_eq!(Target::parse("node-v16.1.0-linux-x64-musl").unwrap(), musl);
+
+        let darwin = Target::new(OperatingSystem::Darwin, version).with_libc(Libc::Musl);
+        assert_eq!(darwin.to_string(), "node-v16.1.0-darwin-x64");
+    }
+
+    #[test]
+    fn from_str() {
+        let version: Version = "v16.1.0".parse().unwrap();
+        assert_eq!(version, Version::parse("v16.1.0").unwrap());
+        assert!("v16.one.0".parse::<Version>().is_err());
+
+        let target: Target = "node-v16.1.0-darwin-x64".parse().unwrap();
+        assert_eq!(target, Target::new(OperatingSystem::Darwin, version));
+        assert!("node-v16.1.0-beos-x64".parse::<Target>().is_err());
+
+        let req: VersionReq = "^16.1".parse().unwrap();
+        assert_eq!(req, VersionReq::parse("^16.1").unwrap());
+    }
+
+    #[test]
+    fn parse_version_req() {
+        let version = |v| Version::parse(v).unwrap();
+
+        let req = VersionReq::parse("16").unwrap();
+        assert!(req.matches(&version("16.0.0")));
+        assert!(req.matches(&version("16.20.1")));
+        assert!(!req.matches(&version("15.9.9")));
+        assert!(!req.matches(&version("17.0.0")));
+        assert_eq!(req, VersionReq::parse("16.x").unwrap());
+        assert_eq!(req, VersionReq::parse("v16.*").unwrap());
+
+        let req = VersionReq::parse("16.1").unwrap();
+        assert!(req.matches(&version("16.1.4")));
+        assert!(!req.matches(&version("16.2.0")));
+
+        let req = VersionReq::parse("~16.1").unwrap();
+        assert!(req.matches(&version("16.1.0")));
+        assert!(req.matches(&version("16.1.9")));
+        assert!(!req.matches(&version("16.2.0")));
+
+        let req = VersionReq::parse("~16.1.2").unwrap();
+        assert!(!req.matches(&version("16.1.1")));
+        assert!(req.matches(&version("16.1.2")));
+        assert!(!req.matches(&version("16.2.0")));
+
+        let req = VersionReq::parse("^16").unwrap();
+        assert!(req.matches(&version("16.0.0")));
+        assert!(req.matches(&version("16.20.1")));
+        assert!(!req.matches(&version("17.0.0")));
+
+        let req = VersionReq::parse("^18.2").unwrap();
+        assert!(!req.matches(&version("18.1.9")));
+        assert!(req.matches(&version("18.2.0")));
+        assert!(req.matches(&version("18.17.0")));
+        assert!(!req.matches(&version("19.0.0")));
+
+        let req = VersionReq::parse("^0.10.2").unwrap();
+        assert!(req.matches(&version("0.10.48")));
+        assert!(!req.matches(&version("0.11.0")));
+
+        let req = VersionReq::parse("16.1.2").unwrap();
+        assert!(req.matches(&version("16.1.2")));
+        assert!(!req.matches(&version("16.1.3")));
+        assert!(!req.matches(&version("16.1.3-rc.1")));
+
+        assert!(VersionReq::parse("sixteen").is_err());
+        assert!(VersionReq::parse("16.y").is_err());
+        assert!(VersionReq::parse("16.1.2xyz").is_err());
+        assert!(VersionReq::parse("16.1.2.3").is_err());
+        assert!(matches!(
+            VersionReq::parse("16.x.5"),
+            Err(VersionError::ComponentAfterWildcard { .. })
+        ));
+        assert_eq!(
+            VersionReq::parse("16.x.x").unwrap(),
+            VersionReq::parse("16").unwrap()
+        );
+    }
+
+    #[test]
+    fn comparison_version_reqs() {
+        let version = |v| Version::parse(v).unwrap();
+
+        let req = VersionReq::parse(">=18").unwrap();
+        assert!(req.matches(&version("18.0.0")));
+        assert!(req.matches(&version("20.5.0")));
+        assert!(!req.matches(&version("17.9.9")));
+
+        let req = VersionReq::parse(">16.1").unwrap();
+        assert!(!req.matches(&version("16.1.9")));
+        assert!(req.matches(&version("16.2.0")));
+
+        let req = VersionReq::parse("<19").unwrap();
+        assert!(req.matches(&version("18.17.0")));
+        assert!(!req.matches(&version("19.0.0")));
+
+        let req = VersionReq::parse("<=16.1").unwrap();
+        assert!(req.matches(&version("16.1.9")));
+        assert!(!req.matches(&version("16.2.0")));
+
+        let req = VersionReq::parse("=16.1.2").unwrap();
+        assert_eq!(req, VersionReq::parse("16.1.2").unwrap());
+    }
+
+    #[test]
+    fn version_range() {
+        let version = |v| Version::parse(v).unwrap();
+
+        let range = VersionRange::parse(">=14 <17 || ^20").unwrap();
+        assert!(range.matches(&version("14.21.3")));
+        assert!(range.matches(&version("16.20.1")));
+        assert!(!range.matches(&version("18.17.0")));
+        assert!(range.matches(&version("20.5.0")));
+        assert_eq!(range.to_string(), ">=14 <17 || ^20.x");
+
+        let range = VersionRange::parse("*").unwrap();
+        assert!(range.matches(&version("10.24.1")));
+
+        let range = VersionRange::parse(">= 16 < 19").unwrap();
+        assert_eq!(range, VersionRange::parse(">=16 <19").unwrap());
+
+        assert!(VersionRange::parse(">=fourteen").is_err());
+        assert!(VersionRange::parse(">= ").is_err());
+    }
+
+    #[test]
+    fn display_version_req() {
+        let displayed: Vec<String> = vec!["16", "16.1.x", "~16.1", "^16.1.2"]
+            .into_iter()
+            .map(|req| VersionReq::parse(req).unwrap().to_string())
+            .collect();
+        assert_eq!(displayed, vec!["16.x", "16.1.x", "~16.1.x", "^16.1.2"]);
+    }
+
+    #[test]
+    fn parse_target_without_arch() {
+        let target = Target::parse("node-v12.9.1-linux").unwrap();
+        assert_eq!(target.os(), OperatingSystem::Linux);
+        assert_eq!(target.arch(), Architecture::default());
+        assert_eq!(target.version(), &Version::parse("v12.9.1").unwrap());
+
+        let target = Target::parse("node-v14.0.0-rc.1-darwin").unwrap();
+        assert_eq!(target.os(), OperatingSystem::Darwin);
+        assert_eq!(target.version(), &Version::parse("v14.0.0-rc.1").unwrap());
+
+        assert!(Target::parse("node-linux").is_err());
     }
 
     #[test]
@@ -291,12 +1309,32 @@ mod tests {
                 major: 12,
                 minor: 15,
                 patch: 1,
+                prerelease: None,
             },
         );
 
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn download_urls() {
+        let base = "https://nodejs.org/dist/";
+        let url = |target: &str| Target::parse(target).unwrap().download_url(base);
+
+        assert_eq!(
+            url("node-v12.9.1-linux-x64"),
+            "https://nodejs.org/dist/v12.9.1/node-v12.9.1-linux-x64.tar.gz"
+        );
+        assert_eq!(
+            url("node-v12.9.1-darwin-arm64"),
+            "https://nodejs.org/dist/v12.9.1/node-v12.9.1-darwin-arm64.tar.gz"
+        );
+        assert_eq!(
+            url("node-v12.9.1-win-x64"),
+            "https://nodejs.org/dist/v12.9.1/node-v12.9.1-win-x64.zip"
+        );
+    }
+
     #[test]
     fn parse_different_target() {
         let target_string = "node-v1.1.1000-linux-x64";
@@ -308,6 +1346,7 @@ mod tests {
                 major: 1,
                 minor: 1,
                 patch: 1000,
+                prerelease: None,
             },
         );
 
@@ -325,6 +1364,7 @@ mod tests {
                 major: 1000,
                 minor: 1000,
                 patch: 1000,
+                prerelease: None,
             },
         );
 
@@ -1,11 +1,15 @@
 use std::{
-    fmt, fs,
+    env, fmt, fs,
     io::ErrorKind,
     path::{Path, PathBuf},
 };
 use which::which;
 
-use crate::{ErrorTask, NodeupError, NODE_EXECUTABLE, NPM_EXECUTABLE, NPX_EXECUTABLE};
+use crate::{
+    bin_path, installed_versions, is_link, is_stale_link, link_node_bins, link_path, links_to,
+    verify_install_at, which as which_target, Config, ConfigError, ErrorTask, NodeupError, Target,
+    COREPACK_EXECUTABLE, DEFAULT_EXECUTABLES, SHIM_EXECUTABLES,
+};
 
 #[derive(Debug, Clone, PartialEq)]
 pub enum ConfigurationCheck {
@@ -19,7 +23,22 @@ pub enum IncorrectConfiguration {
     LinkNotFound,
     NotASymlink(PathBuf),
     MissingSymLink(PathBuf),
+    /// A symlink left pointing at where nodeup used to be, ex/ after reinstalling it elsewhere
+    StaleSymlink {
+        path: PathBuf,
+        points_to: PathBuf,
+    },
     PathNotFound,
+    DownloadDirMissing(PathBuf),
+    DownloadDirNotWritable(PathBuf),
+    CorruptConfig(String),
+    OverrideNotInstalled {
+        dir: PathBuf,
+        target: Target,
+    },
+    DefaultNotInstalled(Target),
+    /// An installed version without a node executable, ex/ when unpacking it was interrupted
+    BrokenInstall(Target),
 }
 
 impl fmt::Display for IncorrectConfiguration {
@@ -32,49 +51,196 @@ impl fmt::Display for IncorrectConfiguration {
             MissingSymLink(path) => {
                 write!(f, "Missing a symlink to nodeup at: {}. Try running `nodeup control link` to configure the symlinks", path.display())
             }
+            StaleSymlink { path, points_to } => {
+                write!(f, "The symlink at {} points to {} instead of this nodeup. nodeup may have moved since it was linked. Try running `nodeup control verify --fix` to relink it.", path.display(), points_to.display())
+            }
             PathNotFound => {
                 write!(f, "Can't find the Path environment variable.")
             }
             LinkNotFound => {
-                write!(f, "Can't find the links for node, npm, and npx in your Path environment variable. Try running `nodeup control link` and adding the printed path to your Path environment variable.")
+                write!(f, "Can't find the links for node, npm, npx, and corepack in your Path environment variable. Try running `nodeup control link` and adding the printed path to your Path environment variable.")
             }
             WrongBinary(path) => {
                 write!(f, "The binary at {} has priority over the symlink to nodeup. This can be fixed by moving the path to the Nodeup symlinks to the beginning of the Path environment variable", path.display())
             }
+            DownloadDirMissing(path) => {
+                write!(f, "The download directory at {} doesn't exist. Install a version with `nodeup versions add` to create it, or point $NODEUP_DOWNLOADS at an existing directory.", path.display())
+            }
+            DownloadDirNotWritable(path) => {
+                write!(f, "nodeup can't write to the download directory at {}. Check its permissions, or point $NODEUP_DOWNLOADS at a directory you own.", path.display())
+            }
+            CorruptConfig(error) => {
+                write!(
+                    f,
+                    "{}. Fix the file by hand or remove it and set up your overrides again.",
+                    error
+                )
+            }
+            OverrideNotInstalled { dir, target } => {
+                write!(f, "The override for {} uses {} which isn't installed. Try running `nodeup versions add {}` or removing the override.", dir.display(), target, target.version())
+            }
+            DefaultNotInstalled(target) => {
+                write!(f, "The default version {} isn't installed. Try running `nodeup versions add {}` or picking a new default with `nodeup override add --default`.", target, target.version())
+            }
+            BrokenInstall(target) => {
+                write!(f, "{} is installed but doesn't have a node executable that can be run. Try running `nodeup versions reinstall {}`.", target, target.version())
+            }
         }
     }
 }
 
-pub fn verify_links(path: &Path) -> Result<ConfigurationCheck, NodeupError> {
-    let node = path.join(NODE_EXECUTABLE);
-    match verify_link(node, NODE_EXECUTABLE) {
-        Ok(ConfigurationCheck::Correct) => (),
-        Ok(i) => return Ok(i),
-        Err(e) => return Err(e),
-    };
+impl IncorrectConfiguration {
+    /// Whether linking again repairs the problem. Problems with the Path need the shell's config
+    /// changed by hand
+    pub fn is_fixable(&self) -> bool {
+        use IncorrectConfiguration::*;
+        matches!(
+            self,
+            MissingSymLink(_) | NotASymlink(_) | StaleSymlink { .. }
+        )
+    }
+}
 
-    let npm = path.join(NPM_EXECUTABLE);
-    match verify_link(npm, NPM_EXECUTABLE) {
-        Ok(ConfigurationCheck::Correct) => (),
-        Ok(i) => return Ok(i),
-        Err(e) => return Err(e),
-    };
+pub fn verify_links(path: &Path) -> Result<ConfigurationCheck, NodeupError> {
+    verify_links_at(path, active_has_corepack())
+}
 
-    let npx = path.join(NPX_EXECUTABLE);
-    match verify_link(npx, NPX_EXECUTABLE) {
-        Ok(ConfigurationCheck::Correct) => (),
-        Ok(i) => return Ok(i),
-        Err(e) => return Err(e),
-    };
+fn verify_links_at(path: &Path, has_corepack: bool) -> Result<ConfigurationCheck, NodeupError> {
+    for executable in DEFAULT_EXECUTABLES.iter() {
+        let link = link_path(path, Path::new(executable));
+        if !is_expected(&link, executable, has_corepack) {
+            continue;
+        }
+        match verify_link(link, executable) {
+            Ok(ConfigurationCheck::Correct) => (),
+            Ok(i) => return Ok(i),
+            Err(e) => return Err(e),
+        };
+    }
 
     Ok(ConfigurationCheck::Correct)
 }
 
+// corepack only ships with node 14.19 and 16.9 onwards, so its link is only expected when it's been
+// linked before or the active version has it
+fn is_expected(link: &Path, executable: &str, has_corepack: bool) -> bool {
+    executable != COREPACK_EXECUTABLE || has_corepack || fs::symlink_metadata(link).is_ok()
+}
+
+fn active_has_corepack() -> bool {
+    env::current_dir()
+        .ok()
+        .and_then(|cwd| which_target(&cwd).ok())
+        .is_some_and(|target| bin_path(&target, COREPACK_EXECUTABLE).is_ok())
+}
+
+/// Runs every check, returning all of the problems found instead of stopping at the first one
+pub fn doctor(
+    links: &Path,
+    download_dir: &Path,
+) -> Result<Vec<IncorrectConfiguration>, NodeupError> {
+    use ErrorTask::Verify as task;
+
+    let mut problems = Vec::new();
+    if let ConfigurationCheck::Incorrect(i) = verify_links(links)? {
+        problems.push(i);
+    }
+
+    if let ConfigurationCheck::Incorrect(i) = verify_download_dir(download_dir) {
+        problems.push(i);
+    }
+
+    problems.extend(verify_installs(download_dir)?);
+
+    match Config::fetch() {
+        Ok(config) => problems.extend(verify_overrides(&config, download_dir)),
+        Err(e @ ConfigError::Corruption { .. }) => {
+            problems.push(IncorrectConfiguration::CorruptConfig(e.to_string()))
+        }
+        Err(source) => return Err(NodeupError::Config { source, task }),
+    }
+
+    Ok(problems)
+}
+
+/// Links the executables again when any of the problems can be fixed that way, returning whether
+/// anything was done. Files that aren't links are kept next to the new link with a .bak extension.
+/// Optional shims such as yarn are only linked again when they were linked to nodeup before
+pub fn fix_links(links: &Path, problems: &[IncorrectConfiguration]) -> Result<bool, NodeupError> {
+    if !problems.iter().any(IncorrectConfiguration::is_fixable) {
+        return Ok(false);
+    }
+
+    let mut executables = DEFAULT_EXECUTABLES.to_vec();
+    executables.extend(linked_shims(links));
+    link_node_bins(links, &executables, true)?;
+    Ok(true)
+}
+
+// The optional shims in the links directory that point at this nodeup or an old one
+fn linked_shims(links: &Path) -> Vec<&'static str> {
+    let nodeup = env::current_exe().ok();
+    SHIM_EXECUTABLES
+        .iter()
+        .filter(|executable| !DEFAULT_EXECUTABLES.contains(executable))
+        .filter(|executable| {
+            let link = link_path(links, Path::new(executable));
+            let current = nodeup
+                .as_ref()
+                .is_some_and(|nodeup| links_to(&link, nodeup).unwrap_or(false));
+            current || is_stale_link(&link).unwrap_or(false)
+        })
+        .copied()
+        .collect()
+}
+
+pub fn verify_download_dir(download_dir: &Path) -> ConfigurationCheck {
+    if !download_dir.is_dir() {
+        return ConfigurationCheck::Incorrect(IncorrectConfiguration::DownloadDirMissing(
+            download_dir.to_path_buf(),
+        ));
+    }
+
+    match tempfile::tempfile_in(download_dir) {
+        Ok(_) => ConfigurationCheck::Correct,
+        Err(_) => ConfigurationCheck::Incorrect(IncorrectConfiguration::DownloadDirNotWritable(
+            download_dir.to_path_buf(),
+        )),
+    }
+}
+
+/// Checks that every installed version has a node executable
+pub fn verify_installs(download_dir: &Path) -> Result<Vec<IncorrectConfiguration>, NodeupError> {
+    Ok(installed_versions(download_dir)?
+        .into_iter()
+        .filter(|target| !verify_install_at(download_dir, target))
+        .map(IncorrectConfiguration::BrokenInstall)
+        .collect())
+}
+
+/// Checks that the default and every override point at a version that's installed
+pub fn verify_overrides(config: &Config, download_dir: &Path) -> Vec<IncorrectConfiguration> {
+    let mut missing: Vec<_> = config
+        .overrides()
+        .filter(|(_, target)| !download_dir.join(target.to_string()).exists())
+        .map(|(dir, target)| match dir == Path::new("default") {
+            true => IncorrectConfiguration::DefaultNotInstalled(target.clone()),
+            false => IncorrectConfiguration::OverrideNotInstalled {
+                dir: dir.clone(),
+                target: target.clone(),
+            },
+        })
+        .collect();
+    missing.sort_by_key(|problem| problem.to_string());
+
+    missing
+}
+
 fn verify_link(path: PathBuf, executable: &'static str) -> Result<ConfigurationCheck, NodeupError> {
     use ErrorTask::Verify as task;
 
-    let metadata = match fs::symlink_metadata(&path) {
-        Ok(metadata) => metadata,
+    let is_link = match is_link(&path) {
+        Ok(is_link) => is_link,
         Err(source) => {
             return match source.kind() {
                 ErrorKind::NotFound => Ok(ConfigurationCheck::Incorrect(
@@ -85,12 +251,28 @@ fn verify_link(path: PathBuf, executable: &'static str) -> Result<ConfigurationC
         }
     };
 
-    if !metadata.file_type().is_symlink() {
+    if !is_link {
         return Ok(ConfigurationCheck::Incorrect(
             IncorrectConfiguration::NotASymlink(path),
         ));
     };
 
+    let nodeup_path = env::current_exe().map_err(|source| NodeupError::IO {
+        source,
+        task,
+        path: PathBuf::from("Looking for current executable"),
+    })?;
+    match links_to(&path, &nodeup_path) {
+        Ok(true) => {}
+        Ok(false) => {
+            let points_to = fs::read_link(&path).unwrap_or_default();
+            return Ok(ConfigurationCheck::Incorrect(
+                IncorrectConfiguration::StaleSymlink { path, points_to },
+            ));
+        }
+        Err(source) => return Err(NodeupError::IO { task, source, path }),
+    }
+
     let active_executable = match which(executable) {
         Ok(path) => path,
         Err(which::Error::CannotFindBinaryPath) => {
@@ -118,7 +300,7 @@ fn verify_link(path: PathBuf, executable: &'static str) -> Result<ConfigurationC
 mod tests {
     use super::*;
     use std::env;
-    use std::fs::File;
+    use std::fs::{self, File};
     use std::os::unix::fs::symlink;
     use tempfile::tempdir;
 
@@ -156,6 +338,174 @@ mod tests {
         assert_eq!(expected, verify_links(fake_link_dir.path()).unwrap())
     }
 
+    #[test]
+    fn corepack_only_checked_when_expected() {
+        let fake_link_dir = tempdir().unwrap();
+        let node = fake_link_dir.path().join("node");
+        let corepack = fake_link_dir.path().join("corepack");
+        assert!(is_expected(&node, "node", false));
+        assert!(!is_expected(&corepack, "corepack", false));
+        assert!(is_expected(&corepack, "corepack", true));
+
+        // A link that's already there is always checked
+        symlink(fake_link_dir.path().join("nodeup"), &corepack).unwrap();
+        assert!(is_expected(&corepack, "corepack", false));
+    }
+
+    #[test]
+    fn fix_missing_symlink() {
+        let fake_link_dir = tempdir().unwrap();
+        let problem = match verify_links(fake_link_dir.path()).unwrap() {
+            ConfigurationCheck::Incorrect(problem) => problem,
+            ConfigurationCheck::Correct => panic!("The links should be missing"),
+        };
+        assert!(problem.is_fixable());
+
+        assert!(fix_links(fake_link_dir.path(), &[problem]).unwrap());
+
+        // The links aren't on the Path in tests so the only problem left is that they can't be found
+        match verify_links(fake_link_dir.path()).unwrap() {
+            ConfigurationCheck::Incorrect(problem) => assert!(!problem.is_fixable()),
+            ConfigurationCheck::Correct => (),
+        }
+        assert!(is_link(&fake_link_dir.path().join("node")).unwrap());
+    }
+
+    #[test]
+    fn symlink_to_old_nodeup_is_stale() {
+        let fake_link_dir = tempdir().unwrap();
+        let old_nodeup = fake_link_dir.path().join("old/bin/nodeup");
+        let node = fake_link_dir.path().join("node");
+        symlink(&old_nodeup, &node).unwrap();
+
+        let problem = match verify_links(fake_link_dir.path()).unwrap() {
+            ConfigurationCheck::Incorrect(problem) => problem,
+            ConfigurationCheck::Correct => panic!("The link should be stale"),
+        };
+        assert_eq!(
+            problem,
+            IncorrectConfiguration::StaleSymlink {
+                path: node.clone(),
+                points_to: old_nodeup,
+            }
+        );
+        assert!(problem.is_fixable());
+
+        assert!(fix_links(fake_link_dir.path(), &[problem]).unwrap());
+        assert_eq!(
+            fs::canonicalize(&node).unwrap(),
+            fs::canonicalize(env::current_exe().unwrap()).unwrap()
+        );
+    }
+
+    #[test]
+    fn fix_keeps_linked_shims() {
+        let fake_link_dir = tempdir().unwrap();
+        let yarn = fake_link_dir.path().join("yarn");
+        let pnpm = fake_link_dir.path().join("pnpm");
+        symlink(fake_link_dir.path().join("old/bin/nodeup"), &yarn).unwrap();
+        fs::write(&pnpm, "standalone pnpm").unwrap();
+        let problem = IncorrectConfiguration::MissingSymLink(fake_link_dir.path().join("node"));
+
+        assert!(fix_links(fake_link_dir.path(), &[problem]).unwrap());
+        assert_eq!(
+            fs::canonicalize(&yarn).unwrap(),
+            fs::canonicalize(env::current_exe().unwrap()).unwrap()
+        );
+        assert_eq!(fs::read_to_string(&pnpm).unwrap(), "standalone pnpm");
+        assert!(!fake_link_dir.path().join("pnpm.bak").exists());
+    }
+
+    #[test]
+    fn unfixable_problems_are_left_alone() {
+        let fake_link_dir = tempdir().unwrap();
+        let problems = vec![IncorrectConfiguration::PathNotFound];
+
+        assert!(!fix_links(fake_link_dir.path(), &problems).unwrap());
+        assert_eq!(fs::read_dir(fake_link_dir.path()).unwrap().count(), 0);
+    }
+
+    fn config_with(mappings: &[(&str, &str)]) -> Config {
+        let entries: Vec<String> = mappings
+            .iter()
+            .map(|(dir, version)| {
+                format!(
+                    "{:?} = {{ os = \"Linux\", version = {{ major = {}, minor = 0, patch = 0 }} }}",
+                    dir, version
+                )
+            })
+            .collect();
+        toml::from_str(&format!("[version_mappings]\n{}", entries.join("\n"))).unwrap()
+    }
+
+    #[test]
+    fn override_points_at_missing_version() {
+        let download_dir = tempdir().unwrap();
+        fs::create_dir(download_dir.path().join("node-v16.0.0-linux-x64")).unwrap();
+        let config = config_with(&[("/installed", "16"), ("/missing", "14")]);
+
+        let problems = verify_overrides(&config, download_dir.path());
+        assert_eq!(
+            problems,
+            vec![IncorrectConfiguration::OverrideNotInstalled {
+                dir: PathBuf::from("/missing"),
+                target: "node-v14.0.0-linux-x64".parse().unwrap(),
+            }]
+        );
+    }
+
+    #[test]
+    fn default_points_at_missing_version() {
+        let download_dir = tempdir().unwrap();
+        let config = config_with(&[("default", "18")]);
+
+        let problems = verify_overrides(&config, download_dir.path());
+        assert_eq!(
+            problems,
+            vec![IncorrectConfiguration::DefaultNotInstalled(
+                "node-v18.0.0-linux-x64".parse().unwrap()
+            )]
+        );
+    }
+
+    #[test]
+    fn install_without_node_is_broken() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let download_dir = tempdir().unwrap();
+        let complete = download_dir.path().join("node-v18.0.0-linux-x64/bin");
+        fs::create_dir_all(&complete).unwrap();
+        let node = complete.join("node");
+        File::create(&node).unwrap();
+        fs::set_permissions(&node, fs::Permissions::from_mode(0o755)).unwrap();
+        fs::create_dir(download_dir.path().join("node-v16.0.0-linux-x64")).unwrap();
+
+        assert_eq!(
+            verify_installs(download_dir.path()).unwrap(),
+            vec![IncorrectConfiguration::BrokenInstall(
+                "node-v16.0.0-linux-x64".parse().unwrap()
+            )]
+        );
+
+        fs::set_permissions(&node, fs::Permissions::from_mode(0o644)).unwrap();
+        assert_eq!(verify_installs(download_dir.path()).unwrap().len(), 2);
+    }
+
+    #[test]
+    fn missing_download_dir() {
+        let fake_dir = tempdir().unwrap();
+        let download_dir = fake_dir.path().join("downloads");
+
+        assert_eq!(
+            verify_download_dir(&download_dir),
+            ConfigurationCheck::Incorrect(IncorrectConfiguration::DownloadDirMissing(download_dir))
+        );
+        assert_eq!(
+            verify_download_dir(fake_dir.path()),
+            ConfigurationCheck::Correct
+        );
+    }
+
     #[test]
     fn not_a_symlink() {
         let fake_link_dir = tempdir().unwrap();
@@ -0,0 +1,70 @@
+// Not every test binary uses every helper
+#![allow(dead_code)]
+
+use std::{
+    env,
+    ffi::{OsStr, OsString},
+    path::{Path, PathBuf},
+    sync::{Mutex, MutexGuard},
+};
+
+// Tests in the same binary share the process environment, so they take turns changing it
+static ENV: Mutex<()> = Mutex::new(());
+
+/// Exclusive use of the process environment for a test. The variables and working directory changed
+/// through it are put back when it's dropped, including when the test fails
+pub struct TestEnv {
+    vars: Vec<(String, Option<OsString>)>,
+    current_dir: Option<PathBuf>,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestEnv {
+    pub fn lock() -> Self {
+        // A failed test has already restored everything it changed, so its panic can be ignored
+        let lock = ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        TestEnv {
+            vars: Vec::new(),
+            current_dir: None,
+            _lock: lock,
+        }
+    }
+
+    pub fn set<V: AsRef<OsStr>>(&mut self, name: &str, value: V) {
+        self.save(name);
+        env::set_var(name, value);
+    }
+
+    pub fn remove(&mut self, name: &str) {
+        self.save(name);
+        env::remove_var(name);
+    }
+
+    pub fn set_current_dir(&mut self, dir: &Path) {
+        if self.current_dir.is_none() {
+            self.current_dir = Some(env::current_dir().unwrap());
+        }
+        env::set_current_dir(dir).unwrap();
+    }
+
+    // Only the value from before the test is kept
+    fn save(&mut self, name: &str) {
+        if !self.vars.iter().any(|(saved, _)| saved == name) {
+            self.vars.push((name.to_string(), env::var_os(name)));
+        }
+    }
+}
+
+impl Drop for TestEnv {
+    fn drop(&mut self) {
+        for (name, value) in self.vars.drain(..) {
+            match value {
+                Some(value) => env::set_var(name, value),
+                None => env::remove_var(name),
+            }
+        }
+        if let Some(dir) = self.current_dir.take() {
+            let _ = env::set_current_dir(dir);
+        }
+    }
+}
@@ -1,20 +1,25 @@
-use nodeup::local::*;
-use std::{env, path::PathBuf};
+mod common;
+
+use common::TestEnv;
+use nodeup::{local::*, Config, Target, Version};
+use std::{env, fs, path::PathBuf, process::Command, thread};
+use tempfile::tempdir;
 
 #[test]
 fn find_config_file() {
-    env::set_var("NODEUP_CONFIG", "/tmp/config");
+    let mut test_env = TestEnv::lock();
+    test_env.set("NODEUP_CONFIG", "/tmp/config");
     let actual = config_file().unwrap();
     let expected = PathBuf::from("/tmp/config/settings.toml");
-    env::remove_var("NODEUP_CONFIG");
+    test_env.remove("NODEUP_CONFIG");
     assert_eq!(actual, expected);
 
     #[cfg(target_os = "linux")]
     {
-        env::set_var("XDG_CONFIG_HOME", "/tmp/xdg-config");
+        test_env.set("XDG_CONFIG_HOME", "/tmp/xdg-config");
         let actual = config_file().unwrap();
         let expected = PathBuf::from("/tmp/xdg-config/nodeup/settings.toml");
-        env::remove_var("XDG_CONFIG_HOME");
+        test_env.remove("XDG_CONFIG_HOME");
         assert_eq!(actual, expected);
     }
 
@@ -37,3 +42,79 @@ fn find_config_file() {
 
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn config_path_honors_nodeup_config() {
+    let config_dir = tempdir().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nodeup"))
+        .args(["config", "path"])
+        .env("NODEUP_CONFIG", config_dir.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let expected = format!("{}\n", config_dir.path().join("settings.toml").display());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), expected);
+}
+
+#[test]
+fn update_keeps_backup_that_can_be_restored() {
+    let config_dir = tempdir().unwrap();
+    let config_file = config_dir.path().join("settings.toml");
+    let backup_file = config_dir.path().join("settings.toml.bak");
+    let nodeup = |args: &[&str]| {
+        let output = Command::new(env!("CARGO_BIN_EXE_nodeup"))
+            .args(args)
+            .env("NODEUP_CONFIG", config_dir.path())
+            .output()
+            .unwrap();
+        assert!(output.status.success(), "{:?}", output);
+    };
+
+    nodeup(&["alias", "add", "work", "v16.1.0"]);
+    let first = fs::read_to_string(&config_file).unwrap();
+
+    nodeup(&["alias", "add", "home", "v18.17.0"]);
+    let second = fs::read_to_string(&config_file).unwrap();
+    let backup = fs::read_to_string(&backup_file).unwrap();
+
+    nodeup(&["config", "restore"]);
+    let restored = fs::read_to_string(&config_file).unwrap();
+    let swapped = fs::read_to_string(&backup_file).unwrap();
+
+    assert_eq!(backup, first);
+    assert_eq!(restored, first);
+    assert_eq!(swapped, second);
+}
+
+const OVERRIDES_PER_THREAD: usize = 20;
+
+#[test]
+fn concurrent_overrides_survive() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+
+    let writers: Vec<_> = vec!["first", "second"]
+        .into_iter()
+        .map(|name| {
+            thread::spawn(move || {
+                for i in 0..OVERRIDES_PER_THREAD {
+                    let version: Version = format!("v14.{}.0", i).parse().unwrap();
+                    let dir = PathBuf::from(format!("/projects/{}/{}", name, i));
+                    let mut config = Config::fetch().unwrap();
+                    config
+                        .set_override(Target::from_version(version), dir)
+                        .unwrap();
+                }
+            })
+        })
+        .collect();
+    writers
+        .into_iter()
+        .for_each(|writer| writer.join().unwrap());
+
+    let overrides = Config::fetch().unwrap().active_versions().count();
+    assert_eq!(overrides, 2 * OVERRIDES_PER_THREAD);
+}
@@ -1,20 +1,25 @@
-use nodeup::local::*;
-use std::{env, path::PathBuf};
+mod common;
+
+use common::TestEnv;
+use nodeup::{local::*, Target, Version};
+use std::{env, fs, path::PathBuf};
+use tempfile::tempdir;
 
 #[test]
 fn find_download_dir() {
-    env::set_var("NODEUP_DOWNLOADS", "/tmp/nodeup");
+    let mut test_env = TestEnv::lock();
+    test_env.set("NODEUP_DOWNLOADS", "/tmp/nodeup");
     let actual = download_dir().unwrap();
     let expected = PathBuf::from("/tmp/nodeup");
-    env::remove_var("NODEUP_DOWNLOADS");
+    test_env.remove("NODEUP_DOWNLOADS");
     assert_eq!(actual, expected);
 
     #[cfg(target_os = "linux")]
     {
-        env::set_var("XDG_DATA_HOME", "/tmp/other-nodeup");
+        test_env.set("XDG_DATA_HOME", "/tmp/other-nodeup");
         let actual = download_dir().unwrap();
         let expected = PathBuf::from("/tmp/other-nodeup/nodeup");
-        env::remove_var("XDG_DATA_HOME");
+        test_env.remove("XDG_DATA_HOME");
         assert_eq!(actual, expected);
     }
 
@@ -36,3 +41,50 @@ fn find_download_dir() {
 
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn download_dir_is_expanded_and_absolute() {
+    let mut test_env = TestEnv::lock();
+    let fake_home = tempdir().unwrap();
+    test_env.set("HOME", fake_home.path());
+    let cwd = tempdir().unwrap();
+    test_env.set_current_dir(cwd.path());
+
+    test_env.set("NODEUP_DOWNLOADS", "~/node");
+    assert_eq!(download_dir().unwrap(), fake_home.path().join("node"));
+    test_env.set("NODEUP_DOWNLOADS", "$HOME/node");
+    assert_eq!(download_dir().unwrap(), fake_home.path().join("node"));
+    test_env.set("NODEUP_DOWNLOADS", "downloads/node");
+    let relative = download_dir().unwrap();
+    assert!(relative.is_absolute());
+    let current_dir = env::current_dir().unwrap();
+    assert_eq!(relative, current_dir.join("downloads").join("node"));
+}
+
+#[test]
+fn prune_unused_versions() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let download_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+    test_env.set("NODEUP_DOWNLOADS", download_dir.path());
+
+    let targets: Vec<Target> = vec!["v12.18.3", "v14.8.0", "v10.22.0"]
+        .into_iter()
+        .map(|version| Target::from_version(version.parse::<Version>().unwrap()))
+        .collect();
+    for target in &targets {
+        let bin_dir = download_dir.path().join(target.to_string()).join("bin");
+        fs::create_dir_all(&bin_dir).unwrap();
+        fs::write(bin_dir.join("node"), "node").unwrap();
+    }
+
+    nodeup::change_default_target(targets[0].clone(), false).unwrap();
+
+    let mut pruned = nodeup::prune_unused().unwrap();
+    pruned.sort_by(|a, b| a.version().cmp(b.version()));
+    assert_eq!(pruned, vec![targets[2].clone(), targets[1].clone()]);
+
+    let remaining = nodeup::installed_versions(download_dir.path()).unwrap();
+    assert_eq!(remaining, vec![targets[0].clone()]);
+}
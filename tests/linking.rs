@@ -1,18 +1,73 @@
+mod common;
+
+use common::TestEnv;
 use nodeup::local::*;
-use std::{env, path::PathBuf};
+use std::{fs, path::PathBuf, process::Command};
+use tempfile::tempdir;
 
 #[test]
 fn linking() {
-    env::set_var("NODEUP_LINKS", "/tmp/links");
+    let mut test_env = TestEnv::lock();
+    test_env.set("NODEUP_LINKS", "/tmp/links");
     let actual = links().unwrap();
     let expected = PathBuf::from("/tmp/links");
-    env::remove_var("NODEUP_LINKS");
+    test_env.remove("NODEUP_LINKS");
     assert_eq!(actual, expected);
 
-    let home = dirs::home_dir().unwrap();
-    env::set_var("HOME", "/tmp/home");
+    test_env.set("HOME", "/tmp/home");
     let actual = links().unwrap();
     let expected = PathBuf::from("/tmp/home/.local/bin/");
-    env::set_var("HOME", home);
     assert_eq!(actual, expected);
 }
+
+#[test]
+fn link_into_explicit_dir() {
+    let env_links = tempdir().unwrap();
+    let explicit = tempdir().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nodeup"))
+        .args(["control", "link", "--dir"])
+        .arg(explicit.path())
+        .env("NODEUP_LINKS", env_links.path())
+        .output()
+        .unwrap();
+
+    assert!(output.status.success(), "{:?}", output);
+    let nodeup = fs::canonicalize(env!("CARGO_BIN_EXE_nodeup")).unwrap();
+    for executable in nodeup::DEFAULT_EXECUTABLES.iter() {
+        let link = explicit.path().join(executable);
+        assert_eq!(fs::canonicalize(link).unwrap(), nodeup);
+    }
+    assert_eq!(env_links.path().read_dir().unwrap().count(), 0);
+}
+
+#[cfg(target_os = "linux")]
+#[test]
+fn shim_exits_like_the_executable() {
+    use std::os::unix::fs::{symlink, PermissionsExt};
+
+    let config_dir = tempdir().unwrap();
+    let download_dir = tempdir().unwrap();
+    let bin_dir = download_dir.path().join("node-v16.20.0-linux-x64/bin");
+    fs::create_dir_all(&bin_dir).unwrap();
+    fs::write(bin_dir.join("node"), "#!/bin/sh\nexit \"$1\"\n").unwrap();
+    fs::set_permissions(bin_dir.join("node"), fs::Permissions::from_mode(0o755)).unwrap();
+    let links = tempdir().unwrap();
+    symlink(env!("CARGO_BIN_EXE_nodeup"), links.path().join("node")).unwrap();
+    let project = tempdir().unwrap();
+    fs::write(project.path().join(".nvmrc"), "v16.20.0\n").unwrap();
+
+    let node = |code: &str| {
+        Command::new(links.path().join("node"))
+            .arg(code)
+            .current_dir(project.path())
+            .env("NODEUP_CONFIG", config_dir.path())
+            .env("NODEUP_DOWNLOADS", download_dir.path())
+            .env("NODEUP_ARCH", "x64")
+            .env_remove("NODEUP_NODE_VERSION")
+            .status()
+            .unwrap()
+    };
+    assert_eq!(node("3").code(), Some(3));
+    assert!(node("0").success());
+}
@@ -0,0 +1,134 @@
+mod common;
+
+use common::TestEnv;
+use nodeup::{Config, Target};
+use std::{env, fs, path::PathBuf, process::Command};
+use tempfile::tempdir;
+
+#[test]
+fn clear_overrides_keeps_default() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+
+    let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+    let mut config = Config::fetch().unwrap();
+    for dir in &["default", "/projects/a", "/projects/b", "/projects/c"] {
+        config
+            .set_override(target.clone(), PathBuf::from(dir))
+            .unwrap();
+    }
+
+    let cleared = nodeup::clear_overrides(true).unwrap();
+    let remaining: Vec<PathBuf> = Config::fetch()
+        .unwrap()
+        .active_versions()
+        .map(|(dir, _)| dir)
+        .collect();
+    let cleared_default = nodeup::clear_overrides(false).unwrap();
+    let empty = Config::fetch().unwrap().active_versions().count();
+
+    assert_eq!(cleared, 3);
+    assert_eq!(remaining, vec![PathBuf::from("default")]);
+    assert_eq!(cleared_default, 1);
+    assert_eq!(empty, 0);
+}
+
+#[test]
+fn prune_overrides_for_deleted_directories() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let live = tempdir().unwrap();
+    let deleted = tempdir().unwrap();
+    let deleted_path = deleted.path().canonicalize().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+
+    let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+    let mut config = Config::fetch().unwrap();
+    config
+        .set_override(target.clone(), PathBuf::from("default"))
+        .unwrap();
+    config
+        .set_override(target.clone(), live.path().to_path_buf())
+        .unwrap();
+    config
+        .set_override(target.clone(), deleted.path().to_path_buf())
+        .unwrap();
+    deleted.close().unwrap();
+
+    let dry_run = nodeup::prune_overrides(true).unwrap();
+    let pruned = nodeup::prune_overrides(false).unwrap();
+    let remaining = Config::fetch().unwrap().stale_overrides();
+    let live_target = Config::fetch()
+        .unwrap()
+        .get_active_target(live.path())
+        .unwrap();
+    let overrides = Config::fetch().unwrap().active_versions().count();
+
+    assert_eq!(dry_run, vec![deleted_path.clone()]);
+    assert_eq!(pruned, vec![deleted_path]);
+    assert!(remaining.is_empty());
+    assert_eq!(live_target, Some(target));
+    assert_eq!(overrides, 2);
+}
+
+#[cfg(unix)]
+#[test]
+fn symlinked_directory_uses_same_override() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let projects = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+
+    let project = projects.path().join("project");
+    let linked = projects.path().join("linked");
+    fs::create_dir_all(project.join("src")).unwrap();
+    std::os::unix::fs::symlink(&project, &linked).unwrap();
+    let target: Target = "node-v16.1.0-linux-x64".parse().unwrap();
+
+    let mut config = Config::fetch().unwrap();
+    config.set_override(target.clone(), linked.clone()).unwrap();
+
+    let through_project = config.get_active_target(&project.join("src")).unwrap();
+    let through_link = config.get_active_target(&linked.join("src")).unwrap();
+    let missing = config
+        .get_active_target(&linked.join("deleted").join("nested"))
+        .unwrap();
+
+    config
+        .remove_override(project.join("src").join(".."))
+        .unwrap();
+    let removed = config.get_active_target(&linked).unwrap();
+
+    assert_eq!(through_project, Some(target.clone()));
+    assert_eq!(through_link, Some(target.clone()));
+    assert_eq!(missing, Some(target));
+    assert_eq!(removed, None);
+}
+
+#[test]
+fn which_for_another_directory() {
+    let config_dir = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    fs::write(project.path().join(".nvmrc"), "v16.20.0\n").unwrap();
+    let elsewhere = tempdir().unwrap();
+    let which = |args: &[&str]| {
+        Command::new(env!("CARGO_BIN_EXE_nodeup"))
+            .args(["override", "which"])
+            .args(args)
+            .current_dir(elsewhere.path())
+            .env("NODEUP_CONFIG", config_dir.path())
+            .env_remove("NODEUP_NODE_VERSION")
+            .output()
+            .unwrap()
+    };
+
+    let resolution = which(&["--cwd", project.path().to_str().unwrap()]);
+    let from_cwd = which(&[]);
+
+    assert!(resolution.status.success(), "{:?}", resolution);
+    let stdout = String::from_utf8(resolution.stdout).unwrap();
+    assert!(stdout.starts_with("node-v16.20.0-"), "{}", stdout);
+    assert!(stdout.contains(".nvmrc at"), "{}", stdout);
+    assert!(!from_cwd.status.success(), "{:?}", from_cwd);
+}
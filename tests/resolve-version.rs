@@ -0,0 +1,163 @@
+mod common;
+
+use common::TestEnv;
+use nodeup::{NodeupError, Version};
+use std::{env, fs, process::Command};
+use tempfile::tempdir;
+
+#[test]
+fn resolve_each_kind_of_input() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let cache_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+    test_env.set("NODEUP_CACHE", cache_dir.path());
+    // Exact versions have to resolve without the index
+    let exact = nodeup::resolve_version("v13.0.0");
+
+    fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/index.json"),
+        cache_dir.path().join("index.json"),
+    )
+    .unwrap();
+    nodeup::add_alias("work", "v14.21.3".parse().unwrap()).unwrap();
+
+    let inputs = [
+        "lts",
+        "lts/*",
+        "lts/gallium",
+        "latest",
+        "node",
+        "18",
+        "^16",
+        "12.18",
+        "work",
+    ];
+    let resolved: Vec<String> = inputs
+        .iter()
+        .map(|input| {
+            let target = nodeup::resolve_version(input).unwrap();
+            target.version().to_string()
+        })
+        .collect();
+    let invalid = nodeup::resolve_version("banana");
+    let unknown_codename = nodeup::resolve_version("lts/banana");
+
+    assert_eq!(exact.unwrap().version().to_string(), "v13.0.0");
+    assert_eq!(
+        resolved,
+        vec![
+            "v18.17.0", "v18.17.0", "v16.20.1", "v20.5.0", "v20.5.0", "v18.17.0", "v16.20.1",
+            "v12.18.3", "v14.21.3",
+        ]
+    );
+    assert!(matches!(invalid, Err(NodeupError::InvalidVersion { .. })));
+    assert!(matches!(
+        unknown_codename,
+        Err(NodeupError::Registry { .. })
+    ));
+}
+
+#[test]
+fn lts_follows_preferred_line() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let cache_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+    test_env.set("NODEUP_CACHE", cache_dir.path());
+    fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/index.json"),
+        cache_dir.path().join("index.json"),
+    )
+    .unwrap();
+    let registry = nodeup::registry::Registry::new().unwrap();
+    let resolve = |input| {
+        nodeup::resolve_version(input)
+            .unwrap()
+            .version()
+            .to_string()
+    };
+
+    let newest = resolve("lts");
+    nodeup::set_preferred_lts(Some(String::from("gallium"))).unwrap();
+    let preferred = resolve("lts");
+    let latest = nodeup::latest_lts_with(&registry).unwrap().to_string();
+    let explicit = resolve("lts/hydrogen");
+    let star = resolve("lts/*");
+    nodeup::set_preferred_lts(None).unwrap();
+    let unset = resolve("lts");
+
+    assert_eq!(newest, "v18.17.0");
+    assert_eq!(preferred, "v16.20.1");
+    assert_eq!(latest, "v16.20.1");
+    assert_eq!(explicit, "v18.17.0");
+    assert_eq!(star, "v18.17.0");
+    assert_eq!(unset, "v18.17.0");
+}
+
+#[test]
+fn define_resolve_and_list_aliases() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+
+    let work: Version = "v18.16.0".parse().unwrap();
+    let legacy: Version = "v12.22.12".parse().unwrap();
+    nodeup::add_alias("work", work.clone()).unwrap();
+    nodeup::add_alias("legacy", legacy.clone()).unwrap();
+    let invalid = nodeup::add_alias("16", legacy.clone());
+
+    let resolved = nodeup::resolve_alias("work").unwrap();
+    let unknown = nodeup::resolve_alias("play").unwrap();
+    let listed = nodeup::list_aliases().unwrap();
+
+    let removed = nodeup::remove_alias("legacy").unwrap();
+    let remaining = nodeup::list_aliases().unwrap();
+    let missing = nodeup::remove_alias("legacy");
+
+    assert!(matches!(invalid, Err(NodeupError::InvalidAlias { .. })));
+    assert_eq!(resolved, Some(work.clone()));
+    assert_eq!(unknown, None);
+    assert_eq!(
+        listed,
+        vec![
+            (String::from("legacy"), legacy.clone()),
+            (String::from("work"), work.clone())
+        ]
+    );
+    assert_eq!(removed, legacy);
+    assert_eq!(remaining, vec![(String::from("work"), work)]);
+    assert!(matches!(missing, Err(NodeupError::AliasNotFound { .. })));
+}
+
+#[test]
+fn dry_run_for_forced_arch() {
+    let config_dir = tempdir().unwrap();
+    let download_dir = tempdir().unwrap();
+
+    let output = Command::new(env!("CARGO_BIN_EXE_nodeup"))
+        .args(["versions", "add", "--dry-run", "18.16.0"])
+        .env("NODEUP_CONFIG", config_dir.path())
+        .env("NODEUP_DOWNLOADS", download_dir.path())
+        .env("NODEUP_ARCH", "arm64")
+        .output()
+        .unwrap();
+
+    assert!(output.status.success());
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let line = |label: &str| {
+        stdout
+            .lines()
+            .find(|line| line.starts_with(label))
+            .unwrap_or_default()
+            .to_string()
+    };
+    assert!(line("Target:").contains("node-v18.16.0-"), "{}", stdout);
+    assert!(line("Target:").contains("-arm64"), "{}", stdout);
+    assert!(
+        line("Source:").contains("/v18.16.0/node-v18.16.0-"),
+        "{}",
+        stdout
+    );
+    assert!(line("Source:").contains("-arm64"), "{}", stdout);
+}
@@ -0,0 +1,175 @@
+mod common;
+
+use common::TestEnv;
+use log::{Level, LevelFilter, Log, Metadata, Record};
+use nodeup::{Config, ResolutionSource, Target, Version};
+use std::{env, fs, sync::Mutex};
+use tempfile::tempdir;
+
+#[test]
+fn nvmrc_keywords_resolve_to_installed_versions() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let cache_dir = tempdir().unwrap();
+    let download_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+    test_env.set("NODEUP_CACHE", cache_dir.path());
+    test_env.set("NODEUP_DOWNLOADS", download_dir.path());
+    fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/index.json"),
+        cache_dir.path().join("index.json"),
+    )
+    .unwrap();
+    for version in &["v14.21.3", "v16.20.0", "v18.16.1", "v19.9.0", "v20.5.0"] {
+        let target = Target::from_version(version.parse::<Version>().unwrap());
+        fs::create_dir(download_dir.path().join(target.to_string())).unwrap();
+    }
+
+    let project = tempdir().unwrap();
+    let nvmrc = project.path().join(".nvmrc");
+    let resolve = |content: &str| {
+        fs::write(&nvmrc, content).unwrap();
+        nodeup::which(project.path())
+            .map(|target| target.version().to_string())
+            .map_err(|e| e.to_string())
+    };
+    let resolved: Vec<_> = ["lts/*\n", "lts/gallium\n", "node\n", "16\n", "v19\n"]
+        .iter()
+        .map(|content| resolve(content))
+        .collect();
+    let missing_line = resolve("lts/dubnium");
+
+    // Without a cached index every even numbered line counts as lts
+    fs::remove_file(cache_dir.path().join("index.json")).unwrap();
+    let uncached_lts = resolve("lts/*");
+
+    let expected = ["v18.16.1", "v16.20.0", "v20.5.0", "v16.20.0", "v19.9.0"];
+    let expected: Vec<_> = expected.iter().map(|v| Ok(v.to_string())).collect();
+    assert_eq!(resolved, expected);
+    assert!(missing_line
+        .unwrap_err()
+        .contains("no installed version matches"));
+    assert_eq!(uncached_lts, Ok(String::from("v20.5.0")));
+}
+
+#[test]
+fn resolve_from_package_json_engines() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let download_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+    test_env.set("NODEUP_DOWNLOADS", download_dir.path());
+
+    for version in &["v14.21.3", "v16.20.1", "v18.16.1"] {
+        let target = Target::from_version(version.parse::<Version>().unwrap());
+        fs::create_dir(download_dir.path().join(target.to_string())).unwrap();
+    }
+
+    let project = tempdir().unwrap();
+    fs::copy(
+        concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/package.json"),
+        project.path().join("package.json"),
+    )
+    .unwrap();
+    let nested = project.path().join("src");
+    fs::create_dir(&nested).unwrap();
+
+    let from_engines = nodeup::which(&nested).unwrap();
+
+    fs::write(project.path().join(".nvmrc"), "v14.21.3").unwrap();
+    let from_nvmrc = nodeup::which(&nested).unwrap();
+
+    assert_eq!(
+        from_engines.version(),
+        &"v18.16.1".parse::<Version>().unwrap()
+    );
+    assert_eq!(
+        from_nvmrc.version(),
+        &"v14.21.3".parse::<Version>().unwrap()
+    );
+}
+
+#[test]
+fn env_version_wins_over_nvmrc() {
+    let mut test_env = TestEnv::lock();
+    let config_dir = tempdir().unwrap();
+    let project = tempdir().unwrap();
+    fs::write(project.path().join(".nvmrc"), "v16.20.1").unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+
+    let mut config = Config::fetch().unwrap();
+    config
+        .set_alias(String::from("ci"), "v20.5.0".parse().unwrap())
+        .unwrap();
+
+    let from_nvmrc = config.resolve_active(project.path()).unwrap().unwrap();
+    test_env.set("NODEUP_NODE_VERSION", "v18.17.0");
+    let pinned = config.resolve_active(project.path()).unwrap().unwrap();
+    test_env.set("NODEUP_NODE_VERSION", "ci");
+    let aliased = config.get_active_target(project.path()).unwrap().unwrap();
+    test_env.set("NODEUP_NODE_VERSION", "eighteen");
+    let invalid = config.resolve_active(project.path());
+
+    assert_eq!(from_nvmrc.target.version(), &"v16.20.1".parse().unwrap());
+    assert_eq!(pinned.target.version(), &"v18.17.0".parse().unwrap());
+    assert_eq!(pinned.source, ResolutionSource::Environment);
+    assert_eq!(aliased.version(), &"v20.5.0".parse().unwrap());
+    assert!(invalid.is_err());
+}
+
+// Keeps the debug logs from resolving versions so the trace can be checked
+struct CaptureLogger {
+    lines: Mutex<Vec<String>>,
+}
+
+impl Log for CaptureLogger {
+    fn enabled(&self, metadata: &Metadata) -> bool {
+        metadata.level() <= Level::Debug && metadata.target() == "nodeup::config"
+    }
+
+    fn log(&self, record: &Record) {
+        if self.enabled(record.metadata()) {
+            self.lines.lock().unwrap().push(record.args().to_string());
+        }
+    }
+
+    fn flush(&self) {}
+}
+
+static LOGGER: CaptureLogger = CaptureLogger {
+    lines: Mutex::new(Vec::new()),
+};
+
+#[test]
+fn nvmrc_resolution_is_logged() {
+    let mut test_env = TestEnv::lock();
+    log::set_logger(&LOGGER).unwrap();
+    log::set_max_level(LevelFilter::Debug);
+
+    let config_dir = tempdir().unwrap();
+    test_env.set("NODEUP_CONFIG", config_dir.path());
+    let project = tempdir().unwrap();
+    let project_dir = project.path().canonicalize().unwrap();
+    let src = project_dir.join("src");
+    fs::create_dir(&src).unwrap();
+    fs::write(project_dir.join(".nvmrc"), "v16.20.0\n").unwrap();
+
+    let resolution = nodeup::resolve_active(&src);
+    let target = resolution.unwrap().target;
+
+    let lines = LOGGER.lines.lock().unwrap();
+    let trace: Vec<&String> = lines
+        .iter()
+        .skip_while(|line| !line.starts_with("Looking for a version"))
+        .collect();
+    let nvmrc = project_dir.join(".nvmrc");
+    assert_eq!(
+        trace,
+        vec![
+            &format!("Looking for a version in {}", src.display()),
+            &format!("Looking for a version in {}", project_dir.display()),
+            &format!("Found {}", nvmrc.display()),
+            &format!("Using {} from .nvmrc at {}", target, project_dir.display()),
+        ]
+    );
+}